@@ -0,0 +1,40 @@
+use std::thread;
+
+use crate::bus::{self, Event};
+use crate::config::CameraConfig;
+use crate::hooks::shell;
+use crate::notify;
+
+// 告警：发布到事件总线（写到 stderr，转发给 webhook 等），并在配置了 alert_command 时执行外部命令，
+// 告警内容通过 ALERT_KIND / ALERT_CAMERA / ALERT_MESSAGE 环境变量传入
+pub fn raise(camera: &CameraConfig, kind: &str, message: &str) {
+    raise_with(camera, kind, message, &[]);
+}
+
+// extra 为附加的环境变量，如事件片段的 ALERT_CLIP
+pub fn raise_with(camera: &CameraConfig, kind: &str, message: &str, extra: &[(&str, String)]) {
+    bus::publish(Event::Alert {
+        camera: camera.name.clone(),
+        kind: kind.to_string(),
+        message: message.to_string(),
+    });
+    notify::desktop(camera, kind, message);
+
+    let command = match &camera.alert_command {
+        Some(command) => command,
+        None => return,
+    };
+    let mut child = shell(command);
+    child
+        .env("ALERT_KIND", kind)
+        .env("ALERT_CAMERA", &camera.name)
+        .env("ALERT_MESSAGE", message)
+        .envs(extra.iter().map(|(key, value)| (key, value)));
+    match child.spawn() {
+        // 在后台等待子进程退出，避免留下僵尸进程
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => error!("[Alert] Failed to run alert command: {}", e),
+    }
+}
@@ -0,0 +1,177 @@
+// 外接音频：为只有视频的摄像头另开一路音频源（另一个 RTSP/RTP 地址或本机麦克风），
+// 与视频封装进同一个分段。两路的时间戳都换算成距同一时刻的微秒数来对齐
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::{Packet, Rational};
+
+use crate::avlog;
+use crate::control::StreamHandle;
+use crate::input::{self, Watchdog};
+use crate::mux::StreamInfo;
+
+// 对齐后的时间基
+pub const TIME_BASE: Rational = Rational(1, 1_000_000);
+
+// 录制线程处理不过来时丢弃音频，不阻塞音频读取
+const QUEUE_PACKETS: usize = 256;
+
+// 把一路流的时间戳映射到以 origin 为零点的微秒数：第一个包按到达时刻定位，
+// 之后按 pts 的增量推算，不受网络抖动影响
+pub struct Aligner {
+    origin: Instant,
+    time_base: Rational,
+    // (第一个包的到达时刻, 第一个包的 dts)，均为微秒
+    anchor: Option<(i64, i64)>,
+}
+
+impl Aligner {
+    pub fn new(origin: Instant, time_base: Rational) -> Aligner {
+        Aligner {
+            origin,
+            time_base,
+            anchor: None,
+        }
+    }
+
+    pub fn align(&mut self, packet: &mut Packet) {
+        packet.rescale_ts(self.time_base, TIME_BASE);
+        let dts = match packet.dts().or(packet.pts()) {
+            Some(dts) => dts,
+            None => return,
+        };
+        let (arrival, first) = *self
+            .anchor
+            .get_or_insert_with(|| (self.origin.elapsed().as_micros() as i64, dts));
+        let offset = arrival - first;
+        packet.set_dts(packet.dts().map(|ts| ts + offset));
+        packet.set_pts(packet.pts().map(|ts| ts + offset));
+    }
+}
+
+pub struct AudioSource {
+    pub receiver: Receiver<Packet>,
+    // 当前连接的音频流，断开时为 None；新分段据此决定是否包含音轨
+    info: Arc<Mutex<Option<StreamInfo>>>,
+    thread: JoinHandle<()>,
+}
+
+impl AudioSource {
+    pub fn info(&self) -> Option<StreamInfo> {
+        self.info.lock().unwrap().clone()
+    }
+
+    // 丢弃视频断开期间积压的包
+    pub fn discard_pending(&self) {
+        while self.receiver.try_recv().is_ok() {}
+    }
+
+    pub fn join(self) {
+        // 先关闭接收端，音频线程随之退出
+        drop(self.receiver);
+        self.thread.join().unwrap();
+    }
+}
+
+// 在后台线程中读取 audio_url，断线后 5 秒重连，直到停止；摄像头暂停期间不连接
+pub fn spawn(
+    id: usize,
+    handle: Arc<StreamHandle>,
+    origin: Instant,
+    running: Arc<AtomicBool>,
+) -> Option<AudioSource> {
+    handle.camera.audio_url.as_ref()?;
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_PACKETS);
+    let info = Arc::new(Mutex::new(None));
+    let shared = info.clone();
+    let thread = thread::spawn(move || {
+        avlog::attach(id);
+        let url = handle.camera.audio_url.clone().unwrap_or_default();
+        info!("[Stream {}] Starting audio: {}", id, url);
+        while running.load(Ordering::SeqCst) {
+            if handle.is_paused() {
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+            let result = read(&url, &handle, origin, &running, &sender, &shared);
+            shared.lock().unwrap().take();
+            match result {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => error!("[Stream {}] Error processing audio {}: {}", id, url, e),
+            }
+            if running.load(Ordering::SeqCst) && !handle.is_paused() {
+                info!("[Stream {}] Retrying audio {} in 5 seconds...", id, url);
+                thread::sleep(Duration::from_secs(5));
+            }
+        }
+        info!("[Stream {}] Stopped audio: {}", id, url);
+    });
+    Some(AudioSource {
+        receiver,
+        info,
+        thread,
+    })
+}
+
+// 返回 false 表示录制线程已不再接收
+fn read(
+    url: &str,
+    handle: &Arc<StreamHandle>,
+    origin: Instant,
+    running: &Arc<AtomicBool>,
+    sender: &SyncSender<Packet>,
+    shared: &Mutex<Option<StreamInfo>>,
+) -> Result<bool, String> {
+    let camera = &handle.camera;
+    let watchdog = Watchdog::new(camera.read_timeout);
+    let stop = {
+        let handle = handle.clone();
+        let running = running.clone();
+        move || !running.load(Ordering::SeqCst) || handle.is_paused()
+    };
+    let options = input::Options {
+        format: camera.audio_format.as_deref(),
+        ..Default::default()
+    };
+    let mut source = input::open(url, &options, &watchdog, stop)?;
+    let audio_stream_index = source.best(ffmpeg::media::Type::Audio)?;
+    let mut aligner = {
+        let stream = source
+            .context
+            .stream(audio_stream_index)
+            .ok_or("No audio stream found")?;
+        *shared.lock().unwrap() = Some(StreamInfo {
+            parameters: stream.parameters(),
+            time_base: TIME_BASE,
+        });
+        Aligner::new(origin, stream.time_base())
+    };
+
+    for result in source.context.packets() {
+        if !running.load(Ordering::SeqCst) || handle.is_paused() {
+            break;
+        }
+        let (stream, mut packet) = match result {
+            Ok(item) => item,
+            Err(_) if watchdog.expired() => return Err(watchdog.timeout_error()),
+            Err(_) => continue,
+        };
+        watchdog.feed();
+        if stream.index() != audio_stream_index {
+            continue;
+        }
+        aligner.align(&mut packet);
+        match sender.try_send(packet) {
+            Ok(_) | Err(TrySendError::Full(_)) => {}
+            // 录制线程已结束
+            Err(TrySendError::Disconnected(_)) => return Ok(false),
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,87 @@
+// 接管 ffmpeg 内部日志（RTP 丢包、解码器报错等）：默认直接写到 stderr，分不清是哪一路流。
+// 这些日志在调用 ffmpeg 的线程上同步产生，因此按线程记录所属的流编号
+use std::cell::{Cell, RefCell};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::ffi;
+use ffmpeg::log::Level;
+
+use crate::log;
+
+thread_local! {
+    static STREAM: Cell<Option<usize>> = const { Cell::new(None) };
+    // ffmpeg 可能分几次输出一行，攒到换行再写
+    static PENDING: RefCell<String> = const { RefCell::new(String::new()) };
+    static PRINT_PREFIX: Cell<c_int> = const { Cell::new(1) };
+}
+
+pub fn capture() {
+    ffmpeg::log::set_level(Level::Warning);
+    unsafe { ffi::av_log_set_callback(Some(callback)) };
+}
+
+// 之后当前线程产生的 ffmpeg 日志归属于该流
+pub fn attach(id: usize) {
+    STREAM.with(|stream| stream.set(Some(id)));
+}
+
+unsafe extern "C" fn callback(
+    context: *mut c_void,
+    level: c_int,
+    format: *const c_char,
+    args: ffi::va_list,
+) {
+    let level = match Level::try_from(level) {
+        Ok(level) if level <= Level::Warning => level,
+        _ => return,
+    };
+    let mut buffer = [0 as c_char; 1024];
+    let mut print_prefix = PRINT_PREFIX.with(Cell::get);
+    ffi::av_log_format_line2(
+        context,
+        level.into(),
+        format,
+        args,
+        buffer.as_mut_ptr(),
+        buffer.len() as c_int,
+        &mut print_prefix,
+    );
+    PRINT_PREFIX.with(|prefix| prefix.set(print_prefix));
+    let text = CStr::from_ptr(buffer.as_ptr()).to_string_lossy();
+    let line = PENDING.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        pending.push_str(&text);
+        pending
+            .ends_with('\n')
+            .then(|| std::mem::take(&mut *pending))
+    });
+    if let Some(line) = line {
+        emit(level, line.trim_end());
+    }
+}
+
+fn emit(level: Level, line: &str) {
+    if line.is_empty() {
+        return;
+    }
+    // 去掉 "[rtsp @ 0x55d0c8a0] " 前缀里的地址，只保留组件名
+    let line = match line.strip_prefix('[').and_then(|l| l.split_once("] ")) {
+        Some((component, message)) => {
+            let component = component.split(" @ ").next().unwrap_or(component);
+            format!("{}: {}", component, message)
+        }
+        None => line.to_string(),
+    };
+    let prefix = match STREAM.with(Cell::get) {
+        Some(id) => format!("[FFmpeg {}]", id),
+        None => "[FFmpeg]".to_string(),
+    };
+    if level == Level::Warning {
+        log::warn(format_args!("{} {}", prefix, line));
+    } else {
+        log::error(format_args!("{} {}", prefix, line));
+    }
+}
@@ -0,0 +1,135 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDateTime, TimeZone};
+use ffmpeg_the_third as ffmpeg;
+
+use crate::avlog;
+use crate::config::CameraConfig;
+use crate::input::{self, Watchdog};
+use crate::segment::{self, Segment, SegmentClock};
+
+// 断线恢复后在后台从摄像头的 SD 卡录像中补录缺失的时间段
+pub fn spawn(
+    id: usize,
+    camera: CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    running: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        avlog::attach(id);
+        info!("[Stream {}] Backfilling {} - {}", id, from, to);
+        match run(id, &camera, from, to, &running) {
+            Ok(_) => info!("[Stream {}] Backfill of {} - {} finished", id, from, to),
+            Err(e) => error!(
+                "[Stream {}] Backfill of {} - {} failed: {}",
+                id, from, to, e
+            ),
+        }
+    });
+}
+
+pub fn replay_url(camera: &CameraConfig, from: NaiveDateTime, to: NaiveDateTime) -> Option<String> {
+    let template = camera.replay_url.as_ref()?;
+    Some(
+        template
+            .replace("{start}", &format_time(camera, from))
+            .replace("{end}", &format_time(camera, to)),
+    )
+}
+
+fn format_time(camera: &CameraConfig, time: NaiveDateTime) -> String {
+    if camera.replay_utc {
+        if let Some(local) = chrono::Local.from_local_datetime(&time).earliest() {
+            return local
+                .with_timezone(&chrono::Utc)
+                .format(&camera.replay_time_format)
+                .to_string();
+        }
+    }
+    time.format(&camera.replay_time_format).to_string()
+}
+
+// 回放流按实时速度播放，用已经过的时间推算分段在原时间轴上的位置
+pub fn run(
+    id: usize,
+    camera: &CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    running: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let url = replay_url(camera, from, to).ok_or("No replay_url configured")?;
+    let total = (to - from)
+        .to_std()
+        .map_err(|_| "Backfill range ends before it starts".to_string())?;
+
+    let watchdog = Watchdog::new(camera.read_timeout);
+    let stop = {
+        let running = running.clone();
+        move || !running.load(Ordering::SeqCst)
+    };
+    let options = input::Options {
+        probesize: camera.probesize,
+        analyzeduration: camera.analyzeduration,
+        avoptions: &camera.avoptions,
+        ..Default::default()
+    };
+    let mut source = input::open(&url, &options, &watchdog, stop)?;
+    let video_stream_index = source.best(ffmpeg::media::Type::Video)?;
+
+    let opened = Instant::now();
+    let time_base = source
+        .context
+        .stream(video_stream_index)
+        .ok_or("No video stream found")?
+        .time_base();
+    let mut clock = SegmentClock::new(time_base);
+    let mut segment = Segment::create_at(camera, from)
+        .map_err(|e| format!("Failed to create output file: {}", e))?;
+    segment.set_time_base(time_base);
+    // 与实时录制一样按 quiet_segment_minutes 切分
+    let target = segment::quiet_length(camera);
+
+    for result in source.context.packets() {
+        if !running.load(Ordering::SeqCst) || opened.elapsed() >= total {
+            break;
+        }
+        let (stream, packet) = match result {
+            Ok(item) => item,
+            Err(_) if watchdog.expired() => return Err(watchdog.timeout_error()),
+            Err(_) => continue,
+        };
+        watchdog.feed();
+        if stream.index() != video_stream_index {
+            continue;
+        }
+        // 回放通常快于实时，按时间戳切分和命名
+        let ts = packet.dts().or(packet.pts());
+        let length = clock.advance(ts);
+        if length >= target {
+            let position = segment.started + media(length);
+            segment
+                .finish(camera, position)
+                .map_err(|e| format!("Failed to finish file: {}", e))?;
+            segment = Segment::create_at(camera, position)
+                .map_err(|e| format!("Failed to create new output file: {}", e))?;
+            segment.set_time_base(time_base);
+            clock.restart(ts);
+            info!("[Stream {}] Created new backfill file", id);
+        }
+        segment
+            .write_packet(camera, 0, &packet)
+            .map_err(|e| format!("Failed to write packet data: {}", e))?;
+    }
+
+    segment
+        .finish(camera, (segment.started + media(clock.elapsed())).min(to))
+        .map_err(|e| format!("Failed to finish file: {}", e))
+}
+
+fn media(length: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(length).unwrap_or_default()
+}
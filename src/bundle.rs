@@ -0,0 +1,276 @@
+// 导出包：把一段时间的录像原样复制到一个独立目录，交给警方、保险公司等第三方。
+// 目录里有分段及其 .meta/.keyframes、每个分段的缩略图、该时段的索引记录（含注释）、说明文件，
+// 以及 sha256sum 格式的清单 manifest.sha256；配置了 export_sign_command 时再对清单签名
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::format::Pixel;
+
+use crate::config::CameraConfig;
+use crate::decode::Decoder;
+use crate::encryption;
+use crate::hooks;
+use crate::index::{self, Record};
+use crate::input::{self, Watchdog};
+use crate::mux::StreamInfo;
+use crate::segment;
+use crate::sha256::Sha256;
+use crate::snapshot;
+
+const THUMBNAIL_WIDTH: u32 = 320;
+const SIGN_TIMEOUT: Duration = Duration::from_secs(300);
+
+pub struct Summary {
+    pub segments: usize,
+    pub files: usize,
+    pub bytes: u64,
+    pub signed: bool,
+}
+
+pub fn export(
+    camera: &CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    output: &Path,
+    sign_command: Option<&str>,
+) -> Result<Summary, String> {
+    if fs::read_dir(output).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(format!(
+            "{} already exists and is not empty",
+            output.display()
+        ));
+    }
+    let records: Vec<Record> = index::load(camera)
+        .map_err(|e| format!("Failed to read index: {}", e))?
+        .into_iter()
+        .filter(|record| overlaps(record, from, to))
+        .collect();
+    let segments: Vec<(PathBuf, NaiveDateTime, NaiveDateTime)> = records
+        .iter()
+        .filter_map(|record| match record {
+            Record::Segment {
+                start,
+                end,
+                path,
+                substream,
+                ..
+            } if *substream == camera.substream_of.is_some() && path.exists() => {
+                Some((path.clone(), *start, *end))
+            }
+            _ => None,
+        })
+        .collect();
+    if segments.is_empty() {
+        return Err(format!("No recordings between {} and {}", from, to));
+    }
+
+    let footage = output.join("footage");
+    let thumbnails = output.join("thumbnails");
+    for dir in [&footage, &thumbnails] {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    // 清单中的 (摘要, 相对路径)
+    let mut manifest: Vec<(String, String)> = Vec::new();
+    let mut bytes = 0;
+    for (path, ..) in &segments {
+        let name = file_name(path);
+        info!("[Export] Copying {}", path.display());
+        for source in [
+            path.clone(),
+            segment::metadata_path(path),
+            segment::keyframes_path(path),
+        ] {
+            if !source.exists() {
+                continue;
+            }
+            let relative = format!("footage/{}", file_name(&source));
+            let target = output.join(&relative);
+            let encrypted =
+                source == *path && encryption::key_id(path).is_ok_and(|id| id.is_some());
+            let (digest, size) = if encrypted {
+                // 导出包里放解密后的分段
+                encryption::decrypt(camera, path, &target)?;
+                hash(&target)?
+            } else {
+                copy(&source, &target)?
+            };
+            bytes += size;
+            manifest.push((digest, relative));
+        }
+        let thumbnail = format!("thumbnails/{}.jpg", name);
+        match thumbnail_of(camera, &footage.join(&name)) {
+            Ok(jpeg) => {
+                fs::write(output.join(&thumbnail), &jpeg)
+                    .map_err(|e| format!("Failed to write {}: {}", thumbnail, e))?;
+                manifest.push((digest(&jpeg), thumbnail));
+            }
+            Err(e) => error!("[Export] No thumbnail for {}: {}", path.display(), e),
+        }
+    }
+
+    // 该时段的索引记录（分段、事件、保全），路径指向导出包内的文件
+    let mut index_text = String::new();
+    for record in &records {
+        let line = match record {
+            Record::Segment { path, .. } => {
+                if !segments.iter().any(|(p, ..)| p == path) {
+                    continue;
+                }
+                record.to_line().replace(
+                    &path.display().to_string(),
+                    &format!("footage/{}", file_name(path)),
+                )
+            }
+            _ => record.to_line(),
+        };
+        index_text.push_str(&line);
+        index_text.push('\n');
+    }
+    let info = format!(
+        "camera={}\nfrom={}\nto={}\nfirst_segment={}\nlast_segment={}\nsegments={}\nnotes={}\ncreated={}\nexporter=save_rtsp {}\n",
+        camera.name,
+        from,
+        to,
+        segments[0].1,
+        segments[segments.len() - 1].2,
+        segments.len(),
+        records
+            .iter()
+            .filter(|record| matches!(record, Record::Note { .. }))
+            .count(),
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S %z"),
+        env!("CARGO_PKG_VERSION")
+    );
+    for (name, text) in [("index.txt", index_text), ("info.txt", info)] {
+        fs::write(output.join(name), &text)
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        manifest.push((digest(text.as_bytes()), name.to_string()));
+    }
+
+    // 可以用 sha256sum -c manifest.sha256 校验
+    let manifest_path = output.join("manifest.sha256");
+    let text: String = manifest
+        .iter()
+        .map(|(digest, path)| format!("{}  {}\n", digest, path))
+        .collect();
+    fs::write(&manifest_path, text)
+        .map_err(|e| format!("Failed to write {}: {}", manifest_path.display(), e))?;
+
+    let signed = match sign_command {
+        Some(command) => {
+            let mut cmd = hooks::shell(command);
+            cmd.arg("manifest.sha256")
+                .current_dir(output)
+                .env("MANIFEST_PATH", &manifest_path);
+            hooks::run_with_timeout(cmd, SIGN_TIMEOUT)
+                .map_err(|e| format!("Failed to sign the manifest: {}", e))?;
+            true
+        }
+        None => false,
+    };
+    Ok(Summary {
+        segments: segments.len(),
+        files: manifest.len() + 1,
+        bytes,
+        signed,
+    })
+}
+
+fn overlaps(record: &Record, from: NaiveDateTime, to: NaiveDateTime) -> bool {
+    match record {
+        Record::Segment { start, end, .. } => *start < to && *end > from,
+        Record::Event { time, seconds, .. } => {
+            *time < to && *time + chrono::Duration::seconds(*seconds as i64) > from
+        }
+        Record::Hold {
+            from: start,
+            to: end,
+            ..
+        } => *start < to && *end > from,
+        Record::Note { time, .. } => *time >= from && *time < to,
+        // 导出的分段已解密
+        Record::Encrypted { .. } => false,
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// 边复制边计算摘要，返回摘要和字节数
+fn copy(source: &Path, target: &Path) -> Result<(String, u64), String> {
+    let copied = (|| -> io::Result<(String, u64)> {
+        let mut input = File::open(source)?;
+        let mut output = File::create(target)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1 << 16];
+        let mut size = 0;
+        loop {
+            let n = input.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            output.write_all(&buffer[..n])?;
+            size += n as u64;
+        }
+        output.sync_all()?;
+        Ok((hasher.finish(), size))
+    })();
+    copied.map_err(|e| format!("Failed to copy {}: {}", source.display(), e))
+}
+
+fn hash(path: &Path) -> Result<(String, u64), String> {
+    let hashed = (|| -> io::Result<(String, u64)> {
+        let mut input = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1 << 16];
+        let mut size = 0;
+        loop {
+            let n = input.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            size += n as u64;
+        }
+        Ok((hasher.finish(), size))
+    })();
+    hashed.map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+fn digest(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+// 分段第一个关键帧的 JPEG
+fn thumbnail_of(camera: &CameraConfig, path: &Path) -> Result<Vec<u8>, String> {
+    let url = path.to_string_lossy();
+    let mut source = input::open(&url, &Default::default(), &Watchdog::new(None), || false)?;
+    let video_stream_index = source.best(ffmpeg::media::Type::Video)?;
+    let info = source
+        .context
+        .stream(video_stream_index)
+        .map(|stream| StreamInfo::of(&stream))
+        .ok_or("No video stream found")?;
+    let packet = source
+        .context
+        .packets()
+        .filter_map(Result::ok)
+        .find(|(stream, packet)| stream.index() == video_stream_index && packet.is_key())
+        .map(|(_, packet)| packet)
+        .ok_or("No keyframe found")?;
+    let mut decoder = Decoder::new(0, camera, &info)?;
+    let picture = decoder.picture(&packet, Some(THUMBNAIL_WIDTH), Pixel::YUVJ420P)?;
+    snapshot::encode_jpeg(&picture)
+}
@@ -0,0 +1,273 @@
+// 引擎内部的事件总线：流状态、分段开始/结束、事件、片段、覆盖率、统计、告警（存储等）
+// 和 info!/error! 的日志都发布到这里。日志是总线上唯一同步处理的订阅者，发布时即写出，
+// 不会丢；其余订阅者（插件、webhook、MQTT、下游 crate）各自用 subscribe 得到一个有上限的
+// 通道，在自己的线程里处理，处理不过来时丢弃并警告，不会拖慢录制线程
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use crate::log;
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+
+// 每个订阅者的通道最多积压的事件数
+const CAPACITY: usize = 1024;
+// 丢弃事件时第一次和此后每这么多次警告一次
+const DROP_WARNING_EVERY: u64 = 100;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+struct Subscriber {
+    name: &'static str,
+    sender: SyncSender<Arc<Event>>,
+    dropped: u64,
+}
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    // up、down、paused 或 disabled，down 附带错误信息
+    StreamState {
+        camera: String,
+        state: &'static str,
+        detail: Option<String>,
+    },
+    SegmentStarted {
+        camera: String,
+        path: PathBuf,
+        start: NaiveDateTime,
+    },
+    SegmentFinished {
+        camera: String,
+        path: PathBuf,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        bytes: u64,
+    },
+    // 触发文件、ONVIF 等产生的事件
+    Triggered {
+        camera: String,
+        source: String,
+    },
+    Clip {
+        camera: String,
+        source: String,
+        path: PathBuf,
+    },
+    Coverage {
+        camera: String,
+        hour: NaiveDateTime,
+        percent: f64,
+    },
+    Stats {
+        camera: String,
+        interval: Duration,
+        bitrate_kbps: f64,
+        packets: u64,
+        drops: u64,
+        reconnects: u64,
+    },
+    // 告警：storage、mirror、down 等
+    Alert {
+        camera: String,
+        kind: String,
+        message: String,
+    },
+    // info!/error! 等日志，level 为 info、warning 或 error；camera 取自 "[Stream 3]" 前缀，
+    // 不属于某路流时为空
+    Log {
+        camera: String,
+        level: &'static str,
+        message: String,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::StreamState { .. } => "stream_state",
+            Event::SegmentStarted { .. } => "segment_started",
+            Event::SegmentFinished { .. } => "segment_finished",
+            Event::Triggered { .. } => "event",
+            Event::Clip { .. } => "clip",
+            Event::Coverage { .. } => "coverage",
+            Event::Stats { .. } => "stats",
+            Event::Alert { .. } => "alert",
+            Event::Log { .. } => "log",
+        }
+    }
+
+    pub fn camera(&self) -> &str {
+        match self {
+            Event::StreamState { camera, .. }
+            | Event::SegmentStarted { camera, .. }
+            | Event::SegmentFinished { camera, .. }
+            | Event::Triggered { camera, .. }
+            | Event::Clip { camera, .. }
+            | Event::Coverage { camera, .. }
+            | Event::Stats { camera, .. }
+            | Event::Alert { camera, .. }
+            | Event::Log { camera, .. } => camera,
+        }
+    }
+
+    // 一行 JSON：{"event":"segment_finished","camera":"gate","ts":...,其余字段}
+    pub fn to_json(&self) -> String {
+        let mut fields: Vec<(&str, String)> = Vec::new();
+        match self {
+            Event::StreamState { state, detail, .. } => {
+                fields.push(("state", string(state)));
+                if let Some(detail) = detail {
+                    fields.push(("detail", string(detail)));
+                }
+            }
+            Event::SegmentStarted { path, start, .. } => {
+                fields.push(("path", string(&path.display().to_string())));
+                fields.push(("start", time(start)));
+            }
+            Event::SegmentFinished {
+                path,
+                start,
+                end,
+                bytes,
+                ..
+            } => {
+                fields.push(("path", string(&path.display().to_string())));
+                fields.push(("start", time(start)));
+                fields.push(("end", time(end)));
+                fields.push(("bytes", bytes.to_string()));
+            }
+            Event::Triggered { source, .. } => fields.push(("source", string(source))),
+            Event::Clip { source, path, .. } => {
+                fields.push(("source", string(source)));
+                fields.push(("path", string(&path.display().to_string())));
+            }
+            Event::Coverage { hour, percent, .. } => {
+                fields.push(("hour", time(hour)));
+                fields.push(("percent", format!("{:.1}", percent)));
+            }
+            Event::Stats {
+                interval,
+                bitrate_kbps,
+                packets,
+                drops,
+                reconnects,
+                ..
+            } => {
+                fields.push(("interval_seconds", interval.as_secs().to_string()));
+                fields.push(("bitrate_kbps", format!("{:.1}", bitrate_kbps)));
+                fields.push(("packets", packets.to_string()));
+                fields.push(("drops", drops.to_string()));
+                fields.push(("reconnects", reconnects.to_string()));
+            }
+            Event::Alert { kind, message, .. } => {
+                fields.push(("kind", string(kind)));
+                fields.push(("message", string(message)));
+            }
+            Event::Log { level, message, .. } => {
+                fields.push(("level", string(level)));
+                fields.push(("message", string(message)));
+            }
+        }
+        let mut json = format!(
+            "{{\"event\":\"{}\",\"camera\":{},\"ts\":\"{}\"",
+            self.name(),
+            string(self.camera()),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z")
+        );
+        for (key, value) in fields {
+            json.push_str(&format!(",\"{}\":{}", key, value));
+        }
+        json.push('}');
+        json
+    }
+}
+
+fn string(value: &str) -> String {
+    format!("\"{}\"", log::escape(value))
+}
+
+fn time(value: &NaiveDateTime) -> String {
+    string(&value.format(TIME_FORMAT).to_string())
+}
+
+// 订阅之后发布的所有事件；接收端丢弃后自动退订
+pub fn subscribe() -> Receiver<Arc<Event>> {
+    subscribe_as("Subscriber")
+}
+
+// name 用于通道已满时的警告
+fn subscribe_as(name: &'static str) -> Receiver<Arc<Event>> {
+    let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+    SUBSCRIBERS.lock().unwrap().push(Subscriber {
+        name,
+        sender,
+        dropped: 0,
+    });
+    receiver
+}
+
+pub fn has_subscribers() -> bool {
+    !SUBSCRIBERS.lock().unwrap().is_empty()
+}
+
+// 不阻塞：通道已满的订阅者丢掉这个事件
+pub fn publish(event: Event) {
+    log::event(&event);
+    let event = Arc::new(event);
+    SUBSCRIBERS.lock().unwrap().retain_mut(|subscriber| {
+        match subscriber.sender.try_send(event.clone()) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                subscriber.dropped += 1;
+                if subscriber.dropped % DROP_WARNING_EVERY == 1 {
+                    // 直接写日志，不再经过总线
+                    log::event(&Event::Log {
+                        camera: String::new(),
+                        level: "warning",
+                        message: format!(
+                            "[Bus] {} is falling behind, dropped {} event(s) so far",
+                            subscriber.name, subscriber.dropped
+                        ),
+                    });
+                }
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    });
+}
+
+// 在单独的线程里逐个处理事件；running 变为 false 后处理完积压的事件再退出。
+// 处理失败只记日志，事件不重发
+pub fn spawn<F>(name: &'static str, running: Arc<AtomicBool>, mut handle: F) -> JoinHandle<()>
+where
+    F: FnMut(&Event) -> Result<(), String> + Send + 'static,
+{
+    let events = subscribe_as(name);
+    let own = format!("[{}] ", name);
+    thread::spawn(move || loop {
+        let event = match events.recv_timeout(Duration::from_secs(1)) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) if running.load(Ordering::SeqCst) => continue,
+            Err(_) => break,
+        };
+        // 自己写的日志（如投递失败）不再交给自己，避免循环
+        if matches!(&*event, Event::Log { message, .. } if message.starts_with(&own)) {
+            continue;
+        }
+        if let Err(e) = handle(&event) {
+            error!(
+                "[{}] Failed to deliver {} of {}: {}",
+                name,
+                event.name(),
+                event.camera(),
+                e
+            );
+        }
+    })
+}
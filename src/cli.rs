@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+
+// 命令行：save_rtsp [子命令 [目标]] [--key value ...]
+pub struct Args {
+    pub command: Option<String>,
+    // 子命令后的位置参数，如 pipe 的摄像头地址
+    pub target: Option<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    pub fn parse(args: Vec<String>) -> Result<Args, String> {
+        let mut args = args.into_iter().peekable();
+        let command = match args.peek() {
+            Some(arg) if !arg.starts_with("--") => args.next(),
+            _ => None,
+        };
+        let target = match args.peek() {
+            Some(arg) if command.is_some() && !arg.starts_with("--") => args.next(),
+            _ => None,
+        };
+        let mut options = HashMap::new();
+        while let Some(arg) = args.next() {
+            let key = arg
+                .strip_prefix("--")
+                .ok_or_else(|| format!("Unexpected argument '{}'", arg))?;
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for --{}", key))?;
+            options.insert(key.to_string(), value);
+        }
+        Ok(Args {
+            command,
+            target,
+            options,
+        })
+    }
+
+    // 检查是否有当前子命令不认识的选项或多余的位置参数
+    pub fn allow(&self, keys: &[&str]) -> Result<(), String> {
+        if let Some(target) = &self.target {
+            return Err(format!("Unexpected argument '{}'", target));
+        }
+        self.allow_with_target(keys)
+    }
+
+    pub fn allow_with_target(&self, keys: &[&str]) -> Result<(), String> {
+        match self
+            .options
+            .keys()
+            .find(|k| k.as_str() != "config" && !keys.contains(&k.as_str()))
+        {
+            Some(key) => Err(format!("Unknown option --{}", key)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(String::as_str)
+    }
+
+    pub fn require(&self, key: &str) -> Result<&str, String> {
+        self.get(key)
+            .ok_or_else(|| format!("Missing required option --{}", key))
+    }
+
+    pub fn config_path(&self) -> &str {
+        self.get("config").unwrap_or("rtsp.txt")
+    }
+
+    pub fn time(&self, key: &str) -> Result<NaiveDateTime, String> {
+        parse_time(self.require(key)?)
+    }
+}
+
+// 本地时间，接受 "2024-01-02 03:04:05" 或 "2024-01-02T03:04:05"
+pub fn parse_time(value: &str) -> Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| format!("Invalid time '{}', expected YYYY-MM-DD HH:MM:SS", value))
+}
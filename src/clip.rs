@@ -0,0 +1,372 @@
+// 事件片段：事件结束后把覆盖事件时段的分段剪裁拼接成一个独立的 MP4（默认不重新编码），
+// 写到 clip_dir 并通知出去，值班人员收到告警就能直接看录像
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::codec::context::Context;
+use ffmpeg::{frame, Packet, Rational};
+
+use crate::alert;
+use crate::audio::TIME_BASE;
+use crate::bus::{self, Event};
+use crate::config::CameraConfig;
+use crate::input::{self, Watchdog};
+use crate::mux::{Muxer, StreamInfo};
+use crate::segment;
+
+// 事件结束后再等一会，让最后一个 GOP 落盘
+const SETTLE: Duration = Duration::from_secs(5);
+
+// 在后台等事件时段结束后生成片段；未配置 clip_dir 时什么也不做
+pub fn schedule(camera: &CameraConfig, source: &str, time: NaiveDateTime, seconds: u64) {
+    let dir = match &camera.clip_dir {
+        Some(dir) => dir.clone(),
+        None => return,
+    };
+    let camera = camera.clone();
+    let source = source.to_string();
+    thread::spawn(move || {
+        let from = time - chrono::Duration::seconds(camera.clip_pre_seconds as i64);
+        let to = time + chrono::Duration::seconds(seconds as i64);
+        let wait = (to - chrono::Local::now().naive_local())
+            .to_std()
+            .unwrap_or_default();
+        thread::sleep(wait + SETTLE);
+        let path = dir.join(format!(
+            "{}_{}.mp4",
+            camera.name,
+            time.format("%Y%m%d_%H%M%S")
+        ));
+        match assemble(&camera, from, to, camera.clip_exact, &path) {
+            Ok(_) => notify(&camera, &source, &path),
+            Err(e) => error!(
+                "[Clip] Failed to create clip of {} for {}: {}",
+                source, camera.name, e
+            ),
+        }
+    });
+}
+
+fn notify(camera: &CameraConfig, source: &str, path: &Path) {
+    info!(
+        "[Clip] {} clip for {}: {}",
+        source,
+        camera.name,
+        path.display()
+    );
+    let file = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut extra = vec![("ALERT_CLIP", path.display().to_string())];
+    if let Some(url) = &camera.clip_url {
+        extra.push(("ALERT_CLIP_URL", url.replace("{file}", &file)));
+    }
+    // 截图是事件期间的最新画面，适合直接贴在消息里
+    if let Some(dir) = &camera.snapshot_dir {
+        extra.push((
+            "ALERT_PREVIEW",
+            dir.join(format!("{}.jpg", camera.name))
+                .display()
+                .to_string(),
+        ));
+    }
+    alert::raise_with(
+        camera,
+        "event",
+        &format!("Clip of {} ready: {}", source, path.display()),
+        &extra,
+    );
+    bus::publish(Event::Clip {
+        camera: camera.name.clone(),
+        source: source.to_string(),
+        path: path.to_path_buf(),
+    });
+}
+
+// 把 [from, to) 内的视频剪成一个 MP4：从 from 之前最近的关键帧开始，时间戳重新从零计起；
+// exact 时首尾两个 GOP 解码后重新编码，片段正好从 from 开始、到 to 结束。
+// 先写临时文件再改名，读到一半的片段不会被当成完整的
+pub fn assemble(
+    camera: &CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    exact: bool,
+    target: &Path,
+) -> Result<(), String> {
+    let segments = segment::list_segments(camera).map_err(|e| e.to_string())?;
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let mut tmp = target.as_os_str().to_owned();
+    tmp.push(".part");
+    let tmp = PathBuf::from(tmp);
+    let mut clip = Clip {
+        from,
+        end: (to - from).num_microseconds().unwrap_or(0),
+        exact,
+        muxer: None,
+        target: &tmp,
+        info: None,
+        pending: Vec::new(),
+        gop: Vec::new(),
+        head: false,
+        begun: false,
+        reached_end: false,
+        start: None,
+        last_dts: None,
+    };
+    for (index, (path, started)) in segments.iter().enumerate() {
+        let next = segments.get(index + 1).map(|(_, next)| *next);
+        if *started >= to || next.is_some_and(|next| next <= from) {
+            continue;
+        }
+        clip.copy(path, *started)?;
+    }
+    // 录像在 to 之前就结束时最后一个 GOP 是完整的，不用剪
+    let trim_end = clip.reached_end;
+    clip.flush(trim_end)?;
+    let mut muxer = clip.muxer.take().ok_or_else(|| {
+        let _ = std::fs::remove_file(&tmp);
+        format!("No recordings between {} and {}", from, to)
+    })?;
+    muxer.finish().map_err(|e| e.to_string())?;
+    drop(muxer);
+    std::fs::rename(&tmp, target).map_err(|e| format!("Failed to rename {}: {}", tmp.display(), e))
+}
+
+// 包的时间戳统一换算成距 from 的微秒数
+struct Clip<'a> {
+    from: NaiveDateTime,
+    end: i64,
+    exact: bool,
+    target: &'a Path,
+    muxer: Option<Muxer>,
+    // 第一个分段的视频参数；摄像头中途改了编码时，片段只包含与它编码相同的部分
+    info: Option<StreamInfo>,
+    // 还没到 from 时缓存最近一个 GOP，保证片段以关键帧开头
+    pending: Vec<Packet>,
+    // 当前 GOP，遇到下一个关键帧时写出，这样才知道最后一个 GOP 是哪个
+    gop: Vec<Packet>,
+    // 当前 GOP 从 from 之前开始
+    head: bool,
+    begun: bool,
+    // 读到了 to 之后的包
+    reached_end: bool,
+    // 片段第一个包的位置，以及上一个写出包的 dts
+    start: Option<i64>,
+    last_dts: Option<i64>,
+}
+
+impl Clip<'_> {
+    fn copy(&mut self, path: &Path, started: NaiveDateTime) -> Result<(), String> {
+        let url = path.to_string_lossy();
+        let mut source = input::open(&url, &Default::default(), &Watchdog::new(None), || false)?;
+        let video_stream_index = source.best(ffmpeg::media::Type::Video)?;
+        let (info, time_base) = match source.context.stream(video_stream_index) {
+            Some(stream) => (
+                StreamInfo {
+                    parameters: stream.parameters(),
+                    time_base: TIME_BASE,
+                },
+                stream.time_base(),
+            ),
+            None => return Ok(()),
+        };
+        let codec = info.parameters.id();
+        if self.info.get_or_insert(info).parameters.id() != codec {
+            return Ok(());
+        }
+        // 分段内时间相对于 from 的微秒数
+        let offset = (started - self.from).num_microseconds().unwrap_or(0);
+        let mut first = None;
+        for (stream, packet) in source.context.packets().filter_map(Result::ok) {
+            if stream.index() != video_stream_index {
+                continue;
+            }
+            let ts = match packet.dts().or(packet.pts()) {
+                Some(ts) => ts,
+                None => continue,
+            };
+            let first = *first.get_or_insert(ts);
+            let position = offset + micros(ts - first, time_base);
+            if position >= self.end {
+                self.reached_end = true;
+                break;
+            }
+            let packet = rebase(position, packet, time_base);
+            if !self.begun {
+                if position < 0 {
+                    if packet.is_key() {
+                        self.pending.clear();
+                    }
+                    if packet.is_key() || !self.pending.is_empty() {
+                        self.pending.push(packet);
+                    }
+                    continue;
+                }
+                if self.pending.is_empty() && !packet.is_key() {
+                    continue;
+                }
+                self.begun = true;
+                self.head = !self.pending.is_empty();
+                self.gop = std::mem::take(&mut self.pending);
+            } else if packet.is_key() {
+                self.flush(false)?;
+            }
+            self.gop.push(packet);
+        }
+        Ok(())
+    }
+
+    // 写出当前 GOP；精确剪裁时首尾的 GOP 重新编码，失败时退回原样复制整个 GOP
+    fn flush(&mut self, trim_end: bool) -> Result<(), String> {
+        let gop = std::mem::take(&mut self.gop);
+        let trim_start = std::mem::take(&mut self.head);
+        if self.exact && (trim_start || trim_end) && !gop.is_empty() {
+            match self.encode(&gop, trim_start, trim_end) {
+                Ok(packets) => {
+                    for packet in packets {
+                        self.write(packet)?;
+                    }
+                    return Ok(());
+                }
+                Err(e) => error!(
+                    "[Clip] Cannot cut {} exactly, keeping the whole GOP: {}",
+                    self.target.display(),
+                    e
+                ),
+            }
+        }
+        for packet in gop {
+            self.write(packet)?;
+        }
+        Ok(())
+    }
+
+    // 解码整个 GOP，只把 [from, to) 内的帧交给同种编码的编码器。编码器不写全局头，
+    // 参数集随关键帧一起写在码流里，与后面直接复制的部分拼接后仍能播放
+    fn encode(
+        &self,
+        gop: &[Packet],
+        trim_start: bool,
+        trim_end: bool,
+    ) -> Result<Vec<Packet>, String> {
+        let info = self.info.as_ref().ok_or("No video stream found")?;
+        let id = info.parameters.id();
+        let codec = ffmpeg::encoder::find(id)
+            .ok_or_else(|| format!("No {} encoder available", id.name()))?;
+        let mut decoder = Context::from_parameters(info.parameters.clone())
+            .and_then(|context| context.decoder().video())
+            .map_err(|e| format!("Failed to open decoder: {}", e))?;
+        let first = if trim_start { 0 } else { i64::MIN };
+        let last = if trim_end { self.end } else { i64::MAX };
+        let mut frames = Vec::new();
+        let mut decoded = frame::Video::empty();
+        for packet in gop {
+            decoder
+                .send_packet(packet)
+                .map_err(|e| format!("Failed to decode frame: {}", e))?;
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                frames.push(std::mem::replace(&mut decoded, frame::Video::empty()));
+            }
+        }
+        decoder
+            .send_eof()
+            .map_err(|e| format!("Failed to decode frame: {}", e))?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            frames.push(std::mem::replace(&mut decoded, frame::Video::empty()));
+        }
+        frames.retain(|frame| frame.pts().is_some_and(|pts| pts >= first && pts < last));
+        let sample = match frames.first() {
+            Some(sample) => sample,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut encoder = Context::new_with_codec(codec)
+            .encoder()
+            .video()
+            .map_err(|e| e.to_string())?;
+        encoder.set_width(sample.width());
+        encoder.set_height(sample.height());
+        encoder.set_format(sample.format());
+        encoder.set_time_base(TIME_BASE);
+        let mut encoder = encoder
+            .open_as(codec)
+            .map_err(|e| format!("Failed to open {} encoder: {}", id.name(), e))?;
+        let mut packets = Vec::new();
+        let mut packet = Packet::empty();
+        for frame in &frames {
+            encoder
+                .send_frame(frame)
+                .map_err(|e| format!("Failed to encode frame: {}", e))?;
+            while encoder.receive_packet(&mut packet).is_ok() {
+                packets.push(std::mem::replace(&mut packet, Packet::empty()));
+            }
+        }
+        encoder
+            .send_eof()
+            .map_err(|e| format!("Failed to encode frame: {}", e))?;
+        while encoder.receive_packet(&mut packet).is_ok() {
+            packets.push(std::mem::replace(&mut packet, Packet::empty()));
+        }
+        Ok(packets)
+    }
+
+    fn write(&mut self, mut packet: Packet) -> Result<(), String> {
+        if self.muxer.is_none() {
+            let info = self.info.as_ref().ok_or("No video stream found")?;
+            let muxer = Muxer::open(
+                self.target,
+                Some("mp4"),
+                std::slice::from_ref(info),
+                &[("movflags", "+faststart")],
+                &[],
+            )
+            .map_err(|e| format!("Failed to create {}: {}", self.target.display(), e))?;
+            self.muxer = Some(muxer);
+        }
+        let position = packet.dts().unwrap_or(0);
+        let delay = packet.pts().map_or(0, |pts| (pts - position).max(0));
+        let start = *self.start.get_or_insert(position);
+        let mut dts = position - start;
+        if let Some(last) = self.last_dts {
+            dts = dts.max(last + 1);
+        }
+        self.last_dts = Some(dts);
+        packet.set_dts(Some(dts));
+        packet.set_pts(Some(dts + delay));
+        match self.muxer.as_mut() {
+            Some(muxer) => muxer.write(0, packet).map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+// dts 为 position，pts 和时长也换算成微秒
+fn rebase(position: i64, mut packet: Packet, time_base: Rational) -> Packet {
+    let delay = match (packet.pts(), packet.dts()) {
+        (Some(pts), Some(dts)) => micros(pts - dts, time_base).max(0),
+        _ => 0,
+    };
+    packet.set_dts(Some(position));
+    packet.set_pts(Some(position + delay));
+    packet.set_duration(micros(packet.duration(), time_base));
+    packet
+}
+
+pub fn micros(ticks: i64, time_base: Rational) -> i64 {
+    let (num, den) = (
+        time_base.numerator() as i128,
+        time_base.denominator() as i128,
+    );
+    if den == 0 {
+        return 0;
+    }
+    (ticks as i128 * num * 1_000_000 / den) as i64
+}
@@ -0,0 +1,60 @@
+// 系统时钟跳变检测：比较墙上时间和单调时钟各自走过的时长。NTP 一步校时、手动改时间时
+// 只有墙上时间跳变；系统休眠时单调时钟停走，开机时间（/proc/uptime，含休眠时间）
+// 与墙上时间一起前进，由此区分两者
+use std::fs;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+
+pub enum Jump {
+    // 墙上时间被调整了这么多，之前的时间按旧时钟计
+    Step(chrono::Duration),
+    // 系统休眠了这么久
+    Suspend(Duration),
+}
+
+pub struct Anchor {
+    wall: NaiveDateTime,
+    mono: Instant,
+    boot: Option<Duration>,
+}
+
+impl Anchor {
+    pub fn now() -> Anchor {
+        Anchor {
+            wall: chrono::Local::now().naive_local(),
+            mono: Instant::now(),
+            boot: boot_time(),
+        }
+    }
+
+    // 两个时钟走过的时长相差超过 threshold 时视为跳变
+    pub fn check(&self, threshold: Duration) -> Option<Jump> {
+        let mono = self.mono.elapsed();
+        let wall = chrono::Local::now().naive_local() - self.wall;
+        let drift = wall - chrono::Duration::from_std(mono).ok()?;
+        if drift.abs().to_std().ok()? < threshold {
+            return None;
+        }
+        let slept = self
+            .boot
+            .zip(boot_time())
+            .map(|(before, now)| now.saturating_sub(before).saturating_sub(mono));
+        match (drift.to_std(), slept) {
+            // 墙上时间多走的部分基本就是休眠的时间
+            (Ok(forward), Some(slept))
+                if slept >= threshold && forward.abs_diff(slept) < threshold =>
+            {
+                Some(Jump::Suspend(slept))
+            }
+            _ => Some(Jump::Step(drift)),
+        }
+    }
+}
+
+// 开机以来的时间（含休眠），仅 Linux
+fn boot_time() -> Option<Duration> {
+    let text = fs::read_to_string("/proc/uptime").ok()?;
+    let seconds: f64 = text.split_whitespace().next()?.parse().ok()?;
+    Some(Duration::from_secs_f64(seconds))
+}
@@ -0,0 +1,142 @@
+// 集群模式：多台录像机共用同一份摄像头列表和一个共享目录（NFS、SMB 等），
+// 各自在 <cluster_dir>/<node>.node 中写心跳计数器。按在线节点做一致性分配
+// （rendezvous 哈希），每台只录分给自己的摄像头；某台停止心跳后，
+// 它的摄像头在几秒内重新分给其余节点，节点恢复后再分回去。
+// 是否在线看计数器在本机观察到的变化，不依赖各台机器的时钟一致
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::control::StreamHandle;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+// 计数器超过该时长没有变化视为节点离线
+const NODE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn spawn(
+    dir: PathBuf,
+    node: String,
+    streams: Vec<Arc<StreamHandle>>,
+    running: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<()>> {
+    fs::create_dir_all(&dir)?;
+    let heartbeat = dir.join(format!("{}.node", node));
+    fs::write(&heartbeat, "0")?;
+    // 第一次分配之前一路也不录，避免与其他节点重复录制
+    for handle in &streams {
+        handle.set_standby(true);
+    }
+    info!("[Cluster] Joined {} as {}", dir.display(), node);
+    Ok(thread::spawn(move || {
+        let mut peers: HashMap<String, (String, Option<Instant>)> = HashMap::new();
+        let mut beat: u64 = 0;
+        let mut last_beat: Option<Instant> = None;
+        let mut assigned: Option<Vec<String>> = None;
+        while running.load(Ordering::SeqCst) {
+            if last_beat.is_none_or(|t| t.elapsed() >= HEARTBEAT_INTERVAL) {
+                beat += 1;
+                if let Err(e) = fs::write(&heartbeat, beat.to_string()) {
+                    error!("[Cluster] Failed to write {}: {}", heartbeat.display(), e);
+                }
+                match scan(&dir, &node, &mut peers) {
+                    Ok(nodes) if assigned.as_ref() != Some(&nodes) => {
+                        info!("[Cluster] Nodes online: {}", nodes.join(", "));
+                        assign(&node, &nodes, &streams);
+                        assigned = Some(nodes);
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("[Cluster] Failed to scan {}: {}", dir.display(), e),
+                }
+                last_beat = Some(Instant::now());
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+        // 正常退出时立即让出摄像头
+        let _ = fs::remove_file(&heartbeat);
+    }))
+}
+
+// 返回在线节点（含自己），按名字排序。peers 记着每个节点的计数器和本机看到它变化的时间，
+// 第一次看到的节点没有变化时间，计数器变了才算在线，早已崩溃的节点留下的文件不会被当成在线
+fn scan(
+    dir: &Path,
+    node: &str,
+    peers: &mut HashMap<String, (String, Option<Instant>)>,
+) -> io::Result<Vec<String>> {
+    let mut nodes = vec![node.to_string()];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = match path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".node"))
+        {
+            Some(name) if name != node => name.to_string(),
+            _ => continue,
+        };
+        let beat = fs::read_to_string(&path).unwrap_or_default();
+        let seen = peers
+            .entry(name.clone())
+            .or_insert_with(|| (beat.clone(), None));
+        if seen.0 != beat {
+            *seen = (beat, Some(Instant::now()));
+        }
+        if seen
+            .1
+            .is_some_and(|changed| changed.elapsed() < NODE_TIMEOUT)
+        {
+            nodes.push(name);
+        }
+    }
+    nodes.sort();
+    Ok(nodes)
+}
+
+fn assign(node: &str, nodes: &[String], streams: &[Arc<StreamHandle>]) {
+    for handle in streams {
+        // 子码流跟随主码流分配
+        let camera = handle
+            .camera
+            .substream_of
+            .as_ref()
+            .unwrap_or(&handle.camera.name);
+        let owner = nodes
+            .iter()
+            .max_by_key(|n| weight(n, camera))
+            .map_or(node, String::as_str);
+        let standby = owner != node;
+        if handle.set_standby(standby) != standby {
+            if !standby {
+                info!("[Cluster] Recording {}", handle.camera.name);
+            } else {
+                info!("[Cluster] {} is recorded by {}", handle.camera.name, owner);
+            }
+        }
+    }
+}
+
+// FNV-1a，各节点必须算出相同的结果，不能用带随机种子的哈希
+fn weight(node: &str, camera: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in node.bytes().chain([0]).chain(camera.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// 默认节点名为主机名
+pub fn default_node_name() -> String {
+    fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| format!("node-{}", std::process::id()))
+}
@@ -0,0 +1,1063 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::NaiveTime;
+
+use crate::migrate;
+
+// rtsp.txt 格式：
+//   version=N                配置格式版本，旧版本在读取时自动迁移（见 migrate.rs）
+//   key=value ...            全局设置，作为所有摄像头的默认值
+//   %tenant key=value ...    属于该租户的摄像头的设置，租户的录像默认写在 <output_dir>/<租户> 下
+//   @tag key=value ...       带该标签的摄像头的设置
+//   rtsp://... key=value ... 摄像头，可覆盖全局和标签设置
+//   # 开头为注释，含空格的值用双引号括起来
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub cameras: Vec<CameraConfig>,
+    // 以下为只能写在全局行上的设置
+    pub ntp_server: Option<String>,
+    pub trigger_dir: Option<PathBuf>,
+    pub hook_concurrency: usize,
+    // Lua 脚本钩子
+    pub script: Option<PathBuf>,
+    // 资源压力阈值：录像目录剩余空间（MB）和每核平均负载
+    pub min_free_mb: Option<u64>,
+    pub max_load: Option<f64>,
+    // 每个摄像头在独立子进程中录制
+    pub isolate: bool,
+    // 子进程常驻内存上限（MB），超过视为泄漏并重启
+    pub worker_max_rss_mb: Option<u64>,
+    pub log_format: LogFormat,
+    // OTLP/HTTP 导出地址及上报的服务名
+    pub otlp_endpoint: Option<String>,
+    pub otlp_service_name: String,
+    // 按需剪辑接口的监听地址，如 127.0.0.1:8080
+    pub http_listen: Option<String>,
+    // 集群模式的共享目录和本节点名
+    pub cluster_dir: Option<PathBuf>,
+    pub node_name: Option<String>,
+    // 对导出包清单签名的命令，清单路径为 $1
+    pub export_sign_command: Option<String>,
+    // 设置后 HTTP 接口开放 POST /streams，需要带上这个令牌
+    pub admin_token: Option<String>,
+    // 读取的配置文件，新增摄像头时写回
+    pub path: Option<PathBuf>,
+    // 存储健康检查的间隔（None 表示不检查）和写延迟阈值
+    pub storage_check: Option<Duration>,
+    pub max_write_latency: Duration,
+    // 启动前在各录像目录写入这么多 MB 测量吞吐，None 表示不测
+    pub preflight_mb: Option<u64>,
+    // 事件总线的 webhook 地址和 MQTT 服务器、主题前缀，以及转发给它们的事件类型
+    pub webhook_url: Option<String>,
+    pub mqtt_broker: Option<String>,
+    pub mqtt_topic: String,
+    pub publish_events: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    // 每条日志一行 JSON
+    Json,
+}
+
+impl Config {
+    // 处理全局专用的设置，返回 false 表示交给摄像头默认值处理
+    fn apply(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "ntp_server" => self.ntp_server = (!value.is_empty()).then(|| value.to_string()),
+            "trigger_dir" => self.trigger_dir = Some(PathBuf::from(value)),
+            "script" => self.script = Some(PathBuf::from(value)),
+            "isolation" => {
+                self.isolate = match value {
+                    "thread" => false,
+                    "process" => true,
+                    _ => return Err(format!("Invalid value for {}: '{}'", key, value)),
+                }
+            }
+            "worker_max_rss_mb" => {
+                let mb = parse_number(key, value)?;
+                self.worker_max_rss_mb = (mb > 0).then_some(mb);
+            }
+            "min_free_mb" => {
+                let mb = parse_number(key, value)?;
+                self.min_free_mb = (mb > 0).then_some(mb);
+            }
+            "max_load" => {
+                let load: f64 = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}: '{}'", key, value))?;
+                self.max_load = (load > 0.0).then_some(load);
+            }
+            "otlp_endpoint" => self.otlp_endpoint = Some(value.to_string()),
+            "otlp_service_name" => self.otlp_service_name = value.to_string(),
+            "cluster_dir" => self.cluster_dir = Some(PathBuf::from(value)),
+            "node_name" => self.node_name = Some(value.to_string()),
+            "export_sign_command" => self.export_sign_command = Some(value.to_string()),
+            "admin_token" => self.admin_token = (!value.is_empty()).then(|| value.to_string()),
+            "storage_check_minutes" => {
+                let minutes: u64 = parse_number(key, value)?;
+                self.storage_check = (minutes > 0).then(|| Duration::from_secs(minutes * 60));
+            }
+            "max_write_latency_ms" => {
+                self.max_write_latency = Duration::from_millis(parse_number(key, value)?)
+            }
+            "preflight_mb" => {
+                let mb = parse_number(key, value)?;
+                self.preflight_mb = (mb > 0).then_some(mb);
+            }
+            "webhook_url" => self.webhook_url = (!value.is_empty()).then(|| value.to_string()),
+            "mqtt_broker" => self.mqtt_broker = (!value.is_empty()).then(|| value.to_string()),
+            "mqtt_topic" => self.mqtt_topic = value.trim_end_matches('/').to_string(),
+            "publish_events" => {
+                let mut events = Vec::new();
+                for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                    if !matches!(
+                        name,
+                        "stream_state"
+                            | "segment_started"
+                            | "segment_finished"
+                            | "event"
+                            | "clip"
+                            | "coverage"
+                            | "stats"
+                            | "alert"
+                            | "log"
+                    ) {
+                        return Err(format!("Invalid value for {}: '{}'", key, name));
+                    }
+                    events.push(name.to_string());
+                }
+                self.publish_events = events;
+            }
+            // 版本号已在迁移时处理
+            "version" => {}
+            "http_listen" => self.http_listen = (!value.is_empty()).then(|| value.to_string()),
+            "log_format" => {
+                self.log_format = match value {
+                    "text" => LogFormat::Text,
+                    "json" => LogFormat::Json,
+                    _ => return Err(format!("Invalid value for {}: '{}'", key, value)),
+                }
+            }
+            "hook_concurrency" => {
+                let limit = parse_number(key, value)?;
+                if limit == 0 {
+                    return Err("hook_concurrency must be at least 1".to_string());
+                }
+                self.hook_concurrency = limit as usize;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+// 资源紧张时先降级、暂停低优先级的摄像头，高优先级的始终完整录制
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    High,
+    Normal,
+    Low,
+}
+
+// 截图等需要解码画面的功能使用的解码方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HwDecode {
+    // 依次尝试可用的硬件解码，都不可用时软解
+    Auto,
+    // Rockchip MPP，需要 rockchip 特性
+    Rkmpp,
+    Vaapi,
+    Software,
+}
+
+// 视频包的 dts 倒退或重复时的处理方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimestampPolicy {
+    // 把这个包和之后的包整体平移，接在上一个包后面
+    Offset,
+    Drop,
+    // 只记日志，原样交给封装器
+    Warn,
+}
+
+pub const SUBDIR_PLACEHOLDERS: &[&str] = &[
+    "{camera}", "{tag}", "{date}", "{year}", "{month}", "{day}", "{hour}",
+];
+
+#[derive(Clone, Debug)]
+pub struct CameraConfig {
+    pub name: String,
+    pub url: String,
+    // 备用地址（如经中继转发的同一路流），url 连不上时依次尝试，录像仍记在这个摄像头下
+    pub failover_urls: Vec<String>,
+    pub tags: Vec<String>,
+    // 所属租户（客户、楼宇等），只能写在摄像头行上
+    pub tenant: Option<String>,
+    // 环形缓冲：只保留最近 N 个分段
+    pub keep_segments: Option<usize>,
+    // 环形缓冲：只保留最近 M 分钟的分段
+    pub keep_minutes: Option<u64>,
+    // 每次切分后更新指向最新完成分段的 latest 链接
+    pub latest_link: bool,
+    // 统计 CSV 的写入周期，None 表示不写
+    pub stats_interval: Option<Duration>,
+    // 保留天数，由清理线程执行；摄像头行上的值覆盖全局值
+    pub retention_days: Option<u64>,
+    pub output_dir: PathBuf,
+    // output_dir 下的子目录模板，如 {camera}/{date}
+    pub subdir: String,
+    // 主目录写入失败时切换到的备用目录
+    pub fallback_dir: Option<PathBuf>,
+    // 每个分段同时写一份到该目录（如 NAS）
+    pub mirror_dir: Option<PathBuf>,
+    // 分段写完后用该密钥文件中的最后一个密钥加密；encryption_reencrypt 时后台把旧分段改用当前密钥
+    pub encryption_keys: Option<PathBuf>,
+    pub encryption_reencrypt: bool,
+    // 告警时执行的外部命令
+    pub alert_command: Option<String>,
+    // 严重告警同时弹出桌面通知
+    pub desktop_notify: bool,
+    // 回放地址模板（{start}/{end} 为时间占位符），用于断线后从摄像头 SD 卡补录
+    pub replay_url: Option<String>,
+    pub replay_time_format: String,
+    pub replay_utc: bool,
+    // 断线超过该时长才补录
+    pub backfill_min: Duration,
+    // 触发一次事件覆盖的时长
+    pub event_seconds: u64,
+    // 自适应分段：没有事件时的分段长度，以及事件期间的分段长度（设置后事件开始和结束时立即切分）
+    pub quiet_segment: Option<Duration>,
+    pub event_segment: Option<Duration>,
+    // 分段完成后执行的命令及其超时
+    pub on_segment: Option<String>,
+    pub hook_timeout: Duration,
+    pub priority: Priority,
+    // 向同机消费者转发视频包的 Unix socket
+    pub export_socket: Option<PathBuf>,
+    // 超过该时长读不到数据即断开重连，None 表示一直等待
+    pub read_timeout: Option<Duration>,
+    // 为每个分段记录关键帧位置
+    pub keyframe_index: bool,
+    // 长期预览存档：目录、取帧间隔、保留天数
+    pub preview_dir: Option<PathBuf>,
+    pub preview_interval: Duration,
+    pub preview_retention_days: Option<u64>,
+    // 同时录制的子码流地址
+    pub substream_url: Option<String>,
+    // 这是哪个摄像头的子码流
+    pub substream_of: Option<String>,
+    // 外接音频源及其输入格式（如 alsa），与视频封装进同一个分段
+    pub audio_url: Option<String>,
+    pub audio_format: Option<String>,
+    // 定期把最新画面截图为 <snapshot_dir>/<name>.jpg，可缩放到指定宽度
+    pub snapshot_dir: Option<PathBuf>,
+    pub snapshot_interval: Duration,
+    pub snapshot_width: Option<u32>,
+    // 每次连上摄像头后另存一张截图并登记到索引，需要 snapshot_dir
+    pub connect_snapshot: bool,
+    // 解码方式及 VAAPI 设备
+    pub hw_decode: HwDecode,
+    pub hw_device: String,
+    // 重连时沿用上次的流参数，不再探测
+    pub reuse_stream_info: bool,
+    // 连不上超过该时长告警 down；窗口内断线达到次数告警 flapping，None 表示不告警
+    pub down_alert: Option<Duration>,
+    pub flap_threshold: Option<usize>,
+    pub flap_window: Duration,
+    // 连不上超过该时长后自动停用，None 表示一直重试
+    pub disable_after: Option<Duration>,
+    // 打开流时探测流信息的上限；摄像头的编码参数在 SDP 里给全时，很少的数据就够了
+    pub probesize: Option<u64>,
+    pub analyzeduration: Option<Duration>,
+    // 断线后在该时长内重连成功时继续写入原分段，None 表示总是新建分段
+    pub continue_grace: Option<Duration>,
+    // 墙上时间与单调时钟相差超过该值时视为系统时钟跳变（校时、休眠），立即切分并改正时间
+    pub clock_jump: Option<Duration>,
+    pub timestamp_policy: TimestampPolicy,
+    // 裸流分段的写入队列上限（包数、字节数），都为 None 时直接在读流线程里写入；
+    // 队列满时丢弃最旧的包，write_queue_block 时等待写入跟上
+    pub write_queue_packets: Option<usize>,
+    pub write_queue_bytes: Option<usize>,
+    pub write_queue_block: bool,
+    // 事件片段的输出目录，None 表示不生成；片段从事件前 clip_pre_seconds 秒开始
+    pub clip_dir: Option<PathBuf>,
+    pub clip_pre_seconds: u64,
+    // 通知里附带的片段链接，{file} 替换为片段文件名
+    pub clip_url: Option<String>,
+    // 片段精确剪裁到请求的时刻：首尾两个 GOP 重新编码，中间仍然直接复制
+    pub clip_exact: bool,
+    // 设置后 HTTP 接口访问该摄像头需要带上这个令牌，通常按租户设置
+    pub api_token: Option<String>,
+    // 上传完成分段的命令，分段路径为 $1 和 SEGMENT_PATH
+    pub upload_command: Option<String>,
+    // 只在该时段内开始上传（开始晚于结束表示跨午夜），以及上传的平均带宽上限
+    pub upload_window: Option<(NaiveTime, NaiveTime)>,
+    // 按需摄像头：平时不连接，被触发后录制 on_demand_seconds，或在 on_demand_schedule 的时段内录制
+    pub on_demand: bool,
+    pub on_demand_seconds: u64,
+    pub on_demand_schedule: Vec<(NaiveTime, NaiveTime)>,
+    // 摄像头的 ONVIF 事件服务地址，订阅 onvif_topics 中的主题作为事件触发；账号缺省取自 RTSP 地址
+    pub onvif_url: Option<String>,
+    pub onvif_topics: Vec<String>,
+    pub upload_max_kbps: Option<u64>,
+    // 某小时的录像覆盖率低于该百分比时告警
+    pub coverage_alert: Option<f64>,
+    // 每月可用率的目标百分比，报告中注明是否达标
+    pub sla_target: Option<f64>,
+    // 期望的最低码率和帧率，统计窗口内低于它们时告警，rate_reconnect 时还会重连
+    pub min_bitrate_kbps: Option<u64>,
+    // 预计的码率，启动前的磁盘吞吐检查按此估算所需的写入速度
+    pub expected_bitrate_kbps: u64,
+    pub min_fps: Option<f64>,
+    pub rate_window: Duration,
+    pub rate_reconnect: bool,
+    // opt.<name>=<value> 传给输入的 ffmpeg AVOption
+    pub avoptions: Vec<(String, String)>,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            name: String::new(),
+            url: String::new(),
+            failover_urls: Vec::new(),
+            tags: Vec::new(),
+            tenant: None,
+            keep_segments: None,
+            keep_minutes: None,
+            latest_link: false,
+            stats_interval: None,
+            retention_days: None,
+            output_dir: PathBuf::from("video"),
+            subdir: String::new(),
+            fallback_dir: None,
+            mirror_dir: None,
+            encryption_keys: None,
+            encryption_reencrypt: false,
+            alert_command: None,
+            desktop_notify: false,
+            replay_url: None,
+            replay_time_format: "%Y%m%dT%H%M%SZ".to_string(),
+            replay_utc: true,
+            backfill_min: Duration::from_secs(30),
+            event_seconds: 60,
+            quiet_segment: None,
+            event_segment: None,
+            on_segment: None,
+            hook_timeout: Duration::from_secs(300),
+            priority: Priority::Normal,
+            export_socket: None,
+            read_timeout: Some(Duration::from_secs(10)),
+            keyframe_index: false,
+            preview_dir: None,
+            preview_interval: Duration::from_secs(10),
+            preview_retention_days: Some(180),
+            substream_url: None,
+            substream_of: None,
+            audio_url: None,
+            audio_format: None,
+            snapshot_dir: None,
+            snapshot_interval: Duration::from_secs(60),
+            snapshot_width: None,
+            connect_snapshot: false,
+            hw_decode: HwDecode::Auto,
+            hw_device: "/dev/dri/renderD128".to_string(),
+            reuse_stream_info: true,
+            down_alert: Some(Duration::from_secs(300)),
+            flap_threshold: Some(5),
+            flap_window: Duration::from_secs(600),
+            disable_after: None,
+            probesize: Some(500_000),
+            analyzeduration: Some(Duration::from_millis(1000)),
+            continue_grace: None,
+            clock_jump: Some(Duration::from_secs(5)),
+            timestamp_policy: TimestampPolicy::Offset,
+            write_queue_packets: None,
+            write_queue_bytes: None,
+            write_queue_block: false,
+            clip_dir: None,
+            clip_pre_seconds: 10,
+            clip_url: None,
+            clip_exact: false,
+            api_token: None,
+            coverage_alert: None,
+            sla_target: None,
+            upload_command: None,
+            upload_window: None,
+            on_demand: false,
+            on_demand_seconds: 300,
+            on_demand_schedule: Vec::new(),
+            onvif_url: None,
+            onvif_topics: ["Motion", "Tamper", "GlobalSceneChange", "LineDetector"]
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            upload_max_kbps: None,
+            min_bitrate_kbps: None,
+            expected_bitrate_kbps: 4000,
+            min_fps: None,
+            rate_window: Duration::from_secs(60),
+            rate_reconnect: false,
+            avoptions: Vec::new(),
+        }
+    }
+}
+
+impl CameraConfig {
+    // 选择器：摄像头名，@tag 表示带该标签的所有摄像头，%tenant 表示该租户的所有摄像头；
+    // 主码流的名字也选中其子码流
+    pub fn matches(&self, selector: &str) -> bool {
+        if let Some(tenant) = selector.strip_prefix('%') {
+            return self.tenant.as_deref() == Some(tenant);
+        }
+        match selector.strip_prefix('@') {
+            Some(tag) => self.tags.iter().any(|t| t == tag),
+            None => self.name == selector || self.substream_of.as_deref() == Some(selector),
+        }
+    }
+
+    // 依次尝试的地址：url 和各个备用地址
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str()).chain(self.failover_urls.iter().map(String::as_str))
+    }
+
+    // 子码流作为一个独立录制的摄像头 <name>_sub，共用主码流的索引；
+    // 不做补录、转发、预览存档，也不接外接音频
+    fn substream(&self) -> Option<CameraConfig> {
+        let url = self.substream_url.clone()?;
+        Some(CameraConfig {
+            name: format!("{}_sub", self.name),
+            url,
+            failover_urls: Vec::new(),
+            substream_url: None,
+            substream_of: Some(self.name.clone()),
+            replay_url: None,
+            export_socket: None,
+            preview_dir: None,
+            audio_url: None,
+            ..self.clone()
+        })
+    }
+
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "name" => self.name = value.to_string(),
+            "tenant" => self.tenant = (!value.is_empty()).then(|| value.to_string()),
+            "tags" => {
+                self.tags = value
+                    .split(',')
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "keep_segments" => {
+                let n = parse_number(key, value)?;
+                if n == 0 {
+                    return Err("keep_segments must be at least 1".to_string());
+                }
+                self.keep_segments = Some(n as usize);
+            }
+            "keep_minutes" => self.keep_minutes = Some(parse_number(key, value)?),
+            "latest_link" => self.latest_link = parse_bool(key, value)?,
+            "stats_seconds" => {
+                let secs = parse_number(key, value)?;
+                self.stats_interval = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            "retention_days" => {
+                let days = parse_number(key, value)?;
+                self.retention_days = (days > 0).then_some(days);
+            }
+            "output_dir" => self.output_dir = PathBuf::from(value),
+            "subdir" => {
+                let mut rest = value.to_string();
+                for placeholder in SUBDIR_PLACEHOLDERS {
+                    rest = rest.replace(placeholder, "");
+                }
+                if rest.contains('{') || rest.contains('}') {
+                    return Err(format!("Unknown placeholder in subdir '{}'", value));
+                }
+                self.subdir = value.to_string();
+            }
+            "fallback_dir" => self.fallback_dir = Some(PathBuf::from(value)),
+            "mirror_dir" => self.mirror_dir = Some(PathBuf::from(value)),
+            "encryption_keys" => {
+                self.encryption_keys = (!value.is_empty()).then(|| PathBuf::from(value))
+            }
+            "encryption_reencrypt" => self.encryption_reencrypt = parse_bool(key, value)?,
+            "alert_command" => self.alert_command = Some(value.to_string()),
+            "desktop_notify" => self.desktop_notify = parse_bool(key, value)?,
+            "replay_url" => self.replay_url = Some(value.to_string()),
+            "replay_time_format" => self.replay_time_format = value.to_string(),
+            "replay_utc" => self.replay_utc = parse_bool(key, value)?,
+            "backfill_min_seconds" => {
+                self.backfill_min = Duration::from_secs(parse_number(key, value)?)
+            }
+            "event_seconds" => self.event_seconds = parse_number(key, value)?,
+            "quiet_segment_minutes" => {
+                let minutes = parse_number(key, value)?;
+                self.quiet_segment = (minutes > 0).then(|| Duration::from_secs(minutes * 60));
+            }
+            "event_segment_seconds" => {
+                let secs = parse_number(key, value)?;
+                self.event_segment = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            "clip_dir" => self.clip_dir = Some(PathBuf::from(value)),
+            "clip_pre_seconds" => self.clip_pre_seconds = parse_number(key, value)?,
+            "clip_url" => self.clip_url = Some(value.to_string()),
+            "clip_exact" => self.clip_exact = parse_bool(key, value)?,
+            "api_token" => self.api_token = (!value.is_empty()).then(|| value.to_string()),
+            "upload_command" => self.upload_command = Some(value.to_string()),
+            "upload_window" => {
+                let window = value.split_once('-').and_then(|(from, to)| {
+                    let from = NaiveTime::parse_from_str(from.trim(), "%H:%M").ok()?;
+                    let to = NaiveTime::parse_from_str(to.trim(), "%H:%M").ok()?;
+                    (from != to).then_some((from, to))
+                });
+                self.upload_window = match (value, window) {
+                    ("", _) => None,
+                    (_, Some(window)) => Some(window),
+                    _ => {
+                        return Err(format!(
+                            "Invalid value for {}: '{}', expected HH:MM-HH:MM",
+                            key, value
+                        ))
+                    }
+                };
+            }
+            "on_demand" => self.on_demand = parse_bool(key, value)?,
+            "on_demand_seconds" => self.on_demand_seconds = parse_number(key, value)?,
+            "on_demand_schedule" => {
+                let mut schedule = Vec::new();
+                for window in value.split(',').filter(|w| !w.trim().is_empty()) {
+                    let parsed = window.split_once('-').and_then(|(from, to)| {
+                        let from = NaiveTime::parse_from_str(from.trim(), "%H:%M").ok()?;
+                        let to = NaiveTime::parse_from_str(to.trim(), "%H:%M").ok()?;
+                        (from != to).then_some((from, to))
+                    });
+                    match parsed {
+                        Some(window) => schedule.push(window),
+                        None => {
+                            return Err(format!(
+                                "Invalid value for {}: '{}', expected HH:MM-HH:MM[,HH:MM-HH:MM...]",
+                                key, value
+                            ))
+                        }
+                    }
+                }
+                self.on_demand_schedule = schedule;
+            }
+            "onvif_url" => self.onvif_url = (!value.is_empty()).then(|| value.to_string()),
+            "onvif_topics" => {
+                self.onvif_topics = value
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            }
+            "upload_max_mbps" => {
+                let mbps: f64 = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}: '{}'", key, value))?;
+                self.upload_max_kbps = (mbps > 0.0).then(|| (mbps * 1000.0).max(1.0) as u64);
+            }
+            "coverage_alert_percent" => {
+                let percent: f64 = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}: '{}'", key, value))?;
+                self.coverage_alert = (percent > 0.0).then_some(percent);
+            }
+            "sla_target_percent" => {
+                let percent: f64 = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}: '{}'", key, value))?;
+                self.sla_target = (percent > 0.0).then_some(percent);
+            }
+            "expected_bitrate_kbps" => self.expected_bitrate_kbps = parse_number(key, value)?,
+            "min_bitrate_kbps" => {
+                let kbps = parse_number(key, value)?;
+                self.min_bitrate_kbps = (kbps > 0).then_some(kbps);
+            }
+            "min_fps" => {
+                let fps: f64 = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}: '{}'", key, value))?;
+                self.min_fps = (fps > 0.0).then_some(fps);
+            }
+            "rate_window_seconds" => {
+                let secs = parse_number(key, value)?;
+                if secs == 0 {
+                    return Err("rate_window_seconds must be at least 1".to_string());
+                }
+                self.rate_window = Duration::from_secs(secs);
+            }
+            "rate_action" => {
+                self.rate_reconnect = match value {
+                    "alert" => false,
+                    "reconnect" => true,
+                    _ => return Err(format!("Invalid value for {}: '{}'", key, value)),
+                }
+            }
+            "on_segment" => self.on_segment = Some(value.to_string()),
+            "hook_timeout_seconds" => {
+                self.hook_timeout = Duration::from_secs(parse_number(key, value)?)
+            }
+            "substream_url" => self.substream_url = Some(value.to_string()),
+            "failover_urls" => {
+                self.failover_urls = value
+                    .split(',')
+                    .filter(|url| !url.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "audio_url" => self.audio_url = Some(value.to_string()),
+            "audio_format" => self.audio_format = Some(value.to_string()),
+            "snapshot_dir" => self.snapshot_dir = Some(PathBuf::from(value)),
+            "snapshot_interval_seconds" => {
+                self.snapshot_interval = Duration::from_secs(parse_number(key, value)?)
+            }
+            "connect_snapshot" => self.connect_snapshot = parse_bool(key, value)?,
+            "snapshot_width" => {
+                let width = parse_number(key, value)?;
+                self.snapshot_width = (width > 0).then_some(width as u32);
+            }
+            "hw_decode" => {
+                self.hw_decode = match value {
+                    "auto" => HwDecode::Auto,
+                    "rkmpp" if cfg!(feature = "rockchip") => HwDecode::Rkmpp,
+                    "rkmpp" => {
+                        return Err("hw_decode=rkmpp requires the rockchip feature".to_string())
+                    }
+                    "vaapi" => HwDecode::Vaapi,
+                    "none" => HwDecode::Software,
+                    _ => return Err(format!("Invalid value for {}: '{}'", key, value)),
+                }
+            }
+            "hw_device" => self.hw_device = value.to_string(),
+            "reuse_stream_info" => self.reuse_stream_info = parse_bool(key, value)?,
+            "down_alert_seconds" => {
+                let secs = parse_number(key, value)?;
+                self.down_alert = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            "flap_threshold" => {
+                let count = parse_number(key, value)?;
+                self.flap_threshold = (count > 0).then_some(count as usize);
+            }
+            "disable_after_hours" => {
+                let hours = parse_number(key, value)?;
+                self.disable_after = (hours > 0).then(|| Duration::from_secs(hours * 3600));
+            }
+            "probesize" => {
+                let bytes = parse_number(key, value)?;
+                self.probesize = (bytes > 0).then_some(bytes);
+            }
+            "analyzeduration_ms" => {
+                let ms = parse_number(key, value)?;
+                self.analyzeduration = (ms > 0).then(|| Duration::from_millis(ms));
+            }
+            "continue_grace_seconds" => {
+                let secs = parse_number(key, value)?;
+                self.continue_grace = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            "clock_jump_seconds" => {
+                let secs = parse_number(key, value)?;
+                self.clock_jump = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            "timestamp_policy" => {
+                self.timestamp_policy = match value {
+                    "offset" => TimestampPolicy::Offset,
+                    "drop" => TimestampPolicy::Drop,
+                    "warn" => TimestampPolicy::Warn,
+                    _ => return Err(format!("Invalid value for {}: '{}'", key, value)),
+                }
+            }
+            "write_queue_packets" => {
+                let packets = parse_number(key, value)? as usize;
+                self.write_queue_packets = (packets > 0).then_some(packets);
+            }
+            "write_queue_mb" => {
+                let mb = parse_number(key, value)? as usize;
+                self.write_queue_bytes = (mb > 0).then_some(mb * 1024 * 1024);
+            }
+            "write_queue_policy" => {
+                self.write_queue_block = match value {
+                    "drop_oldest" => false,
+                    "block" => true,
+                    _ => {
+                        return Err(format!(
+                            "Invalid write_queue_policy '{}', expected drop_oldest or block",
+                            value
+                        ))
+                    }
+                }
+            }
+            "flap_window_minutes" => {
+                self.flap_window = Duration::from_secs(parse_number(key, value)? * 60)
+            }
+            "preview_dir" => self.preview_dir = Some(PathBuf::from(value)),
+            "preview_interval_seconds" => {
+                self.preview_interval = Duration::from_secs(parse_number(key, value)?)
+            }
+            "preview_retention_days" => {
+                let days = parse_number(key, value)?;
+                self.preview_retention_days = (days > 0).then_some(days);
+            }
+            "keyframe_index" => self.keyframe_index = parse_bool(key, value)?,
+            "read_timeout_seconds" => {
+                let secs = parse_number(key, value)?;
+                self.read_timeout = (secs > 0).then(|| Duration::from_secs(secs));
+            }
+            "export_socket" => self.export_socket = Some(PathBuf::from(value)),
+            "priority" => {
+                self.priority = match value {
+                    "high" => Priority::High,
+                    "normal" => Priority::Normal,
+                    "low" => Priority::Low,
+                    _ => return Err(format!("Invalid value for {}: '{}'", key, value)),
+                }
+            }
+            _ => match key.strip_prefix("opt.") {
+                // 后出现的同名选项覆盖前面的，空值表示删除
+                Some(name) if !name.is_empty() => {
+                    self.avoptions.retain(|(k, _)| k != name);
+                    if !value.is_empty() {
+                        self.avoptions.push((name.to_string(), value.to_string()));
+                    }
+                }
+                _ => return Err(format!("Unknown option '{}'", key)),
+            },
+        }
+        Ok(())
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, String> {
+    let path = path.as_ref();
+    let (text, from) = read(path)?;
+    let mut config = parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    config.path = Some(path.to_path_buf());
+    // 旧版本的文件迁移后写回，原文件保留为 <path>.v<N>
+    if from < migrate::CURRENT_VERSION {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{}", from));
+        match fs::copy(path, &backup).and_then(|_| write(path, &text)) {
+            Ok(_) => info!(
+                "[Config] Migrated {} from version {} to {}, the old file is kept as {}",
+                path.display(),
+                from,
+                migrate::CURRENT_VERSION,
+                Path::new(&backup).display()
+            ),
+            Err(e) => error!("[Config] Failed to migrate {}: {}", path.display(), e),
+        }
+    }
+    Ok(config)
+}
+
+// 文件内容迁移到当前版本后的文本，以及文件原来的版本
+fn read(path: &Path) -> Result<(String, u32), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    migrate::migrate(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// 先写临时文件再改名，写到一半不会留下残缺的配置
+fn write(path: &Path, text: &str) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, text)?;
+    fs::rename(&tmp, path)
+}
+
+// 检查在配置文件末尾加上一行摄像头后是否有效，返回新增的摄像头（含子码流），不写入
+pub fn check_camera(path: &Path, line: &str) -> Result<Vec<CameraConfig>, String> {
+    if line.contains('\n') || !tokenize(line)?.first().is_some_and(|t| is_url(t)) {
+        return Err("Expected a single camera line: <url> key=value ...".to_string());
+    }
+    let (text, _) = read(path)?;
+    let existing = parse(&text)?.cameras;
+    let added = parse(&format!("{}\n{}\n", text.trim_end(), line))?;
+    Ok(added
+        .cameras
+        .into_iter()
+        .filter(|c| !existing.iter().any(|e| e.name == c.name))
+        .collect())
+}
+
+// 把一行摄像头追加到配置文件
+pub fn append_camera(path: &Path, line: &str) -> Result<(), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    write(path, &format!("{}\n{}\n", text.trim_end(), line))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// config export：迁移到当前版本并检查无误的配置文本，可直接拿到另一台机器上 import
+pub fn export<P: AsRef<Path>>(path: P) -> Result<String, String> {
+    let path = path.as_ref();
+    let (text, _) = read(path)?;
+    parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(text)
+}
+
+// config import：检查并迁移 from 后写到 to，to 原有的内容保留为 <to>.bak
+pub fn import<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<Config, String> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let (text, _) = read(from)?;
+    let config = parse(&text).map_err(|e| format!("{}: {}", from.display(), e))?;
+    if to.exists() {
+        let mut backup = to.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::copy(to, &backup).map_err(|e| format!("Failed to back up {}: {}", to.display(), e))?;
+    }
+    write(to, &text).map_err(|e| format!("Failed to write {}: {}", to.display(), e))?;
+    Ok(config)
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    let mut lines: Vec<(usize, Vec<String>)> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let tokens = tokenize(line).map_err(|e| format!("line {}: {}", n + 1, e))?;
+        if !tokens.is_empty() {
+            lines.push((n + 1, tokens));
+        }
+    }
+
+    // 先读取全局设置，这样全局行写在摄像头后面也同样生效
+    let mut config = Config {
+        cameras: Vec::new(),
+        ntp_server: None,
+        trigger_dir: None,
+        hook_concurrency: 2,
+        script: None,
+        min_free_mb: None,
+        max_load: None,
+        isolate: false,
+        worker_max_rss_mb: None,
+        log_format: LogFormat::Text,
+        otlp_endpoint: None,
+        otlp_service_name: "save_rtsp".to_string(),
+        http_listen: None,
+        cluster_dir: None,
+        node_name: None,
+        export_sign_command: None,
+        admin_token: None,
+        path: None,
+        storage_check: None,
+        max_write_latency: Duration::from_millis(1000),
+        preflight_mb: Some(32),
+        webhook_url: None,
+        mqtt_broker: None,
+        mqtt_topic: "save_rtsp".to_string(),
+        // 统计每个周期都有、日志太多，默认不转发
+        publish_events: [
+            "stream_state",
+            "segment_started",
+            "segment_finished",
+            "event",
+            "clip",
+            "coverage",
+            "alert",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect(),
+    };
+    let mut defaults = CameraConfig::default();
+    for (n, tokens) in lines
+        .iter()
+        .filter(|(_, t)| !is_url(&t[0]) && !is_tag(&t[0]) && !is_tenant(&t[0]))
+    {
+        let mut camera_options = Vec::new();
+        for token in tokens {
+            let (key, value) = split_option(token).map_err(|e| format!("line {}: {}", n, e))?;
+            if !config
+                .apply(key, value)
+                .map_err(|e| format!("line {}: {}", n, e))?
+            {
+                camera_options.push(token.clone());
+            }
+        }
+        apply_options(&mut defaults, *n, &camera_options, false)?;
+    }
+
+    let scopes: Vec<&(usize, Vec<String>)> = lines.iter().filter(|(_, t)| is_tag(&t[0])).collect();
+    let tenants: Vec<&(usize, Vec<String>)> =
+        lines.iter().filter(|(_, t)| is_tenant(&t[0])).collect();
+
+    for (n, tokens) in lines.iter().filter(|(_, t)| is_url(&t[0])) {
+        let mut camera = defaults.clone();
+        camera.url = tokens[0].clone();
+        camera.name = tokens[0].replace('/', "_");
+        // 优先级：全局 < 租户（%tenant 行） < 标签（@tag 行） < 摄像头行
+        apply_options(&mut camera, *n, &tokens[1..], true)?;
+        if let Some(tenant) = camera.tenant.clone() {
+            // 各租户的录像默认分目录存放，摄像头行或租户行可以另外指定
+            camera.output_dir = camera.output_dir.join(&tenant);
+            for (scope_line, scope) in &tenants {
+                if scope[0][1..] == tenant {
+                    apply_options(&mut camera, *scope_line, &scope[1..], false)?;
+                }
+            }
+        }
+        let tags = camera.tags.clone();
+        for (scope_line, scope) in &scopes {
+            if tags.iter().any(|tag| scope[0][1..] == *tag) {
+                apply_options(&mut camera, *scope_line, &scope[1..], false)?;
+            }
+        }
+        apply_options(&mut camera, *n, &tokens[1..], true)?;
+        let substream = camera.substream();
+        for camera in std::iter::once(camera).chain(substream) {
+            if config.cameras.iter().any(|c| c.name == camera.name) {
+                return Err(format!(
+                    "line {}: duplicate camera name '{}'",
+                    n, camera.name
+                ));
+            }
+            config.cameras.push(camera);
+        }
+    }
+
+    // 不同租户的录像不能写到同一个目录
+    for (i, a) in config.cameras.iter().enumerate() {
+        if let Some(b) = config.cameras[i + 1..]
+            .iter()
+            .find(|b| b.tenant != a.tenant && b.output_dir == a.output_dir)
+        {
+            return Err(format!(
+                "cameras '{}' and '{}' belong to different tenants but share output_dir {}",
+                a.name,
+                b.name,
+                a.output_dir.display()
+            ));
+        }
+    }
+
+    // 片段、HTTP 回放和关键帧索引都直接读分段文件，读不了加密后的分段
+    for camera in config
+        .cameras
+        .iter()
+        .filter(|c| c.encryption_keys.is_some())
+    {
+        let conflict = if camera.clip_dir.is_some() {
+            Some("clip_dir")
+        } else if camera.keyframe_index {
+            Some("keyframe_index")
+        } else if config.http_listen.is_some() {
+            Some("http_listen")
+        } else {
+            None
+        };
+        if let Some(option) = conflict {
+            return Err(format!(
+                "encryption_keys of camera '{}' cannot be combined with {}",
+                camera.name, option
+            ));
+        }
+    }
+
+    // 子进程各自录一路，无法作为一个节点参与分配
+    if config.isolate && config.cluster_dir.is_some() {
+        return Err("cluster_dir cannot be combined with isolation=process".to_string());
+    }
+
+    // 每个子进程都会去监听同一个地址
+    if config.isolate && config.http_listen.is_some() {
+        return Err("http_listen cannot be combined with isolation=process".to_string());
+    }
+
+    Ok(config)
+}
+
+// 按空白切分，双引号内的空白保留，引号本身去掉
+pub(crate) fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut in_quotes = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_token = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if in_quotes {
+        return Err("Unterminated quote".to_string());
+    }
+    if in_token {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn apply_options(
+    camera: &mut CameraConfig,
+    n: usize,
+    tokens: &[String],
+    camera_line: bool,
+) -> Result<(), String> {
+    for token in tokens {
+        let (key, value) = split_option(token).map_err(|e| format!("line {}: {}", n, e))?;
+        if !camera_line && (key == "name" || key == "tags" || key == "tenant") {
+            return Err(format!(
+                "line {}: '{}' is only valid on a camera line",
+                n, key
+            ));
+        }
+        camera
+            .apply(key, value)
+            .map_err(|e| format!("line {}: {}", n, e))?;
+    }
+    Ok(())
+}
+
+fn is_tag(token: &str) -> bool {
+    token.starts_with('@')
+}
+
+fn is_tenant(token: &str) -> bool {
+    token.starts_with('%')
+}
+
+pub(crate) fn is_url(token: &str) -> bool {
+    token.contains("://")
+}
+
+fn split_option(token: &str) -> Result<(&str, &str), String> {
+    token
+        .split_once('=')
+        .ok_or_else(|| format!("Expected key=value, got '{}'", token))
+}
+
+fn parse_number(key: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: '{}'", key, value))
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("Invalid value for {}: '{}'", key, value)),
+    }
+}
@@ -0,0 +1,219 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::CameraConfig;
+use crate::state::{self, State};
+
+// 资源压力下对一路流的限制，由 pressure 线程设置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Throttle {
+    Full,
+    // 只写关键帧
+    KeyframesOnly,
+    Paused,
+}
+
+// 每路流的运行时控制状态，在命令线程和录制线程之间共享
+pub struct StreamHandle {
+    pub camera: CameraConfig,
+    paused: AtomicBool,
+    throttle: AtomicU8,
+    // 当前连接是否已开始写入
+    connected: AtomicBool,
+    // 长时间连不上后自动停用，enable 命令恢复
+    disabled: AtomicBool,
+    // 集群模式下由其他节点录制
+    standby: AtomicBool,
+    // 控制台的 stop 命令：本次运行不再录制
+    stopped: AtomicBool,
+    // 控制台的 rotate 命令：在下一个关键帧开始新分段
+    rotate: AtomicBool,
+    // 当前连接使用的地址，可能是备用地址
+    source: Mutex<Option<String>>,
+    // 按需摄像头被触发后录制到这个时刻
+    demanded_until: Mutex<Option<Instant>>,
+    // 持久化的运行时状态，管道模式等不保存状态时为 None
+    state: Option<Mutex<State>>,
+}
+
+impl StreamHandle {
+    pub fn new(camera: CameraConfig) -> Arc<StreamHandle> {
+        Arc::new(StreamHandle {
+            camera,
+            paused: AtomicBool::new(false),
+            throttle: AtomicU8::new(Throttle::Full as u8),
+            connected: AtomicBool::new(false),
+            disabled: AtomicBool::new(false),
+            standby: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            rotate: AtomicBool::new(false),
+            source: Mutex::new(None),
+            demanded_until: Mutex::new(None),
+            state: None,
+        })
+    }
+
+    // 恢复上次运行保存的状态（暂停、停用等），之后的变化都会写回状态文件
+    pub fn restore(camera: CameraConfig) -> Arc<StreamHandle> {
+        let saved = state::load(&camera).unwrap_or_else(|e| {
+            error!("Failed to read state of {}: {}", camera.name, e);
+            State::default()
+        });
+        Arc::new(StreamHandle {
+            camera,
+            paused: AtomicBool::new(saved.paused),
+            throttle: AtomicU8::new(Throttle::Full as u8),
+            connected: AtomicBool::new(false),
+            disabled: AtomicBool::new(saved.disabled),
+            standby: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            rotate: AtomicBool::new(false),
+            source: Mutex::new(None),
+            demanded_until: Mutex::new(None),
+            state: Some(Mutex::new(saved)),
+        })
+    }
+
+    // 是否保存运行时状态；管道模式和临时的流不保存
+    pub(crate) fn keeps_state(&self) -> bool {
+        self.state.is_some()
+    }
+
+    pub(crate) fn saved_state(&self) -> Option<State> {
+        self.state
+            .as_ref()
+            .map(|state| state.lock().unwrap().clone())
+    }
+
+    // 修改并保存状态，不保存状态时什么也不做
+    pub(crate) fn update_state<F: FnOnce(&mut State)>(&self, update: F) {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return,
+        };
+        let mut state = state.lock().unwrap();
+        update(&mut state);
+        if let Err(e) = state::save(&self.camera, &state) {
+            error!("Failed to save state of {}: {}", self.camera.name, e);
+        }
+    }
+
+    // 手动暂停、因资源压力暂停、已停用、已停止、分给了集群中的其他节点或按需摄像头未被触发
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+            || self.is_stopped()
+            || self.throttle() == Throttle::Paused
+            || self.is_disabled()
+            || self.standby.load(Ordering::SeqCst)
+            || !self.is_demanded()
+    }
+
+    // 不是按需摄像头，或者已被触发、在计划的时段内
+    pub fn is_demanded(&self) -> bool {
+        let camera = &self.camera;
+        if !camera.on_demand {
+            return true;
+        }
+        if self
+            .demanded_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| until > Instant::now())
+        {
+            return true;
+        }
+        let now = chrono::Local::now().naive_local().time();
+        camera.on_demand_schedule.iter().any(|&(from, to)| {
+            if from < to {
+                now >= from && now < to
+            } else {
+                now >= from || now < to
+            }
+        })
+    }
+
+    // 按需摄像头从现在起至少录制 length，返回之前是否已在录制
+    pub fn activate(&self, length: Duration) -> bool {
+        let was_demanded = self.is_demanded();
+        let until = Instant::now() + length;
+        let mut demanded_until = self.demanded_until.lock().unwrap();
+        if demanded_until.is_none_or(|current| current < until) {
+            *demanded_until = Some(until);
+        }
+        was_demanded
+    }
+
+    // 返回之前是否为待命
+    pub fn set_standby(&self, standby: bool) -> bool {
+        self.standby.swap(standby, Ordering::SeqCst)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    // 返回之前是否已停止
+    pub fn stop(&self) -> bool {
+        self.stopped.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn request_rotate(&self) {
+        self.rotate.store(true, Ordering::SeqCst);
+    }
+
+    // 是否要求了切分，取出后清除
+    pub fn take_rotate(&self) -> bool {
+        self.rotate.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::SeqCst)
+    }
+
+    // 返回之前是否已停用
+    pub fn set_disabled(&self, disabled: bool) -> bool {
+        let previous = self.disabled.swap(disabled, Ordering::SeqCst);
+        if previous != disabled {
+            self.update_state(|state| state.disabled = disabled);
+        }
+        previous
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        if self.paused.swap(paused, Ordering::SeqCst) != paused {
+            self.update_state(|state| state.paused = paused);
+        }
+    }
+
+    pub fn source(&self) -> Option<String> {
+        self.source.lock().unwrap().clone()
+    }
+
+    pub fn set_source(&self, url: &str) {
+        *self.source.lock().unwrap() = Some(url.to_string());
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::SeqCst);
+    }
+
+    pub fn throttle(&self) -> Throttle {
+        match self.throttle.load(Ordering::SeqCst) {
+            1 => Throttle::KeyframesOnly,
+            2 => Throttle::Paused,
+            _ => Throttle::Full,
+        }
+    }
+
+    // 返回之前的限制
+    pub fn set_throttle(&self, throttle: Throttle) -> Throttle {
+        let previous = self.throttle();
+        self.throttle.store(throttle as u8, Ordering::SeqCst);
+        previous
+    }
+}
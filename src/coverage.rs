@@ -0,0 +1,133 @@
+// 录像覆盖率：按索引中分段的起止时间统计每小时实际录到的比例。
+// 每个整点过后（等最后一个分段写完）计算上一小时，交给插件作为指标，低于阈值时告警
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Timelike};
+
+use crate::alert;
+use crate::bus::{self, Event};
+use crate::config::CameraConfig;
+use crate::control::StreamHandle;
+use crate::index::{self, Record};
+use crate::segment;
+
+// registry 包括运行中新增的摄像头，每次检查时重新读取
+pub fn spawn(
+    registry: Arc<Mutex<Vec<Arc<StreamHandle>>>>,
+    running: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        // 启动前的小时没有在录，不统计
+        let mut next = hour_start(chrono::Local::now().naive_local()) + chrono::Duration::hours(1);
+        while running.load(Ordering::SeqCst) {
+            let streams = registry.lock().unwrap().clone();
+            // 整点过后等最长的分段再加一分钟，跨整点的分段此时已经写完并记入索引
+            let settle = streams
+                .iter()
+                .map(|h| segment::quiet_length(&h.camera))
+                .max()
+                .unwrap_or_default()
+                + Duration::from_secs(60);
+            let due =
+                next + chrono::Duration::hours(1) + chrono::Duration::from_std(settle).unwrap();
+            if chrono::Local::now().naive_local() >= due {
+                for handle in &streams {
+                    // 暂停和停用的摄像头已经单独告警过
+                    if !handle.is_paused() {
+                        check(&handle.camera, next);
+                    }
+                }
+                next += chrono::Duration::hours(1);
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    })
+}
+
+fn check(camera: &CameraConfig, hour: NaiveDateTime) {
+    let end = hour + chrono::Duration::hours(1);
+    let percent = match percent(camera, hour, end) {
+        Ok(percent) => percent,
+        Err(e) => {
+            error!("[Coverage] Failed to read index of {}: {}", camera.name, e);
+            return;
+        }
+    };
+    info!(
+        "[Coverage] {} recorded {:.1}% of {} - {}",
+        camera.name,
+        percent,
+        hour.format("%Y-%m-%d %H:%M"),
+        end.format("%H:%M")
+    );
+    bus::publish(Event::Coverage {
+        camera: camera.name.clone(),
+        hour,
+        percent,
+    });
+    if camera
+        .coverage_alert
+        .is_some_and(|threshold| percent < threshold)
+    {
+        alert::raise(
+            camera,
+            "coverage",
+            &format!(
+                "Recorded only {:.1}% of {} - {}",
+                percent,
+                hour.format("%Y-%m-%d %H:%M"),
+                end.format("%H:%M")
+            ),
+        );
+    }
+}
+
+// [from, to) 内有录像的百分比
+pub fn percent(camera: &CameraConfig, from: NaiveDateTime, to: NaiveDateTime) -> io::Result<f64> {
+    let total = (to - from).num_milliseconds();
+    if total <= 0 {
+        return Ok(0.0);
+    }
+    let covered = covered(camera, from, to)?.num_milliseconds();
+    Ok(covered as f64 * 100.0 / total as f64)
+}
+
+// [from, to) 内被分段覆盖的总时长，重叠的分段（如备用目录与主目录）只算一次
+pub fn covered(
+    camera: &CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> io::Result<chrono::Duration> {
+    let mut spans: Vec<(NaiveDateTime, NaiveDateTime)> = index::load(camera)?
+        .into_iter()
+        .filter_map(|record| match record {
+            Record::Segment {
+                start,
+                end,
+                substream,
+                ..
+            } if substream == camera.substream_of.is_some() && start < to && end > from => {
+                Some((start.max(from), end.min(to)))
+            }
+            _ => None,
+        })
+        .collect();
+    spans.sort();
+    let mut covered = chrono::Duration::zero();
+    let mut reached = from;
+    for (start, end) in spans {
+        if end > reached {
+            covered = covered + (end - start.max(reached));
+            reached = end;
+        }
+    }
+    Ok(covered)
+}
+
+pub fn hour_start(time: NaiveDateTime) -> NaiveDateTime {
+    time.date().and_hms_opt(time.hour(), 0, 0).unwrap()
+}
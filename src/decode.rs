@@ -0,0 +1,142 @@
+// 解码关键帧，供截图等需要画面的功能使用。尽量用硬件解码：一台机器录十几路 H.265 时，
+// 软解光是截图就能占满 CPU。硬件不可用时自动退回软解
+use std::ffi::CString;
+use std::ptr;
+
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::codec::context::Context;
+use ffmpeg::ffi;
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling::{self, Flags};
+use ffmpeg::{frame, Packet};
+
+use crate::config::{CameraConfig, HwDecode};
+use crate::mux::StreamInfo;
+#[cfg(feature = "rockchip")]
+use crate::rockchip;
+
+pub struct Decoder {
+    decoder: ffmpeg::decoder::Video,
+    // 实际使用的解码方式，用于日志
+    pub backend: &'static str,
+}
+
+impl Decoder {
+    pub fn new(id: usize, camera: &CameraConfig, info: &StreamInfo) -> Result<Decoder, String> {
+        // 按顺序尝试的硬件解码方式
+        let backends: &[&'static str] = match camera.hw_decode {
+            HwDecode::Auto => &["rkmpp", "vaapi"],
+            HwDecode::Rkmpp => &["rkmpp"],
+            HwDecode::Vaapi => &["vaapi"],
+            HwDecode::Software => &[],
+        };
+        for &backend in backends {
+            let opened = match backend {
+                #[cfg(feature = "rockchip")]
+                "rkmpp" => rockchip::open_decoder(info),
+                #[cfg(not(feature = "rockchip"))]
+                "rkmpp" if camera.hw_decode == HwDecode::Auto => continue,
+                "vaapi" => open_vaapi(info, &camera.hw_device),
+                _ => Err("not supported by this build".to_string()),
+            };
+            match opened {
+                Ok(decoder) => return Ok(Decoder { decoder, backend }),
+                // 明确指定了硬件解码时也只是告警，截图不能因此中断
+                Err(e) => error!(
+                    "[Stream {}] {} decoding unavailable ({}), falling back",
+                    id, backend, e
+                ),
+            }
+        }
+        let decoder = Context::from_parameters(info.parameters.clone())
+            .and_then(|context| context.decoder().video())
+            .map_err(|e| format!("Failed to open decoder: {}", e))?;
+        Ok(Decoder {
+            decoder,
+            backend: "software",
+        })
+    }
+
+    // 单独解码一个关键帧，缩放到 width（等比，不放大）并转换为 format 的内存帧
+    pub fn picture(
+        &mut self,
+        packet: &Packet,
+        width: Option<u32>,
+        format: Pixel,
+    ) -> Result<frame::Video, String> {
+        let mut decoded = frame::Video::empty();
+        let result = self
+            .decoder
+            .send_packet(packet)
+            .and_then(|_| self.decoder.send_eof())
+            .and_then(|_| self.decoder.receive_frame(&mut decoded));
+        // 清空解码器以便接收下一个关键帧
+        self.decoder.flush();
+        result.map_err(|e| format!("Failed to decode frame: {}", e))?;
+
+        let (width, height) = match width {
+            Some(width) if width < decoded.width() => (
+                width,
+                (decoded.height() as u64 * width as u64 / decoded.width() as u64) as u32 & !1,
+            ),
+            _ => (decoded.width(), decoded.height()),
+        };
+        let downloaded = download(decoded, width, height)?;
+        let mut converted = frame::Video::empty();
+        scaling::Context::get(
+            downloaded.format(),
+            downloaded.width(),
+            downloaded.height(),
+            format,
+            width,
+            height,
+            Flags::BILINEAR,
+        )
+        .and_then(|mut scaler| scaler.run(&downloaded, &mut converted))
+        .map_err(|e| format!("Failed to scale frame: {}", e))?;
+        Ok(converted)
+    }
+}
+
+// 硬件帧下载到内存；MPP 解码的帧在 RGA 可用时先由 RGA 缩放，省掉大图的下载和 CPU 缩放
+#[cfg_attr(not(feature = "rockchip"), allow(unused_variables))]
+fn download(decoded: frame::Video, width: u32, height: u32) -> Result<frame::Video, String> {
+    if unsafe { (*decoded.as_ptr()).hw_frames_ctx.is_null() } {
+        return Ok(decoded);
+    }
+    #[cfg(feature = "rockchip")]
+    if decoded.format() == Pixel::DRM_PRIME && rockchip::rga_available() {
+        return rockchip::scale(&decoded, width, height);
+    }
+    let mut downloaded = frame::Video::empty();
+    match unsafe { ffi::av_hwframe_transfer_data(downloaded.as_mut_ptr(), decoded.as_ptr(), 0) } {
+        code if code >= 0 => Ok(downloaded),
+        code => Err(format!(
+            "Failed to download frame: {}",
+            ffmpeg::Error::from(code)
+        )),
+    }
+}
+
+fn open_vaapi(info: &StreamInfo, device: &str) -> Result<ffmpeg::decoder::Video, String> {
+    let path = CString::new(device).map_err(|_| format!("Invalid device '{}'", device))?;
+    let mut context =
+        Context::from_parameters(info.parameters.clone()).map_err(|e| e.to_string())?;
+    unsafe {
+        let mut device_context = ptr::null_mut();
+        let code = ffi::av_hwdevice_ctx_create(
+            &mut device_context,
+            ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+            path.as_ptr(),
+            ptr::null_mut(),
+            0,
+        );
+        if code < 0 {
+            return Err(format!("{}: {}", device, ffmpeg::Error::from(code)));
+        }
+        // 解码器上下文接管该引用，关闭时释放
+        (*context.as_mut_ptr()).hw_device_ctx = device_context;
+    }
+    context.decoder().video().map_err(|e| e.to_string())
+}
@@ -0,0 +1,185 @@
+// 删除指定的分段或时间段的录像：只删除索引里已完成的分段（正在写的分段不会被删），
+// 与保全重叠时整个请求拒绝，删完从索引中去掉对应的记录，镜像副本一起删除。
+// 每删一个分段在 <output_dir>/<name>.audit 追加一行 "时间\tdelete\t操作人\t路径\t开始\t结束\t字节数\t原因"，
+// 留下谁在什么时候因为什么删了哪段录像
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+
+use crate::config::CameraConfig;
+use crate::index::{self, Record};
+use crate::segment;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub enum Target<'a> {
+    // 与 [from, to) 重叠的分段
+    Range(NaiveDateTime, NaiveDateTime),
+    // 索引中路径为该值的一个分段
+    Segment(&'a Path),
+}
+
+pub struct Deleted {
+    pub segments: usize,
+    pub bytes: u64,
+}
+
+// 删除失败的原因：请求本身有误、与保全冲突，或读写文件出错
+pub enum Error {
+    Invalid(String),
+    Held(String),
+    Failed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Invalid(message) | Error::Held(message) | Error::Failed(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+pub fn audit_path(camera: &CameraConfig) -> PathBuf {
+    let name = camera.substream_of.as_ref().unwrap_or(&camera.name);
+    camera.output_dir.join(format!("{}.audit", name))
+}
+
+pub fn delete(
+    camera: &CameraConfig,
+    target: Target,
+    user: &str,
+    reason: &str,
+) -> Result<Deleted, Error> {
+    if reason.trim().is_empty() {
+        return Err(Error::Invalid(
+            "A reason is required to delete recordings".to_string(),
+        ));
+    }
+    let records =
+        index::load(camera).map_err(|e| Error::Failed(format!("Failed to read index: {}", e)))?;
+    let selected: Vec<(NaiveDateTime, NaiveDateTime, u64, PathBuf)> = records
+        .into_iter()
+        .filter_map(|record| match record {
+            Record::Segment {
+                start,
+                end,
+                bytes,
+                path,
+                ..
+            } => Some((start, end, bytes, path)),
+            _ => None,
+        })
+        .filter(|(start, end, _, path)| match &target {
+            Target::Range(from, to) => start < to && end > from,
+            Target::Segment(segment) => path == segment,
+        })
+        .collect();
+    if selected.is_empty() {
+        return Err(Error::Invalid(match target {
+            Target::Range(from, to) => format!(
+                "No finished segments of {} between {} and {}",
+                camera.name, from, to
+            ),
+            Target::Segment(segment) => {
+                format!(
+                    "{} is not in the index of {}",
+                    segment.display(),
+                    camera.name
+                )
+            }
+        }));
+    }
+    let holds =
+        index::holds(camera).map_err(|e| Error::Failed(format!("Failed to read holds: {}", e)))?;
+    let held = selected
+        .iter()
+        .filter(|(start, end, ..)| index::is_held(&holds, *start, *end))
+        .count();
+    if held > 0 {
+        return Err(Error::Held(format!(
+            "{} of {} segment(s) are under a hold; release it first",
+            held,
+            selected.len()
+        )));
+    }
+
+    let mut deleted = Deleted {
+        segments: 0,
+        bytes: 0,
+    };
+    let mut removed = Vec::new();
+    let mut failed = None;
+    for (start, end, bytes, path) in &selected {
+        if let Err(e) = remove(camera, path) {
+            error!("[Delete] Failed to remove {}: {}", path.display(), e);
+            failed = Some(format!("Failed to remove {}: {}", path.display(), e));
+            continue;
+        }
+        info!(
+            "[Delete] Removed {} of {} for {}: {}",
+            path.display(),
+            camera.name,
+            user,
+            reason
+        );
+        let line = format!(
+            "{}\tdelete\t{}\t{}\t{}\t{}\t{}\t{}",
+            chrono::Local::now().naive_local().format(TIME_FORMAT),
+            user.replace(['\t', '\n'], " "),
+            path.display(),
+            start.format(TIME_FORMAT),
+            end.format(TIME_FORMAT),
+            bytes,
+            reason.replace(['\t', '\n'], " ")
+        );
+        if let Err(e) = audit(camera, &line) {
+            error!(
+                "[Delete] Failed to write {}: {}",
+                audit_path(camera).display(),
+                e
+            );
+        }
+        removed.push(path.clone());
+        deleted.segments += 1;
+        deleted.bytes += bytes;
+    }
+    index::retain(
+        camera,
+        |record| !matches!(record, Record::Segment { path, .. } | Record::Encrypted { path, .. } if removed.contains(path)),
+    )
+    .map_err(|e| Error::Failed(format!("Failed to update index: {}", e)))?;
+    match failed {
+        Some(e) => Err(Error::Failed(format!(
+            "{} (removed {} of {} segment(s))",
+            e,
+            deleted.segments,
+            selected.len()
+        ))),
+        None => Ok(deleted),
+    }
+}
+
+// 删除分段和镜像目录中的副本
+fn remove(camera: &CameraConfig, path: &Path) -> io::Result<()> {
+    segment::remove_segment(path)?;
+    if let (Some(mirror), Ok(relative)) =
+        (&camera.mirror_dir, path.strip_prefix(&camera.output_dir))
+    {
+        segment::remove_segment(&mirror.join(relative))?;
+    }
+    Ok(())
+}
+
+fn audit(camera: &CameraConfig, line: &str) -> io::Result<()> {
+    fs::create_dir_all(&camera.output_dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(audit_path(camera))?;
+    writeln!(file, "{}", line)
+}
@@ -0,0 +1,538 @@
+// 录像的静态加密：设置了 encryption_keys 时，分段写完后用密钥文件中的当前密钥原地加密
+// （ChaCha20 加密，HMAC-SHA256 校验），镜像副本一并加密，索引记下每个文件所用密钥的编号。
+// 轮换密钥只需在密钥文件末尾加一行，之后的分段使用新密钥；旧密钥留在文件中用于解密，
+// 重新加密（reencrypt 命令或 encryption_reencrypt 后台任务）把旧密钥加密的和未加密的分段改用当前密钥
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::chacha20::ChaCha20;
+use crate::config::CameraConfig;
+use crate::index::{self, Record};
+use crate::sha256::Sha256;
+
+// 文件头：MAGIC、密钥编号长度（1 字节）、密钥编号、12 字节 nonce；文件末尾是 32 字节 HMAC
+const MAGIC: &[u8; 8] = b"SRVENC1\n";
+const TAG: u64 = 32;
+const CHUNK: usize = 1 << 20;
+const REENCRYPT_INTERVAL: Duration = Duration::from_secs(600);
+
+pub struct Key {
+    pub id: String,
+    cipher: [u8; 32],
+    mac: [u8; 32],
+}
+
+pub struct Keys(Vec<Key>);
+
+impl Keys {
+    // 新分段使用的密钥：文件中的最后一个
+    pub fn current(&self) -> &Key {
+        &self.0[self.0.len() - 1]
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Key> {
+        self.0.iter().find(|key| key.id == id)
+    }
+}
+
+// 密钥文件每行 "<编号> <64 位十六进制的 256 位密钥>"，# 开头的行是注释
+pub fn load_keys(path: &Path) -> Result<Keys, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut keys: Vec<Key> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || {
+            format!(
+                "{}:{}: expected '<id> <64 hex digits>'",
+                path.display(),
+                number + 1
+            )
+        };
+        let (id, hex) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+        let hex = hex.trim();
+        if id.len() > 255 || hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut secret = [0u8; 32];
+        for (i, byte) in secret.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        if keys.iter().any(|key| key.id == id) {
+            return Err(format!("{}: duplicate key '{}'", path.display(), id));
+        }
+        keys.push(Key {
+            id: id.to_string(),
+            cipher: derive(&secret, b"encrypt"),
+            mac: derive(&secret, b"authenticate"),
+        });
+    }
+    if keys.is_empty() {
+        return Err(format!("{} contains no keys", path.display()));
+    }
+    Ok(Keys(keys))
+}
+
+// 加密和校验用不同的子密钥
+fn derive(secret: &[u8; 32], purpose: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(secret);
+    hasher.update(purpose);
+    hasher.digest()
+}
+
+// 文件头中的密钥编号，未加密的文件返回 None
+pub fn key_id(path: &Path) -> io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    Ok(read_header(&mut file)?.map(|(id, _, _)| id))
+}
+
+// 分段写完后调用：用当前密钥加密分段及其镜像副本，登记到索引
+pub fn seal(camera: &CameraConfig, path: &Path) -> Result<(), String> {
+    let file = match &camera.encryption_keys {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+    let keys = load_keys(file)?;
+    encrypt_copies(camera, path, &keys)
+}
+
+// 把不是用当前密钥加密的分段改用当前密钥，返回处理的个数；running 变为 false 时中途停止
+pub fn reencrypt(camera: &CameraConfig, running: &AtomicBool) -> Result<usize, String> {
+    let file = camera
+        .encryption_keys
+        .as_ref()
+        .ok_or_else(|| format!("{} has no encryption_keys", camera.name))?;
+    let keys = load_keys(file)?;
+    let records = index::load(camera).map_err(|e| format!("Failed to read index: {}", e))?;
+    let mut encrypted: HashMap<&Path, &str> = HashMap::new();
+    for record in &records {
+        if let Record::Encrypted { path, key } = record {
+            encrypted.insert(path, key);
+        }
+    }
+    let mut count = 0;
+    for record in &records {
+        let path = match record {
+            Record::Segment { path, .. } => path,
+            _ => continue,
+        };
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+        if encrypted.get(path.as_path()) == Some(&keys.current().id.as_str()) || !path.exists() {
+            continue;
+        }
+        encrypt_copies(camera, path, &keys)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// 解密到 output，返回所用密钥的编号
+pub fn decrypt(camera: &CameraConfig, path: &Path, output: &Path) -> Result<String, String> {
+    let file = camera
+        .encryption_keys
+        .as_ref()
+        .ok_or_else(|| format!("{} has no encryption_keys", camera.name))?;
+    let keys = load_keys(file)?;
+    let id = key_id(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .ok_or_else(|| format!("{} is not encrypted", path.display()))?;
+    let key = keys
+        .get(&id)
+        .ok_or_else(|| format!("Key '{}' is not in {}", id, file.display()))?;
+    transform(path, output, Some(key), None)
+        .map_err(|e| format!("Failed to decrypt {}: {}", path.display(), e))?;
+    Ok(id)
+}
+
+// 重新加密的后台任务：定期处理设置了 encryption_reencrypt 的摄像头，一次一个文件
+pub fn spawn(cameras: Vec<CameraConfig>, running: Arc<AtomicBool>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_run: Option<Instant> = None;
+        while running.load(Ordering::SeqCst) {
+            if last_run.is_none_or(|t| t.elapsed() >= REENCRYPT_INTERVAL) {
+                // 子码流的分段记在主码流的索引里，由主码流处理
+                for camera in cameras
+                    .iter()
+                    .filter(|c| c.encryption_reencrypt && c.substream_of.is_none())
+                {
+                    match reencrypt(camera, &running) {
+                        Ok(0) => {}
+                        Ok(count) => info!(
+                            "[Encryption] Re-encrypted {} segment(s) of {}",
+                            count, camera.name
+                        ),
+                        Err(e) => error!(
+                            "[Encryption] Failed to re-encrypt segments of {}: {}",
+                            camera.name, e
+                        ),
+                    }
+                }
+                last_run = Some(Instant::now());
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    })
+}
+
+// 分段和镜像目录中的副本都改用当前密钥，再更新索引
+fn encrypt_copies(camera: &CameraConfig, path: &Path, keys: &Keys) -> Result<(), String> {
+    let current = keys.current();
+    let mut copies = vec![path.to_path_buf()];
+    if let (Some(mirror), Ok(relative)) =
+        (&camera.mirror_dir, path.strip_prefix(&camera.output_dir))
+    {
+        copies.push(mirror.join(relative));
+    }
+    for copy in copies.iter().filter(|copy| copy.exists()) {
+        let from =
+            match key_id(copy).map_err(|e| format!("Failed to read {}: {}", copy.display(), e))? {
+                Some(id) if id == current.id => continue,
+                Some(id) => Some(
+                    keys.get(&id)
+                        .ok_or_else(|| format!("{} uses unknown key '{}'", copy.display(), id))?,
+                ),
+                None => None,
+            };
+        let tmp = temporary(copy);
+        let result =
+            transform(copy, &tmp, from, Some(current)).and_then(|_| fs::rename(&tmp, copy));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&tmp);
+            return Err(format!("Failed to encrypt {}: {}", copy.display(), e));
+        }
+    }
+    index::set_key(camera, path, &current.id).map_err(|e| format!("Failed to update index: {}", e))
+}
+
+fn temporary(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".encrypting");
+    PathBuf::from(name)
+}
+
+// 读取文件头：密钥编号、nonce 和头的长度；不是加密文件时返回 None 并回到文件开头
+fn read_header(file: &mut File) -> io::Result<Option<(String, [u8; 12], u64)>> {
+    let mut magic = [0u8; 8];
+    let matched = match file.read_exact(&mut magic) {
+        Ok(_) => &magic == MAGIC,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(e) => return Err(e),
+    };
+    if !matched {
+        file.seek(SeekFrom::Start(0))?;
+        return Ok(None);
+    }
+    let mut length = [0u8; 1];
+    file.read_exact(&mut length)?;
+    let mut id = vec![0u8; length[0] as usize];
+    file.read_exact(&mut id)?;
+    let mut nonce = [0u8; 12];
+    file.read_exact(&mut nonce)?;
+    let id = String::from_utf8(id).map_err(|_| io::Error::other("Invalid key id"))?;
+    Ok(Some((id, nonce, 8 + 1 + length[0] as u64 + 12)))
+}
+
+// 从 src 读出（from 为 None 表示明文），写成 dst（to 为 None 表示明文）；先校验再解密，
+// 加解密在同一遍中进行，重新加密时明文不落盘
+fn transform(src: &Path, dst: &Path, from: Option<&Key>, to: Option<&Key>) -> io::Result<()> {
+    let mut input = File::open(src)?;
+    let total = input.metadata()?.len();
+    let (mut decrypt, remaining) = match from {
+        Some(key) => {
+            let (id, nonce, header) = read_header(&mut input)?
+                .ok_or_else(|| io::Error::other("Not an encrypted file"))?;
+            if id != key.id || total < header + TAG {
+                return Err(io::Error::other("Corrupt encrypted file"));
+            }
+            let body = total - header - TAG;
+            // 校验整个文件后再解密，被篡改或截断的文件不会产生输出
+            input.seek(SeekFrom::Start(0))?;
+            let mut mac = Hmac::new(&key.mac);
+            copy_chunks(&mut input, header + body, |chunk| {
+                mac.update(chunk);
+                Ok(())
+            })?;
+            let mut tag = [0u8; TAG as usize];
+            input.read_exact(&mut tag)?;
+            let expected = mac.finish();
+            if tag
+                .iter()
+                .zip(expected)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                != 0
+            {
+                return Err(io::Error::other(
+                    "Authentication failed, wrong key or tampered file",
+                ));
+            }
+            input.seek(SeekFrom::Start(header))?;
+            (Some(ChaCha20::new(&key.cipher, &nonce)), body)
+        }
+        None => (None, total),
+    };
+    let mut output = File::create(dst)?;
+    let mut encrypt = match to {
+        Some(key) => {
+            let nonce = nonce(dst);
+            let mut header = MAGIC.to_vec();
+            header.push(key.id.len() as u8);
+            header.extend_from_slice(key.id.as_bytes());
+            header.extend_from_slice(&nonce);
+            output.write_all(&header)?;
+            let mut mac = Hmac::new(&key.mac);
+            mac.update(&header);
+            Some((ChaCha20::new(&key.cipher, &nonce), mac))
+        }
+        None => None,
+    };
+    copy_chunks(&mut input, remaining, |chunk| {
+        if let Some(cipher) = &mut decrypt {
+            cipher.apply(chunk);
+        }
+        if let Some((cipher, mac)) = &mut encrypt {
+            cipher.apply(chunk);
+            mac.update(chunk);
+        }
+        output.write_all(chunk)
+    })?;
+    if let Some((_, mac)) = encrypt {
+        output.write_all(&mac.finish())?;
+    }
+    output.sync_all()
+}
+
+fn copy_chunks<F: FnMut(&mut [u8]) -> io::Result<()>>(
+    input: &mut File,
+    mut length: u64,
+    mut f: F,
+) -> io::Result<()> {
+    let mut buffer = vec![0u8; CHUNK];
+    while length > 0 {
+        let size = (length as usize).min(CHUNK);
+        input.read_exact(&mut buffer[..size])?;
+        f(&mut buffer[..size])?;
+        length -= size as u64;
+    }
+    Ok(())
+}
+
+// 同一个密钥下 nonce 不能重复：随机种子的哈希加上时间和路径
+fn nonce(path: &Path) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    for (i, chunk) in nonce.chunks_mut(6).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos()),
+        );
+        hasher.write(path.as_os_str().as_encoded_bytes());
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..6]);
+    }
+    nonce
+}
+
+// HMAC-SHA256（RFC 2104）
+struct Hmac {
+    inner: Sha256,
+    outer: [u8; 64],
+}
+
+impl Hmac {
+    fn new(key: &[u8]) -> Hmac {
+        // 长于一个块的密钥先取摘要
+        let hashed;
+        let key = if key.len() > 64 {
+            let mut hasher = Sha256::new();
+            hasher.update(key);
+            hashed = hasher.digest();
+            &hashed[..]
+        } else {
+            key
+        };
+        let mut inner_pad = [0x36u8; 64];
+        let mut outer = [0x5cu8; 64];
+        for (i, byte) in key.iter().enumerate() {
+            inner_pad[i] ^= byte;
+            outer[i] ^= byte;
+        }
+        let mut inner = Sha256::new();
+        inner.update(&inner_pad);
+        Hmac { inner, outer }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    fn finish(self) -> [u8; 32] {
+        let mut outer = Sha256::new();
+        outer.update(&self.outer);
+        outer.update(&self.inner.digest());
+        outer.digest()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn hmac(key: &[u8], data: &[u8]) -> String {
+        let mut mac = Hmac::new(key);
+        mac.update(data);
+        hex(&mac.finish())
+    }
+
+    // RFC 4231 的测试用例 1、2、6（长于一个块的密钥）
+    #[test]
+    fn hmac_rfc4231() {
+        assert_eq!(
+            hmac(&[0x0b; 20], b"Hi There"),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hmac(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hmac(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            ),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    struct Fixture {
+        dir: PathBuf,
+        camera: CameraConfig,
+        segment: PathBuf,
+        plaintext: Vec<u8>,
+    }
+
+    impl Fixture {
+        // 临时目录里的一个摄像头、密钥文件和跨越多个读写块的分段
+        fn new(name: &str) -> Fixture {
+            let dir = std::env::temp_dir().join(format!(
+                "save_rtsp-encryption-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let camera = CameraConfig {
+                name: "gate".to_string(),
+                output_dir: dir.clone(),
+                encryption_keys: Some(dir.join("keys")),
+                ..Default::default()
+            };
+            let fixture = Fixture {
+                segment: dir.join("gate_20240501_080000.h264"),
+                plaintext: (0..CHUNK * 2 + 123).map(|i| (i % 251) as u8).collect(),
+                dir,
+                camera,
+            };
+            fixture.write_keys(&[("2024q1", 0x11)]);
+            fs::write(&fixture.segment, &fixture.plaintext).unwrap();
+            fixture
+        }
+
+        fn write_keys(&self, keys: &[(&str, u8)]) {
+            let lines: Vec<String> = keys
+                .iter()
+                .map(|(id, byte)| format!("{} {}", id, hex(&[*byte; 32])))
+                .collect();
+            fs::write(self.dir.join("keys"), lines.join("\n")).unwrap();
+        }
+
+        fn decrypt(&self) -> Result<Vec<u8>, String> {
+            let output = self.dir.join("decrypted");
+            let _ = fs::remove_file(&output);
+            let result = decrypt(&self.camera, &self.segment, &output);
+            // 校验失败时不产生输出
+            assert_eq!(result.is_ok(), output.exists());
+            result.map(|_| fs::read(&output).unwrap())
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn seal_and_decrypt_round_trip() {
+        let fixture = Fixture::new("round-trip");
+        seal(&fixture.camera, &fixture.segment).unwrap();
+        let sealed = fs::read(&fixture.segment).unwrap();
+        assert!(sealed.starts_with(MAGIC));
+        assert_eq!(
+            sealed.len() as u64,
+            fixture.plaintext.len() as u64 + 8 + 1 + 6 + 12 + TAG
+        );
+        assert!(!sealed
+            .windows(64)
+            .any(|window| window == &fixture.plaintext[..64]));
+        assert_eq!(key_id(&fixture.segment).unwrap().as_deref(), Some("2024q1"));
+        assert_eq!(fixture.decrypt().unwrap(), fixture.plaintext);
+        assert!(index::load(&fixture.camera)
+            .unwrap()
+            .iter()
+            .any(|record| matches!(
+                record,
+                Record::Encrypted { path, key } if *path == fixture.segment && key == "2024q1"
+            )));
+
+        // 轮换密钥后再次加密，旧密钥仍在文件中
+        fixture.write_keys(&[("2024q1", 0x11), ("2024q2", 0x22)]);
+        seal(&fixture.camera, &fixture.segment).unwrap();
+        assert_eq!(key_id(&fixture.segment).unwrap().as_deref(), Some("2024q2"));
+        assert_eq!(fixture.decrypt().unwrap(), fixture.plaintext);
+    }
+
+    #[test]
+    fn rejects_tampered_files() {
+        let fixture = Fixture::new("tamper");
+        seal(&fixture.camera, &fixture.segment).unwrap();
+        let sealed = fs::read(&fixture.segment).unwrap();
+
+        // 正文、文件头里的 nonce 和末尾的校验值各改一个比特
+        for position in [CHUNK + 7, 8 + 1 + 6 + 3, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[position] ^= 1;
+            fs::write(&fixture.segment, &tampered).unwrap();
+            assert!(fixture.decrypt().is_err(), "byte {} not detected", position);
+        }
+
+        // 截断
+        fs::write(&fixture.segment, &sealed[..sealed.len() - 100]).unwrap();
+        assert!(fixture.decrypt().is_err());
+
+        // 同一编号但密钥不对
+        fs::write(&fixture.segment, &sealed).unwrap();
+        fixture.write_keys(&[("2024q1", 0x33)]);
+        assert!(fixture.decrypt().is_err());
+        fixture.write_keys(&[("2024q1", 0x11)]);
+        assert_eq!(fixture.decrypt().unwrap(), fixture.plaintext);
+    }
+}
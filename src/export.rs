@@ -0,0 +1,121 @@
+// 通过 Unix socket 把视频包转发给同机的分析程序，免得它们再单独连一次摄像头。
+// 每个包一帧：4 字节长度（大端）、1 字节标志（bit0 关键帧）、8 字节 pts（大端，无 pts 时为 i64::MIN），
+// 随后是包数据。新连接从下一个关键帧开始接收；跟不上的读取方会被断开，不影响录制
+use std::io;
+use std::path::Path;
+
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+#[cfg(unix)]
+struct Client {
+    stream: UnixStream,
+    // 还没收到过关键帧
+    waiting_for_key: bool,
+}
+
+#[cfg(unix)]
+pub struct Exporter {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Vec<Client>,
+}
+
+#[cfg(unix)]
+impl Exporter {
+    pub fn bind(path: &Path) -> io::Result<Exporter> {
+        // 清理上次运行留下的 socket 文件
+        let _ = std::fs::remove_file(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Exporter {
+            path: path.to_path_buf(),
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    pub fn send(&mut self, id: usize, key: bool, pts: Option<i64>, data: &[u8]) {
+        self.accept(id);
+        if self.clients.is_empty() {
+            return;
+        }
+        let mut frame = Vec::with_capacity(13 + data.len());
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.push(key as u8);
+        frame.extend_from_slice(&pts.unwrap_or(i64::MIN).to_be_bytes());
+        frame.extend_from_slice(data);
+        self.clients.retain_mut(|client| {
+            if client.waiting_for_key {
+                if !key {
+                    return true;
+                }
+                client.waiting_for_key = false;
+            }
+            match client.stream.write_all(&frame) {
+                Ok(_) => true,
+                Err(e) => {
+                    info!("[Stream {}] Export client disconnected: {}", id, e);
+                    false
+                }
+            }
+        });
+    }
+
+    // 非阻塞地接受新连接，不需要额外的线程
+    fn accept(&mut self, id: usize) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        error!("[Stream {}] Failed to set up export client: {}", id, e);
+                        continue;
+                    }
+                    info!(
+                        "[Stream {}] Export client connected to {}",
+                        id,
+                        self.path.display()
+                    );
+                    self.clients.push(Client {
+                        stream,
+                        waiting_for_key: true,
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("[Stream {}] Failed to accept export client: {}", id, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(not(unix))]
+pub struct Exporter;
+
+#[cfg(not(unix))]
+impl Exporter {
+    pub fn bind(_path: &Path) -> io::Result<Exporter> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "export_socket requires Unix sockets",
+        ))
+    }
+
+    pub fn send(&mut self, _id: usize, _key: bool, _pts: Option<i64>, _data: &[u8]) {}
+}
@@ -0,0 +1,103 @@
+// 连接健康告警：长时间连不上为 down，短时间内反复断线重连为 flapping。
+// 后者通常是 PoE 供电或 Wi-Fi 不稳，而不是摄像头坏了
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::alert;
+use crate::config::CameraConfig;
+use crate::state::State;
+
+#[derive(Default)]
+pub struct Health {
+    // 最近一个窗口内连上后又断开的时刻
+    drops: VecDeque<Instant>,
+    flapping: bool,
+    down_since: Option<Instant>,
+    down_alerted: bool,
+}
+
+impl Health {
+    // 接着上次运行的状态：重启前已经连不上的时间也计入 down 告警和自动停用
+    pub fn restore(state: &State) -> Health {
+        let down_since = state.down_since.map(|since| {
+            let down = (chrono::Local::now().naive_local() - since)
+                .to_std()
+                .unwrap_or_default();
+            Instant::now()
+                .checked_sub(down)
+                .unwrap_or_else(Instant::now)
+        });
+        Health {
+            down_since,
+            down_alerted: state.down_alerted,
+            ..Health::default()
+        }
+    }
+
+    pub fn store(&self, state: &mut State) {
+        state.down_since = self.down_for().map(|down| {
+            chrono::Local::now().naive_local()
+                - chrono::Duration::from_std(down).unwrap_or_default()
+        });
+        state.down_alerted = self.down_alerted;
+    }
+
+    // 每次连接结束（出错或流结束）后调用，was_up 表示这次连接是否成功开始写入
+    pub fn disconnected(&mut self, camera: &CameraConfig, was_up: bool) {
+        let now = Instant::now();
+        if was_up {
+            self.down_since = Some(now);
+            self.down_alerted = false;
+            self.record_drop(camera, now);
+            return;
+        }
+        let down_since = *self.down_since.get_or_insert(now);
+        if let Some(limit) = camera.down_alert {
+            if !self.down_alerted && down_since.elapsed() >= limit {
+                self.down_alerted = true;
+                alert::raise(
+                    camera,
+                    "down",
+                    &format!(
+                        "Cannot connect for {} seconds",
+                        down_since.elapsed().as_secs()
+                    ),
+                );
+            }
+        }
+    }
+
+    // 连续连不上的时长，当前连接成功过则为 None
+    pub fn down_for(&self) -> Option<Duration> {
+        self.down_since.map(|t| t.elapsed())
+    }
+
+    fn record_drop(&mut self, camera: &CameraConfig, now: Instant) {
+        let threshold = match camera.flap_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+        self.drops.push_back(now);
+        while self
+            .drops
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > camera.flap_window)
+        {
+            self.drops.pop_front();
+        }
+        let flapping = self.drops.len() >= threshold;
+        if flapping && !self.flapping {
+            alert::raise(
+                camera,
+                "flapping",
+                &format!(
+                    "Disconnected {} times in the last {} minutes",
+                    self.drops.len(),
+                    camera.flap_window.as_secs() / 60
+                ),
+            );
+        }
+        // 窗口内的断线次数回落后，再次超过阈值时重新告警
+        self.flapping = flapping;
+    }
+}
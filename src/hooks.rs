@@ -0,0 +1,101 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+
+use crate::config::CameraConfig;
+
+// 同时运行的钩子命令数上限
+static CONCURRENCY: AtomicUsize = AtomicUsize::new(2);
+static RUNNING: Mutex<usize> = Mutex::new(0);
+static SLOT_FREED: Condvar = Condvar::new();
+
+pub fn set_concurrency(limit: usize) {
+    CONCURRENCY.store(limit.max(1), Ordering::SeqCst);
+}
+
+// 分段完成后执行 on_segment 命令：路径、摄像头、开始、结束时间既作为参数 $1..$4，
+// 也通过 SEGMENT_PATH / SEGMENT_CAMERA / SEGMENT_START / SEGMENT_END 环境变量传入
+pub fn segment_finished(
+    camera: &CameraConfig,
+    path: &Path,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) {
+    let command = match &camera.on_segment {
+        Some(command) => command.clone(),
+        None => return,
+    };
+    let args = [
+        path.display().to_string(),
+        camera.name.clone(),
+        start.format("%Y-%m-%d %H:%M:%S").to_string(),
+        end.format("%Y-%m-%d %H:%M:%S").to_string(),
+    ];
+    let timeout = camera.hook_timeout;
+    thread::spawn(move || {
+        let mut cmd = shell(&command);
+        cmd.args(&args)
+            .env("SEGMENT_PATH", &args[0])
+            .env("SEGMENT_CAMERA", &args[1])
+            .env("SEGMENT_START", &args[2])
+            .env("SEGMENT_END", &args[3]);
+        acquire_slot();
+        let result = run_with_timeout(cmd, timeout);
+        release_slot();
+        if let Err(e) = result {
+            error!("[Hook] on_segment for {} failed: {}", args[0], e);
+        }
+    });
+}
+
+fn acquire_slot() {
+    let mut running = RUNNING.lock().unwrap();
+    while *running >= CONCURRENCY.load(Ordering::SeqCst) {
+        running = SLOT_FREED.wait(running).unwrap();
+    }
+    *running += 1;
+}
+
+fn release_slot() {
+    *RUNNING.lock().unwrap() -= 1;
+    SLOT_FREED.notify_one();
+}
+
+pub fn run_with_timeout(mut cmd: Command, timeout: Duration) -> Result<(), String> {
+    let mut child = cmd.spawn().map_err(|e| e.to_string())?;
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(format!("exited with {}", status)),
+            None if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}s", timeout.as_secs()));
+            }
+            None => thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+// 通过系统 shell 执行，命令里可以使用管道和重定向；
+// unix 上额外的参数成为 $1、$2 ...
+pub fn shell(command: &str) -> Command {
+    #[cfg(unix)]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command).arg("sh");
+        cmd
+    }
+    #[cfg(not(unix))]
+    {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    }
+}
@@ -0,0 +1,701 @@
+// 按需剪辑的 HTTP 接口：GET /clip?camera=gate&from=2024-05-01T08:00:00&to=2024-05-01T08:05:00
+// 返回现拼的 MP4，支持 Range 请求，可直接嵌入工单和聊天软件里播放。
+// 摄像头设置了 api_token 时需要 Authorization: Bearer <令牌> 或 token=<令牌>。
+// 同一时段的片段缓存在临时目录，播放器的多次 Range 请求不会重复剪辑。
+// GET /sla?camera=gate&month=2024-05 返回该月的可用率报告，POST /notes 给摄像头加注释，令牌要求与 /clip 相同。
+// 配置了 admin_token 时还可以 POST /streams 新增摄像头：先探测，通了才写入配置并开始录制
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use ffmpeg_the_third as ffmpeg;
+
+use crate::clip;
+use crate::config::{self, CameraConfig};
+use crate::control::StreamHandle;
+use crate::deletion::{self, Target};
+use crate::index;
+use crate::input::{self, Watchdog};
+use crate::sla;
+
+// 单个片段的最大时长
+const MAX_CLIP: Duration = Duration::from_secs(3600);
+// 缓存的片段保留时长
+const CACHE_TTL: Duration = Duration::from_secs(3600);
+
+// 探测新摄像头的最长时间
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+// POST 请求体的上限
+const MAX_BODY: u64 = 64 * 1024;
+
+// 正在剪辑的片段名及各自的锁：同一片段的请求等第一个剪完再共用结果，不同片段互不阻塞
+static ASSEMBLING: Mutex<Vec<(String, Arc<Mutex<()>>)>> = Mutex::new(Vec::new());
+// 新增摄像头逐个进行，避免同名的两个请求同时通过检查
+static ADDING: Mutex<()> = Mutex::new(());
+
+// 新增摄像头所需的设置，start 在录制进程中开始录制新摄像头
+pub struct Admin {
+    pub token: String,
+    pub config_path: PathBuf,
+    pub start: Box<dyn Fn(Vec<CameraConfig>) + Send + Sync>,
+}
+
+struct Server {
+    cameras: Mutex<Vec<CameraConfig>>,
+    // 运行中的各路流，包括新增的，用于触发按需摄像头
+    streams: Arc<Mutex<Vec<Arc<StreamHandle>>>>,
+    admin: Option<Admin>,
+}
+
+pub fn spawn(
+    address: String,
+    cameras: Vec<CameraConfig>,
+    streams: Arc<Mutex<Vec<Arc<StreamHandle>>>>,
+    admin: Option<Admin>,
+    running: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(&address)?;
+    // 非阻塞 accept，按下 q 后能及时退出
+    listener.set_nonblocking(true)?;
+    info!("[HTTP] Listening on {}", address);
+    let server = Arc::new(Server {
+        cameras: Mutex::new(cameras),
+        streams,
+        admin,
+    });
+    Ok(thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let server = server.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle(stream, &server) {
+                            error!("[HTTP] Request failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(200))
+                }
+                Err(e) => error!("[HTTP] Failed to accept connection: {}", e),
+            }
+        }
+    }))
+}
+
+fn handle(stream: TcpStream, server: &Server) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut range = None;
+    let mut authorization = None;
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range = Some(value.trim().to_string());
+            } else if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            } else if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = value
+                    .trim()
+                    .strip_prefix("Bearer ")
+                    .map(|token| token.trim().to_string());
+            }
+        }
+    }
+    let mut stream = stream;
+    let target = match request.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["POST", "/streams", _] => {
+            if length > MAX_BODY {
+                return respond(&mut stream, "413 Payload Too Large", "Request too large");
+            }
+            let mut body = String::new();
+            (&mut reader).take(length).read_to_string(&mut body)?;
+            let (status, message) = add_stream(server, authorization.as_deref(), body.trim());
+            return respond(&mut stream, status, &message);
+        }
+        ["POST", target, _] if target.split('?').next() == Some("/notes") => {
+            if length > MAX_BODY {
+                return respond(&mut stream, "413 Payload Too Large", "Request too large");
+            }
+            let mut body = String::new();
+            (&mut reader).take(length).read_to_string(&mut body)?;
+            let cameras = server.cameras.lock().unwrap().clone();
+            let query = target.split_once('?').map_or("", |(_, query)| query);
+            let (status, message) =
+                add_note(&cameras, authorization.as_deref(), query, body.trim());
+            return respond(&mut stream, status, &message);
+        }
+        ["POST", target, _] if target.split('?').next() == Some("/activate") => {
+            let streams = server.streams.lock().unwrap().clone();
+            let query = target.split_once('?').map_or("", |(_, query)| query);
+            let (status, message) = activate(&streams, authorization.as_deref(), query);
+            return respond(&mut stream, status, &message);
+        }
+        ["DELETE", target, _] if target.split('?').next() == Some("/recordings") => {
+            let cameras = server.cameras.lock().unwrap().clone();
+            let query = target.split_once('?').map_or("", |(_, query)| query);
+            let (status, message) = delete_recordings(&cameras, authorization.as_deref(), query);
+            return respond(&mut stream, status, &message);
+        }
+        ["GET" | "HEAD", target, _] => target.to_string(),
+        _ => {
+            return respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "Only GET, POST /streams, POST /notes, POST /activate and DELETE /recordings are supported",
+            )
+        }
+    };
+    let head = request.starts_with("HEAD");
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let cameras = server.cameras.lock().unwrap().clone();
+    if path == "/sla" {
+        let (status, message) = sla_report(&cameras, authorization.as_deref(), query);
+        return respond(&mut stream, status, &message);
+    }
+    if path != "/clip" {
+        return respond(&mut stream, "404 Not Found", "Not found");
+    }
+    let clip = match locate(&cameras, query) {
+        Ok(clip) => clip,
+        Err(e) => return respond(&mut stream, "400 Bad Request", &e),
+    };
+    if let Some(expected) = &clip.camera.api_token {
+        if authorization.as_ref().or(clip.token.as_ref()) != Some(expected) {
+            return respond(&mut stream, "401 Unauthorized", "Missing or wrong token");
+        }
+    }
+    match prepare(&clip) {
+        Ok(path) => send_file(&mut stream, &path, range.as_deref(), head),
+        Err(e) => respond(&mut stream, "404 Not Found", &e),
+    }
+}
+
+// GET /sla?camera=gate&month=2024-05，month 默认为本月；返回 key=value 行
+fn sla_report(
+    cameras: &[CameraConfig],
+    authorization: Option<&str>,
+    query: &str,
+) -> (&'static str, String) {
+    let mut name = None;
+    let mut month = None;
+    let mut token = None;
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode(value);
+        match key {
+            "camera" => name = Some(value),
+            "month" => month = Some(value),
+            "token" => token = Some(value),
+            _ => {}
+        }
+    }
+    let name = match name {
+        Some(name) => name,
+        None => return ("400 Bad Request", "Missing camera".to_string()),
+    };
+    let camera = match cameras.iter().find(|c| c.name == name) {
+        Some(camera) => camera,
+        None => return ("400 Bad Request", format!("No camera named '{}'", name)),
+    };
+    if let Some(expected) = &camera.api_token {
+        if authorization.or(token.as_deref()) != Some(expected.as_str()) {
+            return ("401 Unauthorized", "Missing or wrong token".to_string());
+        }
+    }
+    let month = month.unwrap_or_else(sla::current_month);
+    match sla::report(camera, &month) {
+        Ok(text) => ("200 OK", text),
+        Err(e) => ("400 Bad Request", e),
+    }
+}
+
+// POST /notes?camera=gate&time=2024-05-01T08:01:30&author=alice，请求体为注释文字；
+// time 默认为现在，令牌要求与 /clip 相同
+fn add_note(
+    cameras: &[CameraConfig],
+    authorization: Option<&str>,
+    query: &str,
+    text: &str,
+) -> (&'static str, String) {
+    let mut name = None;
+    let mut time = None;
+    let mut author = String::new();
+    let mut token = None;
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode(value);
+        match key {
+            "camera" => name = Some(value),
+            "time" => match parse_time(&value) {
+                Ok(value) => time = Some(value),
+                Err(e) => return ("400 Bad Request", e),
+            },
+            "author" => author = value,
+            "token" => token = Some(value),
+            _ => {}
+        }
+    }
+    let name = match name {
+        Some(name) => name,
+        None => return ("400 Bad Request", "Missing camera".to_string()),
+    };
+    let camera = match cameras.iter().find(|c| c.name == name) {
+        Some(camera) => camera,
+        None => return ("400 Bad Request", format!("No camera named '{}'", name)),
+    };
+    if let Some(expected) = &camera.api_token {
+        if authorization.or(token.as_deref()) != Some(expected.as_str()) {
+            return ("401 Unauthorized", "Missing or wrong token".to_string());
+        }
+    }
+    if text.is_empty() {
+        return ("400 Bad Request", "Missing note text".to_string());
+    }
+    let time = time.unwrap_or_else(|| chrono::Local::now().naive_local());
+    match index::annotate(camera, time, &author, text) {
+        Ok(()) => (
+            "201 Created",
+            format!("Added note to {} at {}", camera.name, time),
+        ),
+        Err(e) => (
+            "500 Internal Server Error",
+            format!("Failed to write index: {}", e),
+        ),
+    }
+}
+
+// POST /activate?camera=gate&seconds=600：按需摄像头立即开始录制，camera 也可以是 @标签或 %租户，
+// seconds 默认为 on_demand_seconds；选中的摄像头设置了 api_token 时都要求令牌
+fn activate(
+    streams: &[Arc<StreamHandle>],
+    authorization: Option<&str>,
+    query: &str,
+) -> (&'static str, String) {
+    let mut selector = None;
+    let mut seconds = None;
+    let mut token = None;
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode(value);
+        match key {
+            "camera" => selector = Some(value),
+            "seconds" => match value.parse() {
+                Ok(value) => seconds = Some(value),
+                Err(_) => return ("400 Bad Request", format!("Invalid seconds '{}'", value)),
+            },
+            "token" => token = Some(value),
+            _ => {}
+        }
+    }
+    let selector = match selector {
+        Some(selector) => selector,
+        None => return ("400 Bad Request", "Missing camera".to_string()),
+    };
+    let matched: Vec<_> = streams
+        .iter()
+        .filter(|h| h.camera.on_demand && h.camera.matches(&selector))
+        .collect();
+    if matched.is_empty() {
+        return (
+            "400 Bad Request",
+            format!("No on-demand camera matches '{}'", selector),
+        );
+    }
+    for handle in &matched {
+        if let Some(expected) = &handle.camera.api_token {
+            if authorization.or(token.as_deref()) != Some(expected.as_str()) {
+                return ("401 Unauthorized", "Missing or wrong token".to_string());
+            }
+        }
+    }
+    for handle in &matched {
+        let seconds = seconds.unwrap_or(handle.camera.on_demand_seconds);
+        handle.activate(Duration::from_secs(seconds));
+        info!(
+            "[HTTP] Activated {} for {} seconds",
+            handle.camera.name, seconds
+        );
+    }
+    (
+        "200 OK",
+        format!(
+            "Activated {} camera(s) matching '{}'",
+            matched.len(),
+            selector
+        ),
+    )
+}
+
+// DELETE /recordings?camera=gate&from=...&to=...&reason=...&user=alice，或用 segment=<路径>
+// 代替 from/to；只有设置了 api_token 的摄像头允许通过接口删除
+fn delete_recordings(
+    cameras: &[CameraConfig],
+    authorization: Option<&str>,
+    query: &str,
+) -> (&'static str, String) {
+    let mut name = None;
+    let mut from = None;
+    let mut to = None;
+    let mut segment = None;
+    let mut reason = String::new();
+    let mut user = String::new();
+    let mut token = None;
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode(value);
+        match key {
+            "camera" => name = Some(value),
+            "from" | "to" => match parse_time(&value) {
+                Ok(time) if key == "from" => from = Some(time),
+                Ok(time) => to = Some(time),
+                Err(e) => return ("400 Bad Request", e),
+            },
+            "segment" => segment = Some(PathBuf::from(value)),
+            "reason" => reason = value,
+            "user" => user = value,
+            "token" => token = Some(value),
+            _ => {}
+        }
+    }
+    let name = match name {
+        Some(name) => name,
+        None => return ("400 Bad Request", "Missing camera".to_string()),
+    };
+    let camera = match cameras.iter().find(|c| c.name == name) {
+        Some(camera) => camera,
+        None => return ("400 Bad Request", format!("No camera named '{}'", name)),
+    };
+    match &camera.api_token {
+        Some(expected) if authorization.or(token.as_deref()) == Some(expected.as_str()) => {}
+        Some(_) => return ("401 Unauthorized", "Missing or wrong token".to_string()),
+        None => {
+            return (
+                "403 Forbidden",
+                "Deleting recordings requires an api_token for the camera".to_string(),
+            )
+        }
+    }
+    let target = match (&segment, from, to) {
+        (Some(segment), _, _) => Target::Segment(segment),
+        (None, Some(from), Some(to)) if to > from => Target::Range(from, to),
+        (None, Some(_), Some(_)) => {
+            return ("400 Bad Request", "to must be after from".to_string())
+        }
+        _ => return ("400 Bad Request", "Missing from/to or segment".to_string()),
+    };
+    match deletion::delete(camera, target, &user, &reason) {
+        Ok(deleted) => (
+            "200 OK",
+            format!(
+                "Deleted {} segment(s), {} bytes of {}",
+                deleted.segments, deleted.bytes, camera.name
+            ),
+        ),
+        Err(e @ deletion::Error::Invalid(_)) => ("400 Bad Request", e.to_string()),
+        Err(e @ deletion::Error::Held(_)) => ("409 Conflict", e.to_string()),
+        Err(e @ deletion::Error::Failed(_)) => ("500 Internal Server Error", e.to_string()),
+    }
+}
+
+struct Request<'a> {
+    camera: &'a CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    exact: bool,
+    token: Option<String>,
+}
+
+fn locate<'a>(cameras: &'a [CameraConfig], query: &str) -> Result<Request<'a>, String> {
+    let mut camera = None;
+    let mut from = None;
+    let mut to = None;
+    let mut token = None;
+    let mut exact = None;
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode(value);
+        match key {
+            "camera" => camera = Some(value),
+            "from" => from = Some(parse_time(&value)?),
+            "to" => to = Some(parse_time(&value)?),
+            "token" => token = Some(value),
+            "exact" => exact = Some(value == "1" || value == "true"),
+            _ => {}
+        }
+    }
+    let name = camera.ok_or("Missing camera")?;
+    let camera = cameras
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Err("Missing from or to".to_string()),
+    };
+    match (to - from).to_std() {
+        Ok(length) if !length.is_zero() && length <= MAX_CLIP => {}
+        _ => {
+            return Err(format!(
+                "to must be after from and at most {} minutes later",
+                MAX_CLIP.as_secs() / 60
+            ))
+        }
+    }
+    Ok(Request {
+        camera,
+        from,
+        to,
+        exact: exact.unwrap_or(camera.clip_exact),
+        token,
+    })
+}
+
+// 返回片段文件；时段已经结束的片段缓存起来，还在录制的时段每次重新剪辑
+fn prepare(request: &Request) -> Result<PathBuf, String> {
+    let dir = std::env::temp_dir().join("save_rtsp_clips");
+    let name = format!(
+        "{}_{}_{}{}.mp4",
+        request.camera.name,
+        request.from.format("%Y%m%d_%H%M%S"),
+        request.to.format("%Y%m%d_%H%M%S"),
+        if request.exact { "_exact" } else { "" }
+    );
+    let path = dir.join(&name);
+    let finished = request.to < chrono::Local::now().naive_local();
+    // 缓存命中时不必等其他片段
+    if finished && path.exists() {
+        return Ok(path);
+    }
+    let lock = {
+        let mut assembling = ASSEMBLING.lock().unwrap();
+        match assembling.iter().find(|(n, _)| *n == name) {
+            Some((_, lock)) => lock.clone(),
+            None => {
+                let lock = Arc::new(Mutex::new(()));
+                assembling.push((name.clone(), lock.clone()));
+                lock
+            }
+        }
+    };
+    let result = {
+        let _guard = lock.lock().unwrap();
+        sweep(&dir);
+        // 等待期间同一片段可能已经剪好
+        if finished && path.exists() {
+            Ok(())
+        } else {
+            clip::assemble(
+                request.camera,
+                request.from,
+                request.to,
+                request.exact,
+                &path,
+            )
+        }
+    };
+    // 没有其他请求在等这个片段时移除它的锁（列表和这里各持有一份）
+    ASSEMBLING
+        .lock()
+        .unwrap()
+        .retain(|(n, l)| *n != name || Arc::strong_count(l) > 2);
+    result.map(|_| path)
+}
+
+fn sweep(dir: &Path) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .is_ok_and(|t| t.elapsed().unwrap_or_default() > CACHE_TTL);
+        if expired {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+// POST /streams：请求体是一行配置文件里的摄像头，如 rtsp://... name=gate tags=outdoor。
+// 依次检查配置、探测（最多 PROBE_TIMEOUT）、写入配置文件，然后开始录制
+fn add_stream(server: &Server, token: Option<&str>, line: &str) -> (&'static str, String) {
+    let admin = match &server.admin {
+        Some(admin) => admin,
+        None => {
+            return (
+                "404 Not Found",
+                "Adding cameras needs admin_token".to_string(),
+            )
+        }
+    };
+    if token != Some(admin.token.as_str()) {
+        return ("401 Unauthorized", "Missing or wrong token".to_string());
+    }
+    let _guard = ADDING.lock().unwrap();
+    let cameras = match config::check_camera(&admin.config_path, line) {
+        Ok(cameras) if !cameras.is_empty() => cameras,
+        Ok(_) => return ("400 Bad Request", "No new camera".to_string()),
+        Err(e) => return ("400 Bad Request", e),
+    };
+    let detected = match probe(&cameras[0]) {
+        Ok(detected) => detected,
+        Err(e) => return ("422 Unprocessable Entity", format!("Probe failed: {}", e)),
+    };
+    if let Err(e) = config::append_camera(&admin.config_path, line) {
+        return ("500 Internal Server Error", e);
+    }
+    info!("[HTTP] Added camera {} ({})", cameras[0].name, detected);
+    server
+        .cameras
+        .lock()
+        .unwrap()
+        .extend(cameras.iter().cloned());
+    let name = cameras[0].name.clone();
+    (admin.start)(cameras);
+    ("201 Created", format!("Recording {}: {}", name, detected))
+}
+
+// 连接摄像头并返回视频编码和分辨率，如 h264 1920x1080
+fn probe(camera: &CameraConfig) -> Result<String, String> {
+    let options = input::Options {
+        probesize: camera.probesize,
+        analyzeduration: camera.analyzeduration,
+        avoptions: &camera.avoptions,
+        ..Default::default()
+    };
+    let source = input::open(
+        &camera.url,
+        &options,
+        &Watchdog::new(Some(PROBE_TIMEOUT)),
+        || false,
+    )?;
+    let video_stream_index = source.best(ffmpeg::media::Type::Video)?;
+    let parameters = source
+        .context
+        .stream(video_stream_index)
+        .map(|stream| stream.parameters())
+        .ok_or("No video stream found")?;
+    let (width, height) = unsafe {
+        let raw = parameters.as_ptr();
+        ((*raw).width, (*raw).height)
+    };
+    Ok(format!("{} {}x{}", parameters.id().name(), width, height))
+}
+
+fn send_file(
+    stream: &mut TcpStream,
+    path: &Path,
+    range: Option<&str>,
+    head: bool,
+) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let (status, start, end) = match range.and_then(|r| parse_range(r, size)) {
+        Some((start, end)) => ("206 Partial Content", start, end),
+        None if range.is_some() && size > 0 => {
+            write!(
+                stream,
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\n\
+Content-Length: 0\r\nConnection: close\r\n\r\n",
+                size
+            )?;
+            return Ok(());
+        }
+        None => ("200 OK", 0, size.saturating_sub(1)),
+    };
+    let length = if size == 0 { 0 } else { end - start + 1 };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: video/mp4\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n",
+        status, length
+    )?;
+    if status.starts_with("206") {
+        write!(
+            stream,
+            "Content-Range: bytes {}-{}/{}\r\n",
+            start, end, size
+        )?;
+    }
+    write!(stream, "Connection: close\r\n\r\n")?;
+    if !head {
+        file.seek(SeekFrom::Start(start))?;
+        io::copy(&mut file.take(length), stream)?;
+    }
+    stream.flush()
+}
+
+// 只支持单个区间：bytes=a-b、bytes=a-、bytes=-n
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (size.checked_sub(suffix.min(size))?, size.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, size.checked_sub(1)?),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.min(size.checked_sub(1)?),
+        ),
+    };
+    (start <= end && end < size).then_some((start, end))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, message: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\
+Connection: close\r\n\r\n{}",
+        status,
+        message.len(),
+        message
+    )?;
+    stream.flush()
+}
+
+// 接受 2024-05-01T08:00:00 和 2024-05-01 08:00:00 两种写法
+fn parse_time(value: &str) -> Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(&value.replace('T', " "), "%Y-%m-%d %H:%M:%S")
+        .map_err(|_| format!("Invalid time '{}'", value))
+}
+
+// 查询参数的百分号编码，+ 表示空格
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
@@ -0,0 +1,310 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::NaiveDateTime;
+
+use crate::config::CameraConfig;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// 追加和整理索引互斥，避免整理时丢掉新追加的记录
+static LOCK: Mutex<()> = Mutex::new(());
+
+// hold、delete 等命令和子进程在别的进程里改写同一个索引，所以除了进程内的锁
+// 还要对 <name>.index.lock 加文件锁；两者在 Guard 释放时一起解除。
+// 锁文件跟随 index_path，子码流追加分段时锁的是主码流的索引
+struct Guard {
+    _local: MutexGuard<'static, ()>,
+    _file: File,
+}
+
+fn lock(camera: &CameraConfig) -> io::Result<Guard> {
+    let local = LOCK.lock().unwrap();
+    let mut path = index_path(camera).into_os_string();
+    path.push(".lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)?;
+    file.lock()?;
+    Ok(Guard {
+        _local: local,
+        _file: file,
+    })
+}
+
+// 每个摄像头一个索引文件 <output_dir>/<name>.index，每行一条以 tab 分隔的记录
+pub enum Record {
+    // 已完成的分段：开始、结束时间、字节数、路径；
+    // 子码流的分段记在主码流的索引里（substream 行），便于界面关联两路录像
+    Segment {
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        bytes: u64,
+        path: PathBuf,
+        substream: bool,
+    },
+    // 事件/书签：开始时间、持续秒数、来源
+    Event {
+        time: NaiveDateTime,
+        seconds: u64,
+        source: String,
+    },
+    // 保全（legal hold）：与该时段重叠的分段不会被清理或环形缓冲删除，直到解除
+    Hold {
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        reason: String,
+    },
+    // 注释：某一时刻的说明文字，如 "08:01 快递车进入"，列表和导出中显示在对应的分段旁
+    Note {
+        time: NaiveDateTime,
+        author: String,
+        text: String,
+    },
+    // 加密的分段及所用密钥的编号，轮换密钥后据此找出需要重新加密的文件
+    Encrypted {
+        path: PathBuf,
+        key: String,
+    },
+}
+
+impl Record {
+    pub fn to_line(&self) -> String {
+        match self {
+            Record::Segment {
+                start,
+                end,
+                bytes,
+                path,
+                substream,
+            } => format!(
+                "{}\t{}\t{}\t{}\t{}",
+                if *substream { "substream" } else { "segment" },
+                start.format(TIME_FORMAT),
+                end.format(TIME_FORMAT),
+                bytes,
+                path.display()
+            ),
+            Record::Event {
+                time,
+                seconds,
+                source,
+            } => format!(
+                "event\t{}\t{}\t{}",
+                time.format(TIME_FORMAT),
+                seconds,
+                source
+            ),
+            Record::Hold { from, to, reason } => format!(
+                "hold\t{}\t{}\t{}",
+                from.format(TIME_FORMAT),
+                to.format(TIME_FORMAT),
+                reason.replace(['\t', '\n'], " ")
+            ),
+            Record::Note { time, author, text } => format!(
+                "note\t{}\t{}\t{}",
+                time.format(TIME_FORMAT),
+                author.replace(['\t', '\n'], " "),
+                text.replace(['\t', '\n'], " ")
+            ),
+            Record::Encrypted { path, key } => format!("encrypted\t{}\t{}", key, path.display()),
+        }
+    }
+
+    // 不认识的记录类型返回 None，便于以后增加新类型
+    fn parse(line: &str) -> Option<Record> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields.as_slice() {
+            [kind @ ("segment" | "substream"), start, end, bytes, path] => Some(Record::Segment {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+                bytes: bytes.parse().ok()?,
+                path: PathBuf::from(path),
+                substream: *kind == "substream",
+            }),
+            ["event", time, seconds, source] => Some(Record::Event {
+                time: parse_time(time)?,
+                seconds: seconds.parse().ok()?,
+                source: source.to_string(),
+            }),
+            ["hold", from, to, reason] => Some(Record::Hold {
+                from: parse_time(from)?,
+                to: parse_time(to)?,
+                reason: reason.to_string(),
+            }),
+            ["note", time, author, text] => Some(Record::Note {
+                time: parse_time(time)?,
+                author: author.to_string(),
+                text: text.to_string(),
+            }),
+            ["encrypted", key, path] => Some(Record::Encrypted {
+                path: PathBuf::from(path),
+                key: key.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, TIME_FORMAT).ok()
+}
+
+pub fn index_path(camera: &CameraConfig) -> PathBuf {
+    let name = camera.substream_of.as_ref().unwrap_or(&camera.name);
+    camera.output_dir.join(format!("{}.index", name))
+}
+
+pub fn append(camera: &CameraConfig, record: &Record) -> io::Result<()> {
+    let _guard = lock(camera)?;
+    append_locked(camera, record)
+}
+
+fn append_locked(camera: &CameraConfig, record: &Record) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(index_path(camera))?;
+    file.write_all(format!("{}\n", record.to_line()).as_bytes())
+}
+
+pub fn load(camera: &CameraConfig) -> io::Result<Vec<Record>> {
+    let text = match fs::read_to_string(index_path(camera)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(text.lines().filter_map(Record::parse).collect())
+}
+
+// 只保留满足条件的记录（保留不认识的行），用临时文件替换保证原子性
+pub fn retain<F: Fn(&Record) -> bool>(camera: &CameraConfig, keep: F) -> io::Result<()> {
+    let _guard = lock(camera)?;
+    retain_locked(camera, keep)
+}
+
+fn retain_locked<F: Fn(&Record) -> bool>(camera: &CameraConfig, keep: F) -> io::Result<()> {
+    let path = index_path(camera);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut kept = String::new();
+    let mut removed = false;
+    for line in text.lines() {
+        if Record::parse(line).is_some_and(|r| !keep(&r)) {
+            removed = true;
+            continue;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    if !removed {
+        return Ok(());
+    }
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    fs::write(&tmp, kept)?;
+    fs::rename(&tmp, &path)
+}
+
+// 当前有效的保全时段
+pub fn holds(camera: &CameraConfig) -> io::Result<Vec<(NaiveDateTime, NaiveDateTime)>> {
+    Ok(load(camera)?
+        .into_iter()
+        .filter_map(|record| match record {
+            Record::Hold { from, to, .. } => Some((from, to)),
+            _ => None,
+        })
+        .collect())
+}
+
+pub fn is_held(
+    holds: &[(NaiveDateTime, NaiveDateTime)],
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> bool {
+    holds.iter().any(|(from, to)| start < *to && end > *from)
+}
+
+pub fn hold(
+    camera: &CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    reason: &str,
+) -> io::Result<()> {
+    append(
+        camera,
+        &Record::Hold {
+            from,
+            to,
+            reason: reason.to_string(),
+        },
+    )
+}
+
+// 解除与 [from, to) 重叠的保全，返回解除的个数
+pub fn release(camera: &CameraConfig, from: NaiveDateTime, to: NaiveDateTime) -> io::Result<usize> {
+    let overlaps = |record: &Record| matches!(record, Record::Hold { from: start, to: end, .. } if *start < to && *end > from);
+    let _guard = lock(camera)?;
+    let count = load(camera)?.iter().filter(|r| overlaps(r)).count();
+    retain_locked(camera, |record| !overlaps(record))?;
+    Ok(count)
+}
+
+pub fn annotate(
+    camera: &CameraConfig,
+    time: NaiveDateTime,
+    author: &str,
+    text: &str,
+) -> io::Result<()> {
+    append(
+        camera,
+        &Record::Note {
+            time,
+            author: author.to_string(),
+            text: text.to_string(),
+        },
+    )
+}
+
+// [from, to) 内的注释，按时间排序
+pub fn notes(
+    camera: &CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> io::Result<Vec<(NaiveDateTime, String, String)>> {
+    let mut notes: Vec<_> = load(camera)?
+        .into_iter()
+        .filter_map(|record| match record {
+            Record::Note { time, author, text } if time >= from && time < to => {
+                Some((time, author, text))
+            }
+            _ => None,
+        })
+        .collect();
+    notes.sort_by_key(|(time, ..)| *time);
+    Ok(notes)
+}
+
+// 记下分段现在用哪个密钥加密，替换之前的记录
+pub fn set_key(camera: &CameraConfig, path: &Path, key: &str) -> io::Result<()> {
+    // 去掉旧记录和追加新记录之间不能插入其他进程的改写
+    let _guard = lock(camera)?;
+    retain_locked(
+        camera,
+        |record| !matches!(record, Record::Encrypted { path: p, .. } if p == path),
+    )?;
+    append_locked(
+        camera,
+        &Record::Encrypted {
+            path: path.to_path_buf(),
+            key: key.to_string(),
+        },
+    )
+}
@@ -0,0 +1,202 @@
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::codec::Parameters;
+use ffmpeg::ffi;
+use ffmpeg::format::context::Input;
+use ffmpeg::Dictionary;
+
+// 读超时看门狗：超过 timeout 没有读到数据即视为连接已死
+#[derive(Clone)]
+pub struct Watchdog {
+    last_activity: Arc<Mutex<Instant>>,
+    timeout: Option<Duration>,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Option<Duration>) -> Watchdog {
+        Watchdog {
+            last_activity: Arc::new(Mutex::new(Instant::now())),
+            timeout,
+        }
+    }
+
+    // 每读到一个包调用一次
+    pub fn feed(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    pub fn expired(&self) -> bool {
+        self.timeout
+            .is_some_and(|timeout| self.last_activity.lock().unwrap().elapsed() >= timeout)
+    }
+
+    pub fn timeout_error(&self) -> String {
+        format!(
+            "No data for {} seconds",
+            self.timeout.unwrap_or_default().as_secs()
+        )
+    }
+}
+
+type Interrupt = Box<dyn Fn() -> bool + Send>;
+
+// 打开的输入；中断回调的闭包必须活得比输入上下文久，字段顺序保证先关闭输入
+pub struct Source {
+    pub context: Input,
+    // 是否套用了缓存的流参数
+    pub reused: bool,
+    // 输入格式不认识的 AVOption，通常是拼写错误
+    pub ignored: Vec<String>,
+    _interrupt: Box<Interrupt>,
+}
+
+impl Source {
+    // 各路流协商出的参数，下次重连时可作为 Options::cached 传入
+    pub fn parameters(&self) -> Vec<Parameters> {
+        self.context.streams().map(|s| s.parameters()).collect()
+    }
+
+    pub fn best(&self, kind: ffmpeg::media::Type) -> Result<usize, String> {
+        let name = match kind {
+            ffmpeg::media::Type::Video => "video",
+            ffmpeg::media::Type::Audio => "audio",
+            _ => "matching",
+        };
+        self.context
+            .streams()
+            .best(kind)
+            .map(|stream| stream.index())
+            .ok_or_else(|| format!("No {} stream found", name))
+    }
+}
+
+unsafe extern "C" fn interrupt_callback(opaque: *mut c_void) -> c_int {
+    let interrupt = &*(opaque as *const Interrupt);
+    interrupt() as c_int
+}
+
+#[derive(Default)]
+pub struct Options<'a> {
+    // 输入格式（如 alsa、pulse 等采集设备），None 时自动检测
+    pub format: Option<&'a str>,
+    // 上次连接得到的流参数；流的数量和编码一致时跳过耗时的 avformat_find_stream_info
+    pub cached: Option<&'a [Parameters]>,
+    // 探测流信息时最多读取的字节数和时长，None 为 ffmpeg 默认值（5MB、5 秒）
+    pub probesize: Option<u64>,
+    pub analyzeduration: Option<Duration>,
+    // 传给 avformat_open_input 的 AVOption，如 buffer_size、max_delay
+    pub avoptions: &'a [(String, String)],
+}
+
+// 打开输入并注册 ffmpeg 中断回调：stop 返回 true（按下 q、暂停）或看门狗超时时，
+// 阻塞在网络读写上的 ffmpeg 调用立即返回，不用等到下一个数据包
+pub fn open<F>(url: &str, options: &Options, watchdog: &Watchdog, stop: F) -> Result<Source, String>
+where
+    F: Fn() -> bool + Send + 'static,
+{
+    watchdog.feed();
+    let deadline = watchdog.clone();
+    let interrupt: Box<Interrupt> = Box::new(Box::new(move || stop() || deadline.expired()));
+    let path = CString::new(url).map_err(|_| format!("Invalid URL '{}'", url))?;
+    let format = match options.format {
+        Some(name) => Some(find_format(name)?),
+        None => None,
+    };
+    let mut dictionary = Dictionary::new();
+    if let Some(bytes) = options.probesize {
+        dictionary.set("probesize", &bytes.to_string());
+    }
+    if let Some(duration) = options.analyzeduration {
+        dictionary.set("analyzeduration", &duration.as_micros().to_string());
+    }
+    // 显式的 opt.probesize 等覆盖上面的设置
+    for (key, value) in options.avoptions {
+        dictionary.set(key, value);
+    }
+    let result = unsafe {
+        let mut dictionary = dictionary.disown();
+        let mut context = ffi::avformat_alloc_context();
+        (*context).interrupt_callback = ffi::AVIOInterruptCB {
+            callback: Some(interrupt_callback),
+            opaque: interrupt.as_ref() as *const Interrupt as *mut c_void,
+        };
+        // 失败时 avformat_open_input 会释放 context；用掉的选项从字典中移除
+        let code = ffi::avformat_open_input(
+            &mut context,
+            path.as_ptr(),
+            format.unwrap_or(ptr::null()),
+            &mut dictionary,
+        );
+        let ignored: Vec<String> = Dictionary::own(dictionary)
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .collect();
+        match code {
+            0 => {
+                // 之后出错时由 Input 关闭
+                let mut input = Input::wrap(context);
+                if options
+                    .cached
+                    .is_some_and(|cached| apply_cached(&mut input, cached))
+                {
+                    Ok((input, true, ignored))
+                } else {
+                    match ffi::avformat_find_stream_info(input.as_mut_ptr(), ptr::null_mut()) {
+                        code if code >= 0 => Ok((input, false, ignored)),
+                        code => Err(ffmpeg::Error::from(code)),
+                    }
+                }
+            }
+            code => Err(ffmpeg::Error::from(code)),
+        }
+    };
+    let (context, reused, ignored) = result.map_err(|e| {
+        if watchdog.expired() {
+            watchdog.timeout_error()
+        } else {
+            e.to_string()
+        }
+    })?;
+    Ok(Source {
+        context,
+        reused,
+        ignored,
+        _interrupt: interrupt,
+    })
+}
+
+// 摄像头的流没有变化时直接套用缓存的参数
+fn apply_cached(input: &mut Input, cached: &[Parameters]) -> bool {
+    let unchanged = input.streams().count() == cached.len()
+        && input
+            .streams()
+            .zip(cached)
+            .all(|(stream, parameters)| stream.parameters().id() == parameters.id());
+    if !unchanged {
+        return false;
+    }
+    for (index, parameters) in cached.iter().enumerate() {
+        if let Some(mut stream) = input.stream_mut(index) {
+            stream.set_parameters(parameters.clone());
+        }
+    }
+    true
+}
+
+fn find_format(name: &str) -> Result<*const ffi::AVInputFormat, String> {
+    // 采集设备的输入格式需要先注册
+    static DEVICES: Once = Once::new();
+    DEVICES.call_once(|| unsafe { ffi::avdevice_register_all() });
+    let short_name = CString::new(name).map_err(|_| format!("Invalid format '{}'", name))?;
+    let format = unsafe { ffi::av_find_input_format(short_name.as_ptr()) };
+    if format.is_null() {
+        return Err(format!("Unknown input format '{}'", name));
+    }
+    Ok(format)
+}
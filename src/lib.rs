@@ -0,0 +1,76 @@
+// 录制核心，二进制和下游 crate 共用；下游通过 plugin 模块接入自己的集成
+
+// 普通日志，用法同 println!
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::info(format_args!($($arg)*))
+    };
+}
+
+// 错误日志，用法同 eprintln!
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::error(format_args!($($arg)*))
+    };
+}
+
+pub mod alert;
+mod audio;
+mod avlog;
+pub mod backfill;
+pub mod bundle;
+pub mod bus;
+mod chacha20;
+mod clip;
+mod clock;
+mod cluster;
+pub mod config;
+pub mod control;
+mod coverage;
+mod decode;
+pub mod deletion;
+pub mod encryption;
+mod export;
+mod health;
+mod hooks;
+mod http;
+pub mod index;
+mod input;
+pub mod listing;
+mod log;
+mod migrate;
+pub mod mosaic;
+mod mqtt;
+mod mux;
+mod notify;
+mod ntp;
+mod onvif;
+mod otlp;
+pub mod plugin;
+mod post;
+pub mod preflight;
+mod pressure;
+mod preview;
+mod quality;
+mod queue;
+mod rate;
+pub mod recorder;
+mod retention;
+#[cfg(feature = "rockchip")]
+mod rockchip;
+mod script;
+mod segment;
+mod sha1;
+mod sha256;
+pub mod sla;
+mod snapshot;
+pub mod source;
+mod state;
+mod stats;
+mod storage;
+mod timestamps;
+pub mod trigger;
+mod upload;
+mod webhook;
+
+pub use plugin::RecorderPlugin;
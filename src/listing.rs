@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::config::CameraConfig;
+use crate::coverage;
+use crate::index::{self, Record};
+
+// 相邻分段间隔超过该秒数视为录像缺口
+const GAP_TOLERANCE_SECS: i64 = 2;
+
+// 打印某天的分段列表（时长、大小取自索引，不探测文件），并标出缺口
+pub fn print(camera: &CameraConfig, date: NaiveDate) -> Result<(), String> {
+    let day_start = date.and_hms_opt(0, 0, 0).unwrap();
+    let day_end = day_start + chrono::Duration::days(1);
+
+    let mut segments: Vec<(NaiveDateTime, NaiveDateTime, u64, PathBuf)> = index::load(camera)
+        .map_err(|e| format!("Failed to read index: {}", e))?
+        .into_iter()
+        .filter_map(|record| match record {
+            Record::Segment {
+                start,
+                end,
+                bytes,
+                path,
+                substream,
+            } if substream == camera.substream_of.is_some()
+                && start < day_end
+                && end > day_start
+                && path.exists() =>
+            {
+                Some((start, end, bytes, path))
+            }
+            _ => None,
+        })
+        .collect();
+    segments.sort_by_key(|(start, ..)| *start);
+    let holds = index::holds(camera).map_err(|e| format!("Failed to read index: {}", e))?;
+    // 注释显示在它所在的分段之后
+    let mut notes = index::notes(camera, day_start, day_end)
+        .map_err(|e| format!("Failed to read index: {}", e))?
+        .into_iter()
+        .peekable();
+
+    println!("{} on {}:", camera.name, date);
+    let mut covered = chrono::Duration::zero();
+    let mut previous_end = day_start;
+    for (start, end, bytes, path) in &segments {
+        while let Some(note) = notes.next_if(|(time, ..)| time < start) {
+            print_note(&note);
+        }
+        print_gap(previous_end, *start);
+        println!(
+            "  {}  {:>6}s  {:>9}  {}{}",
+            start.format("%H:%M:%S"),
+            (*end - *start).num_seconds(),
+            format_size(*bytes),
+            path.display(),
+            if index::is_held(&holds, *start, *end) {
+                "  [held]"
+            } else {
+                ""
+            }
+        );
+        covered = covered + (*end.min(&day_end) - *start.max(&day_start));
+        previous_end = previous_end.max(*end);
+    }
+    for note in notes {
+        print_note(&note);
+    }
+    // 今天还没结束的部分不算缺口
+    let now = chrono::Local::now().naive_local();
+    print_gap(previous_end, day_end.min(now));
+
+    println!(
+        "{} segment(s), {} recorded",
+        segments.len(),
+        format_duration(covered)
+    );
+    Ok(())
+}
+
+// 按小时打印某天的录像覆盖率
+pub fn print_coverage(camera: &CameraConfig, date: NaiveDate) -> Result<(), String> {
+    let day_start = date.and_hms_opt(0, 0, 0).unwrap();
+    let now = chrono::Local::now().naive_local();
+    println!("{} on {}:", camera.name, date);
+    for hour in 0..24 {
+        let from = day_start + chrono::Duration::hours(hour);
+        if from >= now {
+            break;
+        }
+        let to = (from + chrono::Duration::hours(1)).min(now);
+        let percent = coverage::percent(camera, from, to)
+            .map_err(|e| format!("Failed to read index: {}", e))?;
+        let notes = index::notes(camera, from, to)
+            .map_err(|e| format!("Failed to read index: {}", e))?
+            .len();
+        if notes > 0 {
+            println!(
+                "  {}  {:>5.1}%  {} note(s)",
+                from.format("%H:%M"),
+                percent,
+                notes
+            );
+        } else {
+            println!("  {}  {:>5.1}%", from.format("%H:%M"), percent);
+        }
+    }
+    let day_end = (day_start + chrono::Duration::days(1)).min(now);
+    let percent = coverage::percent(camera, day_start, day_end)
+        .map_err(|e| format!("Failed to read index: {}", e))?;
+    println!("{:.1}% recorded", percent);
+    Ok(())
+}
+
+fn print_note((time, author, text): &(NaiveDateTime, String, String)) {
+    if author.is_empty() {
+        println!("  {}  note: {}", time.format("%H:%M:%S"), text);
+    } else {
+        println!("  {}  note: {} ({})", time.format("%H:%M:%S"), text, author);
+    }
+}
+
+fn print_gap(from: NaiveDateTime, to: NaiveDateTime) {
+    if (to - from).num_seconds() > GAP_TOLERANCE_SECS {
+        println!(
+            "  -- gap {} - {} ({})",
+            from.format("%H:%M:%S"),
+            to.format("%H:%M:%S"),
+            format_duration(to - from)
+        );
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1024.0 / 1024.0)
+}
+
+fn format_duration(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds();
+    format!("{}h{:02}m{:02}s", secs / 3600, secs % 3600 / 60, secs % 60)
+}
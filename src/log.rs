@@ -0,0 +1,206 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+use crate::bus::{self, Event};
+use crate::config::LogFormat;
+
+// 管道模式下标准输出用来输出视频，普通日志改写到 stderr
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+// log_format=json：每条日志一行 JSON，便于 Loki/ELK 直接采集
+static JSON: AtomicBool = AtomicBool::new(false);
+// 日志中的流编号对应的摄像头名
+static NAMES: RwLock<Vec<(usize, String)>> = RwLock::new(Vec::new());
+
+pub fn use_stderr() {
+    TO_STDERR.store(true, Ordering::SeqCst);
+}
+
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::SeqCst);
+}
+
+pub fn name_stream(id: usize, camera: &str) {
+    NAMES.write().unwrap().push((id, camera.to_string()));
+}
+
+// info!/error! 和 ffmpeg 的日志都发布到事件总线，由总线调用 event 写出
+pub fn info(args: fmt::Arguments) {
+    publish("info", args);
+}
+
+pub fn error(args: fmt::Arguments) {
+    publish("error", args);
+}
+
+pub fn warn(args: fmt::Arguments) {
+    publish("warning", args);
+}
+
+fn publish(level: &'static str, args: fmt::Arguments) {
+    let message = args.to_string();
+    bus::publish(Event::Log {
+        camera: stream(&message)
+            .and_then(|(_, id)| camera_of(id))
+            .unwrap_or_default(),
+        level,
+        message,
+    });
+}
+
+fn write_info(message: &str) {
+    if JSON.load(Ordering::SeqCst) {
+        let line = json_line("info", message);
+        write_out(format_args!("{}", line));
+    } else {
+        write_out(format_args!("{}", message));
+    }
+}
+
+// 告警带上类型和摄像头，JSON 格式下作为独立字段
+fn alert(kind: &str, camera: &str, message: &str) {
+    if JSON.load(Ordering::SeqCst) {
+        let fields = [
+            ("level", "error"),
+            ("event", "alert"),
+            ("kind", kind),
+            ("camera", camera),
+            ("error", message),
+        ];
+        eprintln!("{}", to_json(&fields));
+    } else {
+        eprintln!("[Alert] {} {}: {}", kind, camera, message);
+    }
+}
+
+// 总线上的事件：日志原样写出，流状态和分段的开始、结束写成 "[Stream 3] ..." 的普通日志，
+// 告警单独格式
+pub(crate) fn event(event: &Event) {
+    match event {
+        Event::Log { level, message, .. } => match *level {
+            "info" => write_info(message),
+            level => to_stderr(level, format_args!("{}", message)),
+        },
+        Event::StreamState { camera, state, .. } => {
+            write_info(&format!("{} State: {}", prefix(camera), state))
+        }
+        Event::SegmentStarted { camera, path, .. } => {
+            write_info(&format!("{} Created {}", prefix(camera), path.display()))
+        }
+        Event::SegmentFinished {
+            camera,
+            path,
+            bytes,
+            ..
+        } => write_info(&format!(
+            "{} Finished {} ({} MB)",
+            prefix(camera),
+            path.display(),
+            bytes / 1_000_000
+        )),
+        Event::Alert {
+            camera,
+            kind,
+            message,
+        } => alert(kind, camera, message),
+        _ => {}
+    }
+}
+
+// 按摄像头名找回流编号，找不到时（如 pipe 以外的命令）用摄像头名
+fn prefix(camera: &str) -> String {
+    match NAMES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(_, name)| name == camera)
+    {
+        Some((id, _)) => format!("[Stream {}]", id),
+        None => format!("[Stream] {}:", camera),
+    }
+}
+
+fn to_stderr(level: &str, args: fmt::Arguments) {
+    if JSON.load(Ordering::SeqCst) {
+        eprintln!("{}", json_line(level, &args.to_string()));
+    } else {
+        eprintln!("{}", args);
+    }
+}
+
+fn write_out(args: fmt::Arguments) {
+    if TO_STDERR.load(Ordering::SeqCst) {
+        eprintln!("{}", args);
+    } else {
+        println!("{}", args);
+    }
+}
+
+// "[Stream 3] Created new file" 这样的日志的组件名和流编号
+fn stream(text: &str) -> Option<(&str, Option<usize>)> {
+    let (prefix, _) = text.strip_prefix('[')?.split_once("] ")?;
+    let (component, number) = prefix.split_once(' ').unwrap_or((prefix, ""));
+    Some((component, number.parse().ok()))
+}
+
+fn camera_of(id: Option<usize>) -> Option<String> {
+    let id = id?;
+    NAMES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(i, _)| *i == id)
+        .map(|(_, name)| name.clone())
+}
+
+// 把 "[Stream 3] Created new file" 这样的日志拆成 event、stream、camera 和正文
+fn json_line(level: &str, text: &str) -> String {
+    let message = match text.strip_prefix('[').and_then(|t| t.split_once("] ")) {
+        Some((_, message)) => message,
+        None => text,
+    };
+    let (event, stream) = match stream(text) {
+        Some((component, number)) => (component.to_lowercase(), number),
+        None => ("message".to_string(), None),
+    };
+    let camera = camera_of(stream);
+    let stream = stream.map(|id| id.to_string());
+    let mut fields = vec![("level", level), ("event", event.as_str())];
+    if let Some(stream) = &stream {
+        fields.push(("stream", stream));
+    }
+    if let Some(camera) = &camera {
+        fields.push(("camera", camera));
+    }
+    let key = if level == "info" { "message" } else { "error" };
+    fields.push((key, message));
+    to_json(&fields)
+}
+
+fn to_json(fields: &[(&str, &str)]) -> String {
+    let mut line = format!(
+        "{{\"ts\":\"{}\"",
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z")
+    );
+    for (key, value) in fields {
+        line.push_str(&format!(",\"{}\":\"{}\"", key, escape(value)));
+    }
+    line.push('}');
+    line
+}
+
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
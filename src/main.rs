@@ -1,138 +1,481 @@
-use std::fs::{self, File};
-use std::io::{self, BufRead, Write};
+use std::env;
+use std::fs;
 use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
-use std::thread;
-use std::time::{Duration, Instant};
 
 use ffmpeg_the_third as ffmpeg;
 
+use save_rtsp::config::{self, CameraConfig, Config};
+use save_rtsp::deletion::{self, Target};
+use save_rtsp::encryption;
+use save_rtsp::index::{self, Record};
+use save_rtsp::{backfill, bundle, listing, mosaic, preflight, recorder, sla};
+
+mod cli;
+mod supervisor;
+
+use cli::Args;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 初始化FFmpeg
-    ffmpeg::init()?;
+    let args = Args::parse(env::args().skip(1).collect())?;
+    // 直接给出地址时不需要配置文件
+    if args.command.as_deref() == Some("pipe") {
+        args.allow_with_target(&["camera"])?;
+        return pipe(&args);
+    }
+    // 导入时现有的配置文件可能还不存在或有错
+    if args.command.as_deref() == Some("config") {
+        return config_command(&args);
+    }
+    // 读取摄像头配置
+    let config = config::load(args.config_path())?;
 
-    // 读取RTSP URL列表
-    let urls = read_urls("rtsp.txt")?;
-
-    // 创建video文件夹
-    fs::create_dir_all("video")?;
-
-    // 创建一个原子布尔值来控制程序运行
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
-
-    // 创建一个线程来监听用户输入
-    let input_thread = thread::spawn(move || {
-        let mut input = String::new();
-        while input.trim() != "q" {
-            input.clear();
-            if let Ok(_) = io::stdin().read_line(&mut input) {
-                if input.trim() == "q" {
-                    r.store(false, Ordering::SeqCst);
-                    break;
-                }
+    match args.command.as_deref() {
+        None => {
+            args.allow(&[])?;
+            if config.isolate {
+                supervisor::run(config, args.config_path())
+            } else {
+                recorder::run(config)
             }
         }
-    });
-
-    // 为每个URL创建一个线程
-    let handles: Vec<_> = urls
-        .into_iter()
-        .enumerate()
-        .map(|(index, url)| {
-            let running = running.clone();
-            thread::spawn(move || process_stream(index, url, running))
-        })
-        .collect();
-
-    // 等待输入线程完成（即用户按下'q'）
-    input_thread.join().unwrap();
+        Some("worker") => {
+            args.allow(&["camera", "id"])?;
+            worker(&args, config)
+        }
+        Some("pull") => {
+            args.allow(&["camera", "from", "to", "url"])?;
+            pull(&args, config)
+        }
+        Some("list") => {
+            args.allow(&["camera", "date"])?;
+            list(&args, &config, listing::print)
+        }
+        Some("coverage") => {
+            args.allow(&["camera", "date"])?;
+            list(&args, &config, listing::print_coverage)
+        }
+        Some("export") => {
+            args.allow(&["camera", "from", "to", "output"])?;
+            export(&args, &config)
+        }
+        Some("mosaic") => {
+            args.allow(&["camera", "from", "to", "output", "width", "fps"])?;
+            mosaic(&args, &config)
+        }
+        Some("sla") => {
+            args.allow(&["camera", "month"])?;
+            sla_report(&args, &config)
+        }
+        Some("note") => {
+            args.allow(&["camera", "time", "text", "author"])?;
+            note(&args, &config)
+        }
+        Some("hold") => {
+            args.allow(&["camera", "from", "to", "segment", "reason"])?;
+            hold(&args, &config)
+        }
+        Some("release") => {
+            args.allow(&["camera", "from", "to"])?;
+            release(&args, &config)
+        }
+        Some("preflight") => {
+            args.allow(&["size"])?;
+            preflight_report(&args, &config)
+        }
+        Some("delete") => {
+            args.allow(&["camera", "from", "to", "segment", "reason", "user"])?;
+            delete(&args, &config)
+        }
+        Some("decrypt") => {
+            args.allow(&["camera", "segment", "output"])?;
+            decrypt(&args, &config)
+        }
+        Some("reencrypt") => {
+            args.allow(&["camera"])?;
+            reencrypt(&args, &config)
+        }
+        Some(other) => Err(format!("Unknown command '{}'", other).into()),
+    }
+}
 
-    println!("Stopping all streams...");
+fn pipe(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let camera = match (&args.target, args.get("camera")) {
+        (Some(url), None) => CameraConfig {
+            name: url.replace('/', "_"),
+            url: url.clone(),
+            ..CameraConfig::default()
+        },
+        (None, Some(name)) => config::load(args.config_path())?
+            .cameras
+            .into_iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format!("No camera named '{}'", name))?,
+        _ => return Err("pipe needs either a URL or --camera".into()),
+    };
+    recorder::pipe(camera)
+}
 
-    // 等待所有工作线程完成
-    for handle in handles {
-        handle.join().unwrap();
+// config export [--output 文件] / config import --from 文件
+fn config_command(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    match args.target.as_deref() {
+        Some("export") => {
+            args.allow_with_target(&["output"])?;
+            let text = config::export(args.config_path())?;
+            match args.get("output") {
+                Some(output) => {
+                    fs::write(output, text)?;
+                    eprintln!("Exported {} to {}", args.config_path(), output);
+                }
+                None => print!("{}", text),
+            }
+        }
+        Some("import") => {
+            args.allow_with_target(&["from"])?;
+            let from = args.require("from")?;
+            let config = config::import(from, args.config_path())?;
+            println!(
+                "Imported {} cameras from {} into {}",
+                config.cameras.len(),
+                from,
+                args.config_path()
+            );
+        }
+        _ => return Err("config needs export or import".into()),
     }
+    Ok(())
+}
 
-    println!("All streams stopped. Program exiting.");
+// 进程隔离模式下由主进程启动，只录制一个摄像头
+fn worker(args: &Args, mut config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let id = args
+        .require("id")?
+        .parse()
+        .map_err(|_| "Invalid value for --id".to_string())?;
+    config.cameras.retain(|c| c.name == name);
+    if config.cameras.is_empty() {
+        return Err(format!("No camera named '{}'", name).into());
+    }
+    recorder::run_worker(config, id)
+}
 
+fn list(
+    args: &Args,
+    config: &Config,
+    print: fn(&CameraConfig, chrono::NaiveDate) -> Result<(), String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let camera = config
+        .cameras
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    let date = match args.get("date") {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date))?,
+        None => chrono::Local::now().date_naive(),
+    };
+    print(camera, date)?;
     Ok(())
 }
 
-fn read_urls<P: AsRef<Path>>(path: P) -> io::Result<Vec<String>> {
-    let file = File::open(path)?;
-    let reader = io::BufReader::new(file);
-    reader.lines().collect()
+// 把一段时间的录像连同缩略图、元数据和清单复制到一个目录，交给第三方
+fn export(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let camera = config
+        .cameras
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    let from = args.time("from")?;
+    let to = args.time("to")?;
+    let output = Path::new(args.require("output")?);
+
+    // 初始化FFmpeg
+    ffmpeg::init()?;
+    let summary = bundle::export(
+        camera,
+        from,
+        to,
+        output,
+        config.export_sign_command.as_deref(),
+    )?;
+    println!(
+        "Exported {} segment(s), {} files, {} MB to {}{}",
+        summary.segments,
+        summary.files,
+        summary.bytes / 1_000_000,
+        output.display(),
+        if summary.signed {
+            ""
+        } else {
+            " (manifest not signed)"
+        }
+    );
+    Ok(())
 }
 
-fn process_stream(id: usize, url: String, running: Arc<AtomicBool>) {
-    println!("[Stream {}] Starting: {}", id, url);
-    while running.load(Ordering::SeqCst) {
-        match stream_to_file(id, &url, running.clone()) {
-            Ok(_) => println!("[Stream {}] Ended for {}", id, url),
-            Err(e) => eprintln!("[Stream {}] Error processing {}: {:?}", id, url, e),
+// 把几个摄像头同一时段的录像拼成一个宫格画面的 MP4；--camera 用逗号分隔，也可以是 @标签或 %租户
+fn mosaic(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cameras: Vec<&CameraConfig> = Vec::new();
+    for selector in args.require("camera")?.split(',') {
+        let matched: Vec<&CameraConfig> = config
+            .cameras
+            .iter()
+            .filter(|c| c.matches(selector))
+            .collect();
+        if matched.is_empty() {
+            return Err(format!("No camera matches '{}'", selector).into());
         }
-        if running.load(Ordering::SeqCst) {
-            println!("[Stream {}] Retrying {} in 5 seconds...", id, url);
-            thread::sleep(Duration::from_secs(5)); // 等待5秒后重试
+        for camera in matched {
+            // 拼接直接解码分段文件
+            if camera.encryption_keys.is_some() {
+                return Err(format!("Camera '{}' has encrypted recordings", camera.name).into());
+            }
+            if !cameras.iter().any(|c| c.name == camera.name) {
+                cameras.push(camera);
+            }
         }
     }
-    println!("[Stream {}] Stopped: {}", id, url);
+    let from = args.time("from")?;
+    let to = args.time("to")?;
+    if to <= from {
+        return Err("--to must be after --from".into());
+    }
+    let number = |key: &str, default: u32| -> Result<u32, String> {
+        args.get(key).map_or(Ok(default), |value| {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid value for --{}", key))
+        })
+    };
+    let layout = mosaic::Layout {
+        width: number("width", 1920)?,
+        fps: number("fps", 10)?,
+    };
+    let output = Path::new(args.require("output")?);
+
+    // 初始化FFmpeg
+    ffmpeg::init()?;
+    let frames = mosaic::export(&cameras, from, to, &layout, output)?;
+    println!(
+        "Wrote {} frames of {} camera(s) to {}",
+        frames,
+        cameras.len(),
+        output.display()
+    );
+    Ok(())
 }
 
-fn stream_to_file(id: usize, url: &str, running: Arc<AtomicBool>) -> Result<(), String> {
-    let mut ictx = ffmpeg::format::input(&url).map_err(|e| e.to_string())?;
-    let input = ictx
-        .streams()
-        .best(ffmpeg::media::Type::Video)
-        .ok_or_else(|| "No video stream found".to_string())?;
-    let video_stream_index = input.index();
+// 每月可用率：sla [--camera 名称/@标签/%租户] [--month YYYY-MM]，默认所有摄像头和本月
+fn sla_report(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let selector = args.get("camera");
+    let cameras: Vec<&CameraConfig> = config
+        .cameras
+        .iter()
+        .filter(|c| selector.is_none_or(|selector| c.matches(selector)))
+        .collect();
+    if cameras.is_empty() {
+        return Err(format!("No camera matches '{}'", selector.unwrap_or_default()).into());
+    }
+    let month = args
+        .get("month")
+        .map_or_else(sla::current_month, str::to_string);
+    sla::print_summary(&cameras, &month)?;
+    Ok(())
+}
 
-    let mut output_file =
-        create_output_file(url).map_err(|e| format!("Failed to create output file: {}", e))?;
-    let mut last_split = Instant::now();
+// 在某一时刻（默认现在）给摄像头加一条注释，list 和导出中可以看到
+fn note(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let camera = config
+        .cameras
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    let time = match args.get("time") {
+        Some(_) => args.time("time")?,
+        None => chrono::Local::now().naive_local(),
+    };
+    let text = args.require("text")?;
+    index::annotate(camera, time, args.get("author").unwrap_or_default(), text)?;
+    println!("Added note to {} at {}", camera.name, time);
+    Ok(())
+}
 
-    println!("[Stream {}] Started writing to file", id);
+// 保全一段时间或一个分段的录像，清理和环形缓冲不会删除，直到 release
+fn hold(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let camera = config
+        .cameras
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    let (from, to) = match args.get("segment") {
+        Some(segment) => index::load(camera)?
+            .into_iter()
+            .find_map(|record| match record {
+                Record::Segment {
+                    start, end, path, ..
+                } if path == Path::new(segment) => Some((start, end)),
+                _ => None,
+            })
+            .ok_or_else(|| format!("{} is not in the index of {}", segment, camera.name))?,
+        None => (args.time("from")?, args.time("to")?),
+    };
+    if to <= from {
+        return Err("--to must be after --from".into());
+    }
+    index::hold(camera, from, to, args.get("reason").unwrap_or_default())?;
+    println!("Holding {} from {} to {}", camera.name, from, to);
+    Ok(())
+}
 
-    for (stream, packet) in ictx.packets().filter_map(|r| r.ok()) {
-        if !running.load(Ordering::SeqCst) {
-            println!("[Stream {}] Stopping gracefully...", id);
-            break;
-        }
+// 解除与该时间段重叠的保全
+fn release(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let camera = config
+        .cameras
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    let count = index::release(camera, args.time("from")?, args.time("to")?)?;
+    println!("Released {} hold(s) of {}", count, camera.name);
+    Ok(())
+}
 
-        if stream.index() == video_stream_index {
-            if let Some(data) = packet.data() {
-                output_file
-                    .write_all(data)
-                    .map_err(|e| format!("Failed to write packet data: {}", e))?;
-            }
+// 测量各录像目录的写入速度并与摄像头所需的码率比较，--size 为写入的 MB 数
+fn preflight_report(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let size_mb = match args.get("size") {
+        Some(size) => size
+            .parse()
+            .map_err(|_| "Invalid value for --size".to_string())?,
+        None => config.preflight_mb.unwrap_or(32),
+    };
+    let reports = preflight::measure(&config.cameras, size_mb);
+    let mut insufficient = 0;
+    for report in &reports {
+        let measured = match &report.measured_kbps {
+            Ok(measured) => format!("{:.1} Mbit/s", *measured as f64 / 1000.0),
+            Err(e) => e.clone(),
+        };
+        let verdict = if report.sufficient() {
+            "ok"
+        } else {
+            insufficient += 1;
+            "INSUFFICIENT"
+        };
+        println!(
+            "{}: {}, {} camera(s) need {:.1} Mbit/s: {}",
+            report.dir.display(),
+            measured,
+            report.cameras.len(),
+            report.required_kbps as f64 / 1000.0,
+            verdict
+        );
+    }
+    if insufficient > 0 {
+        return Err(format!(
+            "{} of {} directories are too slow",
+            insufficient,
+            reports.len()
+        )
+        .into());
+    }
+    Ok(())
+}
 
-            if last_split.elapsed() >= Duration::from_secs(300) {
-                // 5分钟
-                output_file
-                    .flush()
-                    .map_err(|e| format!("Failed to flush file: {}", e))?;
-                output_file = create_output_file(url)
-                    .map_err(|e| format!("Failed to create new output file: {}", e))?;
-                last_split = Instant::now();
-                println!("[Stream {}] Created new file", id);
+// 删除一段时间或一个分段的录像，保全中的拒绝删除，记入审计日志
+fn delete(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let camera = config
+        .cameras
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    let target = match args.get("segment") {
+        Some(segment) => Target::Segment(Path::new(segment)),
+        None => {
+            let (from, to) = (args.time("from")?, args.time("to")?);
+            if to <= from {
+                return Err("--to must be after --from".into());
             }
+            Target::Range(from, to)
         }
-    }
+    };
+    let user = match args.get("user") {
+        Some(user) => user.to_string(),
+        None => env::var("USER").unwrap_or_default(),
+    };
+    let deleted = deletion::delete(camera, target, &user, args.require("reason")?)
+        .map_err(|e| e.to_string())?;
+    println!(
+        "Deleted {} segment(s), {} MB of {}",
+        deleted.segments,
+        deleted.bytes / 1_000_000,
+        camera.name
+    );
+    Ok(())
+}
 
-    // 确保所有数据都写入磁盘
-    output_file
-        .flush()
-        .map_err(|e| format!("Failed to flush final file: {}", e))?;
-    println!("[Stream {}] Finished writing to file", id);
+// 用摄像头的密钥文件解密一个分段
+fn decrypt(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let camera = config
+        .cameras
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    let output = args.require("output")?;
+    let key = encryption::decrypt(
+        camera,
+        Path::new(args.require("segment")?),
+        Path::new(output),
+    )?;
+    println!("Decrypted to {} with key '{}'", output, key);
+    Ok(())
+}
 
+// 立即把旧密钥加密的和未加密的分段改用当前密钥，--camera 也可以是 @标签或 %租户
+fn reencrypt(args: &Args, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let selector = args.require("camera")?;
+    let cameras: Vec<&CameraConfig> = config
+        .cameras
+        .iter()
+        .filter(|c| c.matches(selector) && c.substream_of.is_none())
+        .collect();
+    if cameras.is_empty() {
+        return Err(format!("No camera matches '{}'", selector).into());
+    }
+    let running = AtomicBool::new(true);
+    for camera in cameras {
+        let count = encryption::reencrypt(camera, &running)?;
+        println!("Re-encrypted {} segment(s) of {}", count, camera.name);
+    }
     Ok(())
 }
 
-fn create_output_file(url: &str) -> io::Result<File> {
-    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-    let filename = format!("video/{}_{}.mp4", url.replace("/", "_"), timestamp);
-    File::create(filename)
+// 从支持回放的摄像头或 NVR 拉取指定的历史时间段，保存到该摄像头的录像目录
+fn pull(args: &Args, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let name = args.require("camera")?;
+    let mut camera = config
+        .cameras
+        .into_iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| format!("No camera named '{}'", name))?;
+    if let Some(url) = args.get("url") {
+        camera.replay_url = Some(url.to_string());
+    }
+    let from = args.time("from")?;
+    let to = args.time("to")?;
+
+    // 初始化FFmpeg
+    ffmpeg::init()?;
+    fs::create_dir_all(&camera.output_dir)?;
+    println!("Pulling {} from {} to {}", camera.name, from, to);
+    backfill::run(0, &camera, from, to, &Arc::new(AtomicBool::new(true)))?;
+    println!("Pull finished");
+    Ok(())
 }
@@ -0,0 +1,84 @@
+// 配置文件版本迁移。当前格式的文件有一行 version=N；没有这一行的按版本 1 处理，
+// 包括最早每行只写一个地址的 rtsp.txt。读到旧版本时依次执行迁移得到当前版本的文本，
+// 升级到新版本不需要手改配置；以后改名或改含义的设置在这里加一步迁移
+use crate::config;
+
+pub const CURRENT_VERSION: u32 = 2;
+
+// 第 n 项把版本 n+1 的文本迁移到版本 n+2
+const MIGRATIONS: &[fn(&str) -> String] = &[v1_to_v2];
+
+pub fn version(text: &str) -> Result<u32, String> {
+    for (n, line) in text.lines().enumerate() {
+        let value = match line.trim().strip_prefix("version=") {
+            Some(value) => value,
+            None => continue,
+        };
+        let version = value
+            .parse::<u32>()
+            .ok()
+            .filter(|v| *v > 0)
+            .ok_or_else(|| format!("line {}: Invalid config version '{}'", n + 1, value))?;
+        if version > CURRENT_VERSION {
+            return Err(format!(
+                "line {}: config version {} was written by a newer release, this one reads up to {}",
+                n + 1,
+                version,
+                CURRENT_VERSION
+            ));
+        }
+        return Ok(version);
+    }
+    Ok(1)
+}
+
+// 返回迁移到当前版本后的文本和原来的版本
+pub fn migrate(text: &str) -> Result<(String, u32), String> {
+    let from = version(text)?;
+    let mut text = text.to_string();
+    for migration in &MIGRATIONS[from as usize - 1..] {
+        text = migration(&text);
+    }
+    Ok((text, from))
+}
+
+// 版本 1 → 2：最早的格式每行原样是一个地址，含空格的地址在现在的格式下会被切成几段，
+// 这里给整行加上引号；其余行不变，最后写上版本号
+fn v1_to_v2(text: &str) -> String {
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            if is_legacy_url(trimmed) {
+                format!("\"{}\"", trimmed)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    set_version(&lines.join("\n"), 2)
+}
+
+// 含 :// 但按现在的格式切分不出 地址 key=value ... 的行
+fn is_legacy_url(line: &str) -> bool {
+    if line.starts_with('#') || line.contains('"') || !line.contains("://") {
+        return false;
+    }
+    match config::tokenize(line) {
+        Ok(tokens) => !config::is_url(&tokens[0]) || tokens[1..].iter().any(|t| !t.contains('=')),
+        Err(_) => true,
+    }
+}
+
+// 替换已有的 version 行，没有时加在最前面
+fn set_version(text: &str, version: u32) -> String {
+    let line = format!("version={}", version);
+    let mut lines: Vec<&str> = text.lines().collect();
+    match lines.iter().position(|l| l.trim().starts_with("version=")) {
+        Some(i) => lines[i] = &line,
+        None => lines.insert(0, &line),
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
@@ -0,0 +1,376 @@
+// 多画面导出：把几个摄像头同一时段的录像按时间对齐拼成宫格画面，重新编码为一个 MP4，
+// 回看一个事件时能同时看到各个角度。每个输出帧取各摄像头在该时刻之前最近的一帧，
+// 某个摄像头这时没有录像（断线、还没开始录）时该格为黑色
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::codec::context::Context;
+use ffmpeg::codec::Parameters;
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling::{self, Flags};
+use ffmpeg::{frame, Packet, Rational};
+
+use crate::clip::micros;
+use crate::config::CameraConfig;
+use crate::input::{self, Source, Watchdog};
+use crate::mux::{Muxer, StreamInfo};
+use crate::segment;
+
+// 超过该时长没有新画面时该格显示黑色
+const STALE: Duration = Duration::from_secs(2);
+
+pub struct Layout {
+    // 输出画面宽度，高度按 16:9 的格子和行数计算
+    pub width: u32,
+    pub fps: u32,
+}
+
+// 返回写出的帧数
+pub fn export(
+    cameras: &[&CameraConfig],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    layout: &Layout,
+    target: &Path,
+) -> Result<u64, String> {
+    if cameras.is_empty() {
+        return Err("No cameras selected".to_string());
+    }
+    if layout.fps == 0 {
+        return Err("fps must be at least 1".to_string());
+    }
+    let columns = (cameras.len() as f64).sqrt().ceil() as u32;
+    let rows = (cameras.len() as u32).div_ceil(columns);
+    let tile_width = (layout.width / columns) & !1;
+    let tile_height = (tile_width * 9 / 16) & !1;
+    if tile_width == 0 || tile_height == 0 {
+        return Err(format!("Width {} is too small", layout.width));
+    }
+    let (width, height) = (tile_width * columns, tile_height * rows);
+
+    let mut tiles = Vec::new();
+    for camera in cameras {
+        let segments = segment::list_segments(camera)
+            .map_err(|e| format!("Failed to list segments of {}: {}", camera.name, e))?;
+        tiles.push(Tile::new(
+            camera,
+            &segments,
+            from,
+            to,
+            tile_width,
+            tile_height,
+        ));
+    }
+    if tiles.iter().all(|tile| tile.segments.is_empty()) {
+        return Err(format!("No recordings between {} and {}", from, to));
+    }
+
+    let codec =
+        ffmpeg::encoder::find(ffmpeg::codec::Id::H264).ok_or("No H.264 encoder available")?;
+    let time_base = Rational::new(1, layout.fps as i32);
+    let mut encoder = Context::new_with_codec(codec)
+        .encoder()
+        .video()
+        .map_err(|e| e.to_string())?;
+    encoder.set_width(width);
+    encoder.set_height(height);
+    encoder.set_format(Pixel::YUV420P);
+    encoder.set_time_base(time_base);
+    encoder.set_frame_rate(Some((layout.fps as i32, 1)));
+    // MP4 需要在文件头里写参数集
+    encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+    let mut encoder = encoder
+        .open_as(codec)
+        .map_err(|e| format!("Failed to open H.264 encoder: {}", e))?;
+
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let mut tmp = target.as_os_str().to_owned();
+    tmp.push(".part");
+    let tmp = PathBuf::from(tmp);
+    let info = StreamInfo {
+        parameters: Parameters::from(&encoder),
+        time_base,
+    };
+    let mut muxer = Muxer::open(
+        &tmp,
+        Some("mp4"),
+        std::slice::from_ref(&info),
+        &[("movflags", "+faststart")],
+        &[],
+    )
+    .map_err(|e| format!("Failed to create {}: {}", tmp.display(), e))?;
+
+    let frames =
+        ((to - from).num_microseconds().unwrap_or(0) * layout.fps as i64 / 1_000_000).max(0) as u64;
+    let mut packet = Packet::empty();
+    for n in 0..frames {
+        let position = n as i64 * 1_000_000 / layout.fps as i64;
+        let mut picture = blank(width, height);
+        for (index, tile) in tiles.iter_mut().enumerate() {
+            if let Some(shown) = tile.at(position) {
+                let index = index as u32;
+                place(
+                    &mut picture,
+                    shown,
+                    (index % columns) * tile_width,
+                    (index / columns) * tile_height,
+                );
+            }
+        }
+        picture.set_pts(Some(n as i64));
+        encoder
+            .send_frame(&picture)
+            .map_err(|e| format!("Failed to encode frame: {}", e))?;
+        while encoder.receive_packet(&mut packet).is_ok() {
+            muxer
+                .write(0, std::mem::replace(&mut packet, Packet::empty()))
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    encoder
+        .send_eof()
+        .map_err(|e| format!("Failed to encode frame: {}", e))?;
+    while encoder.receive_packet(&mut packet).is_ok() {
+        muxer
+            .write(0, std::mem::replace(&mut packet, Packet::empty()))
+            .map_err(|e| e.to_string())?;
+    }
+    muxer.finish().map_err(|e| e.to_string())?;
+    drop(muxer);
+    std::fs::rename(&tmp, target)
+        .map_err(|e| format!("Failed to rename {}: {}", tmp.display(), e))?;
+    Ok(frames)
+}
+
+// 一个摄像头的画面：依次解码与时段重叠的分段，时间为距 from 的微秒数
+struct Tile<'a> {
+    camera: &'a CameraConfig,
+    segments: Vec<(PathBuf, NaiveDateTime)>,
+    from: NaiveDateTime,
+    size: (u32, u32),
+    reader: Option<Reader>,
+    // 正在显示的画面和下一帧，已缩放为格子大小
+    shown: Option<(i64, frame::Video)>,
+    next: Option<(i64, frame::Video)>,
+}
+
+struct Reader {
+    source: Source,
+    stream_index: usize,
+    time_base: Rational,
+    decoder: ffmpeg::decoder::Video,
+    scaler: Option<scaling::Context>,
+    // 分段开始距 from 的微秒数，以及分段第一个包的时间戳
+    offset: i64,
+    first: Option<i64>,
+    eof: bool,
+}
+
+impl<'a> Tile<'a> {
+    fn new(
+        camera: &'a CameraConfig,
+        segments: &[(PathBuf, NaiveDateTime)],
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        width: u32,
+        height: u32,
+    ) -> Tile<'a> {
+        let overlapping = segments
+            .iter()
+            .enumerate()
+            .filter(|(index, (_, started))| {
+                let next = segments.get(index + 1).map(|(_, next)| *next);
+                *started < to && next.is_none_or(|next| next > from)
+            })
+            .map(|(_, segment)| segment.clone())
+            .collect();
+        Tile {
+            camera,
+            segments: overlapping,
+            from,
+            size: (width, height),
+            reader: None,
+            shown: None,
+            next: None,
+        }
+    }
+
+    // position 时刻应显示的画面
+    fn at(&mut self, position: i64) -> Option<&frame::Video> {
+        loop {
+            if self.next.is_none() {
+                self.next = self.decode();
+                if self.next.is_none() {
+                    break;
+                }
+            }
+            match &self.next {
+                Some((next, _)) if *next <= position => self.shown = self.next.take(),
+                _ => break,
+            }
+        }
+        let stale = STALE.as_micros() as i64;
+        match &self.shown {
+            Some((shown, picture)) if position - shown <= stale => Some(picture),
+            _ => None,
+        }
+    }
+
+    // 下一帧；读不了的分段跳过
+    fn decode(&mut self) -> Option<(i64, frame::Video)> {
+        loop {
+            if self.reader.is_none() {
+                if self.segments.is_empty() {
+                    return None;
+                }
+                let (path, started) = self.segments.remove(0);
+                match Reader::open(&path, (started - self.from).num_microseconds().unwrap_or(0)) {
+                    Ok(reader) => self.reader = Some(reader),
+                    Err(e) => {
+                        error!(
+                            "[Mosaic] Skipping {} of {}: {}",
+                            path.display(),
+                            self.camera.name,
+                            e
+                        );
+                        continue;
+                    }
+                }
+            }
+            let reader = self.reader.as_mut()?;
+            match reader.frame(self.size) {
+                Ok(Some(frame)) => return Some(frame),
+                Ok(None) => self.reader = None,
+                Err(e) => {
+                    error!("[Mosaic] {}: {}", self.camera.name, e);
+                    self.reader = None;
+                }
+            }
+        }
+    }
+}
+
+impl Reader {
+    fn open(path: &Path, offset: i64) -> Result<Reader, String> {
+        let url = path.to_string_lossy();
+        let source = input::open(&url, &Default::default(), &Watchdog::new(None), || false)?;
+        let stream_index = source.best(ffmpeg::media::Type::Video)?;
+        let stream = source
+            .context
+            .stream(stream_index)
+            .ok_or("No video stream found")?;
+        let time_base = stream.time_base();
+        let decoder = Context::from_parameters(stream.parameters())
+            .and_then(|context| context.decoder().video())
+            .map_err(|e| format!("Failed to open decoder: {}", e))?;
+        Ok(Reader {
+            source,
+            stream_index,
+            time_base,
+            decoder,
+            scaler: None,
+            offset,
+            first: None,
+            eof: false,
+        })
+    }
+
+    // 解码出下一帧并缩放为 size，分段读完时返回 None
+    fn frame(&mut self, size: (u32, u32)) -> Result<Option<(i64, frame::Video)>, String> {
+        let mut decoded = frame::Video::empty();
+        loop {
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                let pts = match decoded.pts() {
+                    Some(pts) => pts,
+                    None => continue,
+                };
+                let position =
+                    self.offset + micros(pts - self.first.unwrap_or(pts), self.time_base);
+                return self
+                    .scale(&decoded, size)
+                    .map(|scaled| Some((position, scaled)));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            let item = self
+                .source
+                .context
+                .packets()
+                .next()
+                .map(|result| result.map(|(stream, packet)| (stream.index(), packet)));
+            match item {
+                Some(Ok((index, packet))) if index == self.stream_index => {
+                    if self.first.is_none() {
+                        self.first = packet.dts().or(packet.pts());
+                    }
+                    // 个别坏包不影响后面的画面
+                    let _ = self.decoder.send_packet(&packet);
+                }
+                Some(_) => {}
+                None => {
+                    let _ = self.decoder.send_eof();
+                    self.eof = true;
+                }
+            }
+        }
+    }
+
+    fn scale(&mut self, decoded: &frame::Video, size: (u32, u32)) -> Result<frame::Video, String> {
+        if self.scaler.is_none() {
+            let scaler = scaling::Context::get(
+                decoded.format(),
+                decoded.width(),
+                decoded.height(),
+                Pixel::YUV420P,
+                size.0,
+                size.1,
+                Flags::BILINEAR,
+            )
+            .map_err(|e| format!("Failed to scale frame: {}", e))?;
+            self.scaler = Some(scaler);
+        }
+        let mut scaled = frame::Video::empty();
+        self.scaler
+            .as_mut()
+            .ok_or("No scaler")?
+            .run(decoded, &mut scaled)
+            .map_err(|e| format!("Failed to scale frame: {}", e))?;
+        Ok(scaled)
+    }
+}
+
+// 黑色的 YUV420P 画面
+fn blank(width: u32, height: u32) -> frame::Video {
+    let mut picture = frame::Video::new(Pixel::YUV420P, width, height);
+    picture.data_mut(0).fill(16);
+    picture.data_mut(1).fill(128);
+    picture.data_mut(2).fill(128);
+    picture
+}
+
+// 把 tile 复制到 picture 的 (x, y) 处，两者都是 YUV420P
+fn place(picture: &mut frame::Video, tile: &frame::Video, x: u32, y: u32) {
+    for plane in 0..3 {
+        // 色度平面的宽高和位置都是亮度的一半
+        let shift = if plane == 0 { 0 } else { 1 };
+        let (x, y) = ((x >> shift) as usize, (y >> shift) as usize);
+        let width = (tile.width() >> shift) as usize;
+        let height = (tile.height() >> shift) as usize;
+        let source_stride = tile.stride(plane);
+        let target_stride = picture.stride(plane);
+        let source = tile.data(plane);
+        let target = picture.data_mut(plane);
+        for row in 0..height {
+            let from = row * source_stride;
+            let to = (y + row) * target_stride + x;
+            target[to..to + width].copy_from_slice(&source[from..from + width]);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// MQTT：把总线上的事件发布到 <mqtt_topic>/<摄像头名>/<事件名>，正文为一行 JSON，QoS 0。
+// 只实现 MQTT 3.1.1 的 CONNECT/PUBLISH，不需要心跳（keepalive 为 0），断开后在下一个事件时重连
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::bus;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Broker {
+    address: String,
+    credentials: Option<(String, String)>,
+    client_id: String,
+}
+
+pub fn spawn(
+    broker: &str,
+    prefix: String,
+    events: Vec<String>,
+    running: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, String> {
+    let broker = Broker::parse(broker)?;
+    let mut connection: Option<TcpStream> = None;
+    Ok(bus::spawn("MQTT", running, move |event| {
+        if !events.iter().any(|name| name == event.name()) {
+            return Ok(());
+        }
+        let topic = format!(
+            "{}/{}/{}",
+            prefix,
+            event.camera().replace(['/', '+', '#'], "_"),
+            event.name()
+        );
+        let packet = publish_packet(&topic, event.to_json().as_bytes());
+        // 连接可能已被服务器关闭，写失败时重连一次
+        for attempt in 0..2 {
+            let stream = match &mut connection {
+                Some(stream) => stream,
+                None => connection.insert(broker.connect()?),
+            };
+            match stream.write_all(&packet) {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt == 0 => {
+                    info!("[MQTT] Reconnecting to {}: {}", broker.address, e);
+                    connection = None;
+                }
+                Err(e) => {
+                    connection = None;
+                    return Err(e.to_string());
+                }
+            }
+        }
+        Ok(())
+    }))
+}
+
+impl Broker {
+    // [mqtt://][user:pass@]host[:port]
+    fn parse(value: &str) -> Result<Broker, String> {
+        let rest = value.strip_prefix("mqtt://").unwrap_or(value);
+        let (credentials, host) = match rest.rsplit_once('@') {
+            Some((userinfo, host)) => (
+                Some(
+                    userinfo
+                        .split_once(':')
+                        .map(|(user, pass)| (user.to_string(), pass.to_string()))
+                        .unwrap_or_else(|| (userinfo.to_string(), String::new())),
+                ),
+                host,
+            ),
+            None => (None, rest),
+        };
+        let host = host.trim_end_matches('/');
+        if host.is_empty() {
+            return Err(format!("Invalid value for mqtt_broker: '{}'", value));
+        }
+        Ok(Broker {
+            address: if host.contains(':') {
+                host.to_string()
+            } else {
+                format!("{}:1883", host)
+            },
+            credentials,
+            client_id: format!("save_rtsp-{}", std::process::id()),
+        })
+    }
+
+    fn connect(&self) -> Result<TcpStream, String> {
+        let mut stream = TcpStream::connect(&self.address)
+            .map_err(|e| format!("Failed to connect to {}: {}", self.address, e))?;
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        // 协议名、版本 4、连接标志（clean session 及账号）、keepalive 0
+        let mut body = Vec::new();
+        put_string(&mut body, b"MQTT");
+        body.push(4);
+        body.push(match &self.credentials {
+            Some(_) => 0xc2,
+            None => 0x02,
+        });
+        body.extend_from_slice(&[0, 0]);
+        put_string(&mut body, self.client_id.as_bytes());
+        if let Some((user, pass)) = &self.credentials {
+            put_string(&mut body, user.as_bytes());
+            put_string(&mut body, pass.as_bytes());
+        }
+        stream
+            .write_all(&packet(0x10, &body))
+            .map_err(|e| e.to_string())?;
+        let mut ack = [0u8; 4];
+        stream.read_exact(&mut ack).map_err(|e| e.to_string())?;
+        match ack {
+            [0x20, 2, _, 0] => {
+                info!("[MQTT] Connected to {}", self.address);
+                Ok(stream)
+            }
+            [0x20, 2, _, code] => Err(format!("Broker refused the connection (code {})", code)),
+            _ => Err("Unexpected reply to CONNECT".to_string()),
+        }
+    }
+}
+
+fn publish_packet(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet(0x30, &body)
+}
+
+// 固定头：类型和标志、变长编码的剩余长度
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::codec::Parameters;
+use ffmpeg::format::context::Output;
+use ffmpeg::{Dictionary, Packet, Rational};
+
+// 一路待封装的流：编码参数和送入包的时间基
+#[derive(Clone)]
+pub struct StreamInfo {
+    pub parameters: Parameters,
+    pub time_base: Rational,
+}
+
+impl StreamInfo {
+    pub fn of(stream: &ffmpeg::format::Stream) -> StreamInfo {
+        StreamInfo {
+            parameters: stream.parameters(),
+            time_base: stream.time_base(),
+        }
+    }
+}
+
+// 把输入的若干路流原样（不重新编码）封装到文件或管道
+pub struct Muxer {
+    output: Output,
+    // 每路流的输入、输出时间基
+    time_bases: Vec<(Rational, Rational)>,
+}
+
+impl Muxer {
+    // format 为 None 时按扩展名选择封装格式，options 为封装器选项（如 movflags），
+    // metadata 为写进文件头的全局标签（如 comment）
+    pub fn open(
+        target: &Path,
+        format: Option<&str>,
+        streams: &[StreamInfo],
+        options: &[(&str, &str)],
+        metadata: &[(&str, &str)],
+    ) -> Result<Muxer, ffmpeg::Error> {
+        let mut output = match format {
+            Some(format) => ffmpeg::format::output_as(target, format)?,
+            None => ffmpeg::format::output(target)?,
+        };
+        for info in streams {
+            let mut stream = output.add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))?;
+            stream.set_parameters(info.parameters.clone());
+            stream.set_time_base(info.time_base);
+            // 让输出格式自己选择 codec tag，RTSP 源的 tag 不一定与容器兼容
+            let mut parameters = stream.parameters();
+            unsafe {
+                (*parameters.as_mut_ptr()).codec_tag = 0;
+            }
+        }
+        if !metadata.is_empty() {
+            let mut tags = Dictionary::new();
+            for (key, value) in metadata {
+                tags.set(key, value);
+            }
+            output.set_metadata(tags);
+        }
+        let mut dictionary = Dictionary::new();
+        for (key, value) in options {
+            dictionary.set(key, value);
+        }
+        output.write_header_with(dictionary)?;
+        // 写头之后封装器可能调整了时间基
+        let time_bases = streams
+            .iter()
+            .enumerate()
+            .map(|(index, info)| {
+                let output_time_base = output
+                    .stream(index)
+                    .map(|s| s.time_base())
+                    .unwrap_or(info.time_base);
+                (info.time_base, output_time_base)
+            })
+            .collect();
+        Ok(Muxer { output, time_bases })
+    }
+
+    pub fn write(&mut self, index: usize, mut packet: Packet) -> Result<(), ffmpeg::Error> {
+        let (input_time_base, output_time_base) = self.time_bases[index];
+        packet.rescale_ts(input_time_base, output_time_base);
+        packet.set_stream(index);
+        packet.set_position(-1);
+        packet.write_interleaved(&mut self.output)
+    }
+
+    pub fn finish(&mut self) -> Result<(), ffmpeg::Error> {
+        self.output.write_trailer()
+    }
+}
@@ -0,0 +1,64 @@
+// 桌面通知：在工作站上直接运行时，严重告警（掉线、停用、存储故障等）弹出系统通知。
+// Linux 用 libnotify 的 notify-send，macOS 用 osascript，Windows 用 PowerShell 发送 toast；
+// 文本通过环境变量传入，不拼接到命令里
+use std::process::Command;
+use std::thread;
+
+use crate::config::CameraConfig;
+
+// 值得打扰用户的告警类型
+const CRITICAL: &[&str] = &["down", "disabled", "storage", "pressure", "worker"];
+
+pub fn desktop(camera: &CameraConfig, kind: &str, message: &str) {
+    if !camera.desktop_notify || !CRITICAL.contains(&kind) {
+        return;
+    }
+    let mut command = command();
+    command
+        .env(
+            "ALERT_TITLE",
+            format!("save_rtsp: {} {}", kind, camera.name),
+        )
+        .env("ALERT_MESSAGE", message);
+    match command.spawn() {
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => error!("[Alert] Failed to show desktop notification: {}", e),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn command() -> Command {
+    const SCRIPT: &str = "\
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+$xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+$text = $xml.GetElementsByTagName('text'); \
+$text[0].AppendChild($xml.CreateTextNode($env:ALERT_TITLE)) > $null; \
+$text[1].AppendChild($xml.CreateTextNode($env:ALERT_MESSAGE)) > $null; \
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('save_rtsp').Show([Windows.UI.Notifications.ToastNotification]::new($xml))";
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT]);
+    command
+}
+
+#[cfg(target_os = "macos")]
+fn command() -> Command {
+    let mut command = Command::new("osascript");
+    command.args([
+        "-e",
+        "display notification (system attribute \"ALERT_MESSAGE\") with title (system attribute \"ALERT_TITLE\")",
+    ]);
+    command
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn command() -> Command {
+    // notify-send 不读环境变量，由 sh 展开
+    let mut command = Command::new("sh");
+    command.args([
+        "-c",
+        "exec notify-send --urgency=critical --app-name=save_rtsp \"$ALERT_TITLE\" \"$ALERT_MESSAGE\"",
+    ]);
+    command
+}
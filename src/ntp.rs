@@ -0,0 +1,106 @@
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chrono::NaiveDateTime;
+
+// NTP 时间戳从 1900 年开始计
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Debug)]
+pub struct NtpSample {
+    pub server: String,
+    // 系统时间与 NTP 时间的差值，正数表示系统时间落后
+    pub offset_ms: f64,
+    pub measured: NaiveDateTime,
+}
+
+static LATEST: Mutex<Option<NtpSample>> = Mutex::new(None);
+
+pub fn latest() -> Option<NtpSample> {
+    LATEST.lock().unwrap().clone()
+}
+
+// 定期查询 NTP 服务器，记录系统时钟偏差供分段元数据使用
+pub fn spawn(server: String, running: Arc<AtomicBool>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_query: Option<Instant> = None;
+        while running.load(Ordering::SeqCst) {
+            if last_query.is_none_or(|t| t.elapsed() >= REFRESH_INTERVAL) {
+                match query(&server) {
+                    Ok(offset_ms) => {
+                        info!("[NTP] Clock offset to {}: {:.1} ms", server, offset_ms);
+                        *LATEST.lock().unwrap() = Some(NtpSample {
+                            server: server.clone(),
+                            offset_ms,
+                            measured: chrono::Local::now().naive_local(),
+                        });
+                    }
+                    Err(e) => error!("[NTP] Failed to query {}: {}", server, e),
+                }
+                last_query = Some(Instant::now());
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    })
+}
+
+// SNTP 查询，返回毫秒偏差 ((t2 - t1) + (t3 - t4)) / 2
+fn query(server: &str) -> Result<f64, String> {
+    let addr = if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{}:123", server)
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .map_err(|e| e.to_string())?;
+
+    let mut request = [0u8; 48];
+    // LI = 0, VN = 4, Mode = 3 (client)
+    request[0] = 0x23;
+    let t1 = now_ntp();
+    request[40..48].copy_from_slice(&to_ntp_bytes(t1));
+    socket
+        .send_to(&request, addr.as_str())
+        .map_err(|e| e.to_string())?;
+
+    let mut response = [0u8; 48];
+    let (len, _) = socket.recv_from(&mut response).map_err(|e| e.to_string())?;
+    let t4 = now_ntp();
+    if len < 48 {
+        return Err("Short NTP response".to_string());
+    }
+    if response[0] & 0x07 != 4 {
+        return Err("Unexpected NTP mode".to_string());
+    }
+    let t2 = from_ntp_bytes(&response[32..40]);
+    let t3 = from_ntp_bytes(&response[40..48]);
+    Ok(((t2 - t1) + (t3 - t4)) / 2.0 * 1000.0)
+}
+
+fn now_ntp() -> f64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch.as_secs_f64() + NTP_UNIX_OFFSET
+}
+
+fn to_ntp_bytes(seconds: f64) -> [u8; 8] {
+    let secs = seconds.trunc() as u32;
+    let frac = (seconds.fract() * 4_294_967_296.0) as u32;
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&secs.to_be_bytes());
+    bytes[4..].copy_from_slice(&frac.to_be_bytes());
+    bytes
+}
+
+fn from_ntp_bytes(bytes: &[u8]) -> f64 {
+    let secs = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let frac = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    secs as f64 + frac as f64 / 4_294_967_296.0
+}
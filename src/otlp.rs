@@ -0,0 +1,288 @@
+// OpenTelemetry 导出：连接、分段的生命周期作为 trace，流量统计作为 metric，
+// 以 OTLP/HTTP JSON 发送到 otlp_endpoint（如 http://collector:4318）。
+// 作为插件接入（即事件总线的插件线程上的回调），不引入 HTTP 客户端依赖，只支持明文 http
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chrono::NaiveDateTime;
+
+use crate::config::CameraConfig;
+use crate::log::escape;
+use crate::plugin::{PacketStats, RecorderPlugin, SegmentInfo, StreamState};
+use crate::post;
+
+// 攒一批再发送
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+enum Item {
+    // 已序列化的 span 或 metric
+    Span(String),
+    Metric(String),
+}
+
+pub struct OtlpPlugin {
+    sender: Mutex<Sender<Item>>,
+    // 每个摄像头最近一次状态变化：(状态名, 开始时间, trace id)
+    states: Mutex<HashMap<String, (&'static str, u64, String)>>,
+    // 进行中的分段：路径 -> (开始时间, trace id, span id)
+    segments: Mutex<HashMap<PathBuf, (u64, String, String)>>,
+    started: u64,
+}
+
+impl OtlpPlugin {
+    pub fn new(endpoint: &str, service: &str) -> Result<OtlpPlugin, String> {
+        let address = endpoint
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("otlp_endpoint must start with http://: '{}'", endpoint))?
+            .trim_end_matches('/')
+            .to_string();
+        let (host, base) = match address.split_once('/') {
+            Some((host, path)) => (host.to_string(), format!("/{}", path)),
+            None => (address, String::new()),
+        };
+        let (sender, receiver) = mpsc::channel();
+        let resource = format!(
+            "{{\"attributes\":[{}]}}",
+            attribute("service.name", service)
+        );
+        thread::spawn(move || export(&host, &base, &resource, receiver));
+        Ok(OtlpPlugin {
+            sender: Mutex::new(sender),
+            states: Mutex::new(HashMap::new()),
+            segments: Mutex::new(HashMap::new()),
+            started: now_nanos(),
+        })
+    }
+
+    fn send(&self, item: Item) {
+        let _ = self.sender.lock().unwrap().send(item);
+    }
+}
+
+impl RecorderPlugin for OtlpPlugin {
+    fn on_segment_start(&self, segment: &SegmentInfo) {
+        let trace_id = random_id(16);
+        let span_id = random_id(8);
+        self.segments
+            .lock()
+            .unwrap()
+            .insert(segment.path.to_path_buf(), (now_nanos(), trace_id, span_id));
+    }
+
+    fn on_segment_close(&self, segment: &SegmentInfo) {
+        let (start, trace_id, span_id) = match self.segments.lock().unwrap().remove(segment.path) {
+            Some(entry) => entry,
+            None => (now_nanos(), random_id(16), random_id(8)),
+        };
+        let attributes = [
+            camera_attributes(segment.camera),
+            attribute("path", &segment.path.display().to_string()),
+            int_attribute("bytes", segment.bytes),
+        ];
+        self.send(Item::Span(span(
+            &trace_id,
+            &span_id,
+            "segment",
+            start,
+            &attributes,
+            None,
+        )));
+    }
+
+    // 每次状态变化结束上一个状态的 span：启动到首次连上为 connect，
+    // up 期间为 connection，down 期间为 reconnect
+    fn on_stream_state(&self, camera: &CameraConfig, state: &StreamState) {
+        let now = now_nanos();
+        let previous = self
+            .states
+            .lock()
+            .unwrap()
+            .insert(camera.name.clone(), (state.name(), now, random_id(16)));
+        let (name, start, trace_id) = previous.unwrap_or(("starting", self.started, random_id(16)));
+        let span_name = match name {
+            "up" => "connection",
+            "starting" => "connect",
+            "down" => "reconnect",
+            other => other,
+        };
+        let error = match state {
+            StreamState::Down(error) if name == "up" => Some(*error),
+            _ => None,
+        };
+        let attributes = [
+            camera_attributes(camera),
+            attribute("next_state", state.name()),
+        ];
+        self.send(Item::Span(span(
+            &trace_id,
+            &random_id(8),
+            span_name,
+            start,
+            &attributes,
+            error,
+        )));
+    }
+
+    fn on_packet_stats(&self, camera: &CameraConfig, stats: &PacketStats) {
+        let now = now_nanos();
+        let start = now.saturating_sub(stats.interval.as_nanos() as u64);
+        let attributes = camera_attributes(camera);
+        self.send(Item::Metric(format!(
+            "{{\"name\":\"save_rtsp.bitrate\",\"unit\":\"kbit/s\",\"gauge\":{{\"dataPoints\":[\
+{{\"asDouble\":{:.1},\"timeUnixNano\":\"{}\",\"attributes\":[{}]}}]}}}}",
+            stats.bitrate_kbps, now, attributes
+        )));
+        for (name, value) in [
+            ("save_rtsp.packets", stats.packets),
+            ("save_rtsp.drops", stats.drops),
+            ("save_rtsp.reconnects", stats.reconnects),
+        ] {
+            // 每个统计周期的增量
+            self.send(Item::Metric(format!(
+                "{{\"name\":\"{}\",\"sum\":{{\"aggregationTemporality\":1,\"isMonotonic\":true,\
+\"dataPoints\":[{{\"asInt\":\"{}\",\"startTimeUnixNano\":\"{}\",\"timeUnixNano\":\"{}\",\
+\"attributes\":[{}]}}]}}}}",
+                name, value, start, now, attributes
+            )));
+        }
+    }
+
+    fn on_coverage(&self, camera: &CameraConfig, _hour: NaiveDateTime, percent: f64) {
+        self.send(Item::Metric(format!(
+            "{{\"name\":\"save_rtsp.coverage\",\"unit\":\"%\",\"gauge\":{{\"dataPoints\":[\
+{{\"asDouble\":{:.1},\"timeUnixNano\":\"{}\",\"attributes\":[{}]}}]}}}}",
+            percent,
+            now_nanos(),
+            camera_attributes(camera)
+        )));
+    }
+}
+
+fn span(
+    trace_id: &str,
+    span_id: &str,
+    name: &str,
+    start: u64,
+    attributes: &[String],
+    error: Option<&str>,
+) -> String {
+    let status = match error {
+        Some(message) => format!("{{\"code\":2,\"message\":\"{}\"}}", escape(message)),
+        None => "{\"code\":1}".to_string(),
+    };
+    format!(
+        "{{\"traceId\":\"{}\",\"spanId\":\"{}\",\"name\":\"{}\",\"kind\":1,\
+\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[{}],\"status\":{}}}",
+        trace_id,
+        span_id,
+        name,
+        start,
+        now_nanos(),
+        attributes.join(","),
+        status
+    )
+}
+
+// 摄像头名，属于租户的摄像头再加上 tenant，便于按客户或楼宇区分指标
+fn camera_attributes(camera: &CameraConfig) -> String {
+    match &camera.tenant {
+        Some(tenant) => format!(
+            "{},{}",
+            attribute("camera", &camera.name),
+            attribute("tenant", tenant)
+        ),
+        None => attribute("camera", &camera.name),
+    }
+}
+
+fn attribute(key: &str, value: &str) -> String {
+    format!(
+        "{{\"key\":\"{}\",\"value\":{{\"stringValue\":\"{}\"}}}}",
+        key,
+        escape(value)
+    )
+}
+
+fn int_attribute(key: &str, value: u64) -> String {
+    format!(
+        "{{\"key\":\"{}\",\"value\":{{\"intValue\":\"{}\"}}}}",
+        key, value
+    )
+}
+
+// 后台线程：每 FLUSH_INTERVAL 把攒下的 span 和 metric 各发一次，失败的批次丢弃
+fn export(host: &str, base: &str, resource: &str, receiver: Receiver<Item>) {
+    let mut spans = Vec::new();
+    let mut metrics = Vec::new();
+    let mut last_flush = Instant::now();
+    loop {
+        let closed = match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(Item::Span(span)) => {
+                spans.push(span);
+                false
+            }
+            Ok(Item::Metric(metric)) => {
+                metrics.push(metric);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if !closed && last_flush.elapsed() < FLUSH_INTERVAL {
+            continue;
+        }
+        last_flush = Instant::now();
+        let scope = "{\"name\":\"save_rtsp\"}";
+        if !spans.is_empty() {
+            let body = format!(
+                "{{\"resourceSpans\":[{{\"resource\":{},\"scopeSpans\":[{{\"scope\":{},\"spans\":[{}]}}]}}]}}",
+                resource,
+                scope,
+                spans.join(",")
+            );
+            spans.clear();
+            if let Err(e) = post::json(host, &format!("{}/v1/traces", base), &body) {
+                error!("[OTLP] Failed to export traces: {}", e);
+            }
+        }
+        if !metrics.is_empty() {
+            let body = format!(
+                "{{\"resourceMetrics\":[{{\"resource\":{},\"scopeMetrics\":[{{\"scope\":{},\"metrics\":[{}]}}]}}]}}",
+                resource,
+                scope,
+                metrics.join(",")
+            );
+            metrics.clear();
+            if let Err(e) = post::json(host, &format!("{}/v1/metrics", base), &body) {
+                error!("[OTLP] Failed to export metrics: {}", e);
+            }
+        }
+        if closed {
+            break;
+        }
+    }
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+// trace/span id 只需要不重复，用随机种子的哈希生成即可
+fn random_id(bytes: usize) -> String {
+    let mut id = String::new();
+    while id.len() < bytes * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(now_nanos());
+        id.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    id.truncate(bytes * 2);
+    id
+}
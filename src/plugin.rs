@@ -0,0 +1,195 @@
+// 插件接口：下游 crate 实现 RecorderPlugin 并在 recorder::run 之前调用 register，
+// 即可在分段开始/结束、流状态变化和统计周期时收到回调，无需修改本仓库。
+// 插件是事件总线的一个订阅者：回调在插件线程中按事件顺序执行，不占用录制线程，
+// 只有需要返回 .meta 条目的 segment_metadata 在分段结束时同步调用
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use crate::bus::{self, Event};
+use crate::config::CameraConfig;
+
+static PLUGINS: RwLock<Vec<Arc<dyn RecorderPlugin>>> = RwLock::new(Vec::new());
+
+pub struct SegmentInfo<'a> {
+    pub camera: &'a CameraConfig,
+    pub path: &'a Path,
+    pub start: NaiveDateTime,
+    // 分段开始时为 None
+    pub end: Option<NaiveDateTime>,
+    pub bytes: u64,
+}
+
+pub enum StreamState<'a> {
+    // 已连接并开始写入
+    Up,
+    // 连接失败或中断，附带错误信息
+    Down(&'a str),
+    // 被 pause 命令暂停
+    Paused,
+    // 长时间连不上，已停止重试
+    Disabled,
+}
+
+impl StreamState<'_> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamState::Up => "up",
+            StreamState::Down(_) => "down",
+            StreamState::Paused => "paused",
+            StreamState::Disabled => "disabled",
+        }
+    }
+}
+
+// 一个统计周期内的计数，与 <name>_stats.csv 中的一行对应
+pub struct PacketStats {
+    pub interval: Duration,
+    pub bitrate_kbps: f64,
+    pub packets: u64,
+    pub drops: u64,
+    pub reconnects: u64,
+}
+
+// 所有插件共用一个插件线程，耗时的工作应交给插件自己的线程
+pub trait RecorderPlugin: Send + Sync {
+    fn on_segment_start(&self, _segment: &SegmentInfo) {}
+
+    // 在录制线程中同步调用，返回的条目写入分段的 .meta
+    fn segment_metadata(&self, _segment: &SegmentInfo) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn on_segment_close(&self, _segment: &SegmentInfo) {}
+
+    fn on_stream_state(&self, _camera: &CameraConfig, _state: &StreamState) {}
+
+    fn on_packet_stats(&self, _camera: &CameraConfig, _stats: &PacketStats) {}
+
+    // 触发文件等产生的事件
+    fn on_event(&self, _camera: &CameraConfig, _source: &str) {}
+
+    // 事件结束后生成的片段（需要配置 clip_dir）
+    fn on_event_clip(&self, _camera: &CameraConfig, _source: &str, _clip: &Path) {}
+
+    // 每小时一次：hour 开始的一小时内有录像的百分比
+    fn on_coverage(&self, _camera: &CameraConfig, _hour: NaiveDateTime, _percent: f64) {}
+}
+
+pub fn register<P: RecorderPlugin + 'static>(plugin: P) {
+    PLUGINS.write().unwrap().push(Arc::new(plugin));
+}
+
+pub fn has_plugins() -> bool {
+    !PLUGINS.read().unwrap().is_empty()
+}
+
+// 先复制一份列表再回调，插件在回调中注册新插件也不会死锁
+fn each<F: FnMut(&dyn RecorderPlugin)>(mut f: F) {
+    let plugins = PLUGINS.read().unwrap().clone();
+    for plugin in plugins {
+        f(plugin.as_ref());
+    }
+}
+
+// 收集各插件为结束的分段提供的 .meta 条目
+pub(crate) fn segment_metadata(segment: &SegmentInfo) -> Vec<(String, String)> {
+    let mut metadata = Vec::new();
+    each(|p| metadata.extend(p.segment_metadata(segment)));
+    metadata
+}
+
+// 插件线程：把总线上的事件转成回调，camera 按名字找回摄像头配置（找不到时跳过）
+pub(crate) fn spawn<F>(camera: F, running: Arc<AtomicBool>) -> JoinHandle<()>
+where
+    F: Fn(&str) -> Option<CameraConfig> + Send + 'static,
+{
+    bus::spawn("Plugin", running, move |event| {
+        let config = match event {
+            Event::Alert { .. } | Event::Log { .. } => return Ok(()),
+            _ => match camera(event.camera()) {
+                Some(config) => config,
+                None => return Ok(()),
+            },
+        };
+        dispatch(&config, event);
+        Ok(())
+    })
+}
+
+fn dispatch(camera: &CameraConfig, event: &Event) {
+    match event {
+        Event::SegmentStarted { path, start, .. } => {
+            let segment = SegmentInfo {
+                camera,
+                path,
+                start: *start,
+                end: None,
+                bytes: 0,
+            };
+            each(|p| p.on_segment_start(&segment));
+        }
+        Event::SegmentFinished {
+            path,
+            start,
+            end,
+            bytes,
+            ..
+        } => {
+            let segment = SegmentInfo {
+                camera,
+                path,
+                start: *start,
+                end: Some(*end),
+                bytes: *bytes,
+            };
+            each(|p| p.on_segment_close(&segment));
+        }
+        Event::StreamState { state, detail, .. } => {
+            let state = match *state {
+                "up" => StreamState::Up,
+                "down" => StreamState::Down(detail.as_deref().unwrap_or("")),
+                "paused" => StreamState::Paused,
+                _ => StreamState::Disabled,
+            };
+            each(|p| p.on_stream_state(camera, &state));
+        }
+        Event::Stats {
+            interval,
+            bitrate_kbps,
+            packets,
+            drops,
+            reconnects,
+            ..
+        } => {
+            let stats = PacketStats {
+                interval: *interval,
+                bitrate_kbps: *bitrate_kbps,
+                packets: *packets,
+                drops: *drops,
+                reconnects: *reconnects,
+            };
+            each(|p| p.on_packet_stats(camera, &stats));
+        }
+        Event::Triggered { source, .. } => each(|p| p.on_event(camera, source)),
+        Event::Clip { source, path, .. } => each(|p| p.on_event_clip(camera, source, path)),
+        Event::Coverage { hour, percent, .. } => each(|p| p.on_coverage(camera, *hour, *percent)),
+        Event::Alert { .. } | Event::Log { .. } => {}
+    }
+}
+
+// 发布流状态变化
+pub(crate) fn stream_state(camera: &CameraConfig, state: StreamState) {
+    bus::publish(Event::StreamState {
+        camera: camera.name.clone(),
+        state: state.name(),
+        detail: match state {
+            StreamState::Down(error) => Some(error.to_string()),
+            _ => None,
+        },
+    });
+}
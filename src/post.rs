@@ -0,0 +1,43 @@
+// 明文 HTTP 的 JSON POST，供 OTLP 导出和 webhook 使用，不引入 HTTP 客户端依赖
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// host 可带端口（默认 80），返回 2xx 以外的状态时报错
+pub(crate) fn json(host: &str, path: &str, body: &str) -> Result<(), String> {
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let mut stream = TcpStream::connect(&address).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    )
+    .map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    let _ = stream.take(4096).read_to_end(&mut response);
+    let response = String::from_utf8_lossy(&response);
+    let status = response
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("");
+    if status.starts_with('2') {
+        Ok(())
+    } else {
+        Err(format!("HTTP status '{}'", status))
+    }
+}
@@ -0,0 +1,106 @@
+// 启动前的磁盘吞吐检查：在每个录像目录（含镜像目录）顺序写入并同步 preflight_mb，
+// 测出的写入速度低于写到该目录的各摄像头 expected_bitrate_kbps 之和的两倍时告警，
+// 在开始录制之前就发现 SD 卡、NAS 挂载等跟不上的存储
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::alert;
+use crate::config::CameraConfig;
+
+const CHUNK: usize = 1 << 20;
+// 要求的余量：分段收尾、清理、钩子和上传也要读写磁盘
+const HEADROOM: f64 = 2.0;
+
+pub struct Report<'a> {
+    pub dir: PathBuf,
+    // 写到该目录的摄像头，告警记在第一个上
+    pub cameras: Vec<&'a CameraConfig>,
+    // 所需的总码率，kbit/s
+    pub required_kbps: u64,
+    // 测得的写入速度，kbit/s
+    pub measured_kbps: Result<u64, String>,
+}
+
+impl Report<'_> {
+    pub fn sufficient(&self) -> bool {
+        self.measured_kbps
+            .as_ref()
+            .is_ok_and(|&measured| measured as f64 >= self.required_kbps as f64 * HEADROOM)
+    }
+}
+
+// 各目录的测量结果
+pub fn measure(cameras: &[CameraConfig], size_mb: u64) -> Vec<Report<'_>> {
+    let mut reports: Vec<Report> = Vec::new();
+    for camera in cameras {
+        for dir in std::iter::once(&camera.output_dir).chain(camera.mirror_dir.as_ref()) {
+            match reports.iter_mut().find(|r| r.dir == *dir) {
+                Some(report) => {
+                    report.cameras.push(camera);
+                    report.required_kbps += camera.expected_bitrate_kbps;
+                }
+                None => reports.push(Report {
+                    dir: dir.clone(),
+                    cameras: vec![camera],
+                    required_kbps: camera.expected_bitrate_kbps,
+                    measured_kbps: Ok(0),
+                }),
+            }
+        }
+    }
+    for report in &mut reports {
+        report.measured_kbps = throughput(&report.dir, size_mb)
+            .map_err(|e| format!("Failed to test writing to {}: {}", report.dir.display(), e));
+    }
+    reports
+}
+
+// 开始录制前调用：记录各目录的速度，不够时告警，不阻止录制
+pub fn run(cameras: &[CameraConfig], size_mb: u64) {
+    for report in measure(cameras, size_mb) {
+        match &report.measured_kbps {
+            Ok(measured) if report.sufficient() => info!(
+                "[Preflight] {} writes {:.1} Mbit/s, {} camera(s) need {:.1} Mbit/s",
+                report.dir.display(),
+                *measured as f64 / 1000.0,
+                report.cameras.len(),
+                report.required_kbps as f64 / 1000.0
+            ),
+            Ok(measured) => alert::raise(
+                report.cameras[0],
+                "storage",
+                &format!(
+                    "{} only writes {:.1} Mbit/s, but {} camera(s) need {:.1} Mbit/s ({}x headroom); recordings may be lost",
+                    report.dir.display(),
+                    *measured as f64 / 1000.0,
+                    report.cameras.len(),
+                    report.required_kbps as f64 / 1000.0,
+                    HEADROOM
+                ),
+            ),
+            Err(e) => error!("[Preflight] {}", e),
+        }
+    }
+}
+
+// 顺序写入 size_mb 并同步，返回 kbit/s
+fn throughput(dir: &Path, size_mb: u64) -> io::Result<u64> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(".preflight_probe");
+    let chunk = vec![0u8; CHUNK];
+    let started = Instant::now();
+    let result = (|| {
+        let mut file = File::create(&path)?;
+        for _ in 0..size_mb.max(1) {
+            file.write_all(&chunk)?;
+        }
+        file.sync_all()
+    })();
+    let elapsed = started.elapsed();
+    let _ = fs::remove_file(&path);
+    result?;
+    let bits = size_mb.max(1) as f64 * CHUNK as f64 * 8.0;
+    Ok((bits / elapsed.as_secs_f64().max(1e-6) / 1000.0) as u64)
+}
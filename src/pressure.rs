@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::alert;
+use crate::config::Priority;
+use crate::control::{StreamHandle, Throttle};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// 压力等级：0 正常，1 超过阈值，2 严重（剩余空间不足阈值一半或负载超过 1.5 倍）
+type Level = u8;
+
+pub struct Limits {
+    pub min_free_mb: Option<u64>,
+    pub max_load: Option<f64>,
+}
+
+// 周期检查录像目录剩余空间和系统负载，按优先级限制摄像头：
+//   等级 1：低优先级只写关键帧
+//   等级 2：低优先级暂停，普通优先级只写关键帧
+// 高优先级的摄像头始终完整录制；压力解除后自动恢复
+pub fn spawn(
+    limits: Limits,
+    registry: Arc<Mutex<Vec<Arc<StreamHandle>>>>,
+    running: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_check: Option<Instant> = None;
+        while running.load(Ordering::SeqCst) {
+            if last_check.is_none_or(|t| t.elapsed() >= CHECK_INTERVAL) {
+                // 包括运行中新增的摄像头
+                let streams = registry.lock().unwrap().clone();
+                check(&limits, &streams);
+                last_check = Some(Instant::now());
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    })
+}
+
+fn check(limits: &Limits, streams: &[Arc<StreamHandle>]) {
+    let cpu = limits.max_load.map_or(0, cpu_level);
+    // 同一目录只查询一次
+    let mut disks: HashMap<PathBuf, Level> = HashMap::new();
+    for handle in streams {
+        let camera = &handle.camera;
+        let disk = match limits.min_free_mb {
+            Some(min_free_mb) => *disks
+                .entry(camera.output_dir.clone())
+                .or_insert_with(|| disk_level(&camera.output_dir, min_free_mb)),
+            None => 0,
+        };
+        let level = cpu.max(disk);
+        let throttle = match (camera.priority, level) {
+            (Priority::High, _) | (_, 0) | (Priority::Normal, 1) => Throttle::Full,
+            (Priority::Low, 1) | (Priority::Normal, _) => Throttle::KeyframesOnly,
+            (Priority::Low, _) => Throttle::Paused,
+        };
+        let previous = handle.set_throttle(throttle);
+        if previous == throttle {
+            continue;
+        }
+        if throttle == Throttle::Full {
+            info!("[Pressure] {} back to full recording", camera.name);
+        } else {
+            alert::raise(
+                camera,
+                "pressure",
+                &format!(
+                    "Resource pressure (disk level {}, cpu level {}); {}",
+                    disk,
+                    cpu,
+                    match throttle {
+                        Throttle::Paused => "recording paused",
+                        _ => "recording keyframes only",
+                    }
+                ),
+            );
+        }
+    }
+}
+
+fn disk_level(dir: &Path, min_free_mb: u64) -> Level {
+    match free_mb(dir) {
+        Some(free) if free < min_free_mb / 2 => 2,
+        Some(free) if free < min_free_mb => 1,
+        Some(_) => 0,
+        None => {
+            error!("[Pressure] Failed to query free space of {}", dir.display());
+            0
+        }
+    }
+}
+
+fn cpu_level(max_load: f64) -> Level {
+    let cores = thread::available_parallelism().map_or(1, |n| n.get()) as f64;
+    match load_average() {
+        Some(load) if load / cores > max_load * 1.5 => 2,
+        Some(load) if load / cores > max_load => 1,
+        _ => 0,
+    }
+}
+
+// 用 df 查询剩余空间，避免引入额外依赖
+fn free_mb(dir: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    // 第二行第 4 列为可用 KB
+    let available: u64 = text
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(available / 1024)
+}
+
+// 1 分钟平均负载，仅 Linux 可用
+fn load_average() -> Option<f64> {
+    let text = fs::read_to_string("/proc/loadavg").ok()?;
+    text.split_whitespace().next()?.parse().ok()
+}
@@ -0,0 +1,98 @@
+// 长期预览存档：每隔 preview_interval 取一个关键帧追加到 <preview_dir>/<name>_<日期>.preview.mp4，
+// 完整录像只保留很短时间时，也能以很低的成本看到几个月的画面
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use chrono::NaiveDate;
+use ffmpeg_the_third::Packet;
+
+use crate::config::CameraConfig;
+
+const DATE_FORMAT: &str = "%Y%m%d";
+
+pub struct Preview {
+    dir: PathBuf,
+    // 当前写入的文件及其日期，跨天时切换
+    file: Option<(NaiveDate, File)>,
+    last_frame: Option<Instant>,
+}
+
+impl Preview {
+    pub fn new(camera: &CameraConfig) -> Option<Preview> {
+        let dir = camera.preview_dir.clone()?;
+        Some(Preview {
+            dir,
+            file: None,
+            last_frame: None,
+        })
+    }
+
+    pub fn write(&mut self, camera: &CameraConfig, packet: &Packet) -> io::Result<()> {
+        if !packet.is_key()
+            || self
+                .last_frame
+                .is_some_and(|t| t.elapsed() < camera.preview_interval)
+        {
+            return Ok(());
+        }
+        let data = match packet.data() {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+        let today = chrono::Local::now().date_naive();
+        if self.file.as_ref().is_none_or(|(date, _)| *date != today) {
+            fs::create_dir_all(&self.dir)?;
+            // 追加写入，重启后继续写当天的文件
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(file_name(camera, today)))?;
+            self.file = Some((today, file));
+        }
+        if let Some((_, file)) = &mut self.file {
+            file.write_all(data)?;
+        }
+        self.last_frame = Some(Instant::now());
+        Ok(())
+    }
+}
+
+fn file_name(camera: &CameraConfig, date: NaiveDate) -> String {
+    format!("{}_{}.preview.mp4", camera.name, date.format(DATE_FORMAT))
+}
+
+// 由清理线程调用，删除超过 preview_retention_days 的预览文件
+pub fn sweep(camera: &CameraConfig) {
+    let (dir, days) = match (&camera.preview_dir, camera.preview_retention_days) {
+        (Some(dir), Some(days)) => (dir, days),
+        _ => return,
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            error!("[Retention] Failed to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    let cutoff = (chrono::Local::now().naive_local() - chrono::Duration::days(days as i64)).date();
+    let prefix = format!("{}_", camera.name);
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let date = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|n| n.strip_suffix(".preview.mp4"))
+            .and_then(|d| NaiveDate::parse_from_str(d, DATE_FORMAT).ok());
+        // 当天结束后才算过期
+        if date.is_some_and(|date| date < cutoff) {
+            match fs::remove_file(&path) {
+                Ok(_) => info!("[Retention] Removed expired preview {}", path.display()),
+                Err(e) => error!("[Retention] Failed to remove {}: {}", path.display(), e),
+            }
+        }
+    }
+}
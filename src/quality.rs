@@ -0,0 +1,139 @@
+// 分段的录制质量：丢包、断档和重连的位置，分段结束时写进文件本身，
+// 不依赖旁边的 .meta 和索引也能看出这段录像是否完整。
+// MP4 分段写在 moov 的 comment 标签里（ffprobe 可见），裸流分段追加一个
+// user data unregistered SEI（H.264/H.265）
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use ffmpeg_the_third::codec::Id;
+
+// 超过该时长没有写入视频记为一次断档
+const GAP: Duration = Duration::from_secs(5);
+// SEI 的 UUID，用于识别本程序写入的统计
+const SEI_UUID: [u8; 16] = [
+    0x73, 0x61, 0x76, 0x65, 0x5f, 0x72, 0x74, 0x73, 0x70, 0x2d, 0x71, 0x75, 0x61, 0x6c, 0x69, 0x74,
+];
+
+pub struct Quality {
+    created: Instant,
+    last_write: Option<Instant>,
+    drops: u64,
+    // 距分段开始的毫秒数和断档时长（毫秒）
+    gaps: Vec<(u128, u128)>,
+    // 重连后继续写入的位置（距分段开始的毫秒数）
+    reconnects: Vec<u128>,
+}
+
+impl Quality {
+    pub fn new() -> Quality {
+        Quality {
+            created: Instant::now(),
+            last_write: None,
+            drops: 0,
+            gaps: Vec::new(),
+            reconnects: Vec::new(),
+        }
+    }
+
+    pub fn record_write(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_write.filter(|last| now - *last > GAP) {
+            self.gaps
+                .push(((last - self.created).as_millis(), (now - last).as_millis()));
+        }
+        self.last_write = Some(now);
+    }
+
+    pub fn record_drop(&mut self) {
+        self.drops += 1;
+    }
+
+    pub fn record_reconnect(&mut self) {
+        self.reconnects.push(self.created.elapsed().as_millis());
+    }
+
+    // 如 save_rtsp quality drops=2 gaps=61000+7200 reconnects=61000
+    pub fn describe(&self) -> String {
+        let gaps: Vec<String> = self
+            .gaps
+            .iter()
+            .map(|(at, length)| format!("{}+{}", at, length))
+            .collect();
+        let reconnects: Vec<String> = self.reconnects.iter().map(|at| at.to_string()).collect();
+        format!(
+            "save_rtsp quality drops={} gaps={} reconnects={}",
+            self.drops,
+            gaps.join(","),
+            reconnects.join(",")
+        )
+    }
+}
+
+// 分片 MP4 的 moov 在写头时就写出，之后不再改写，而统计要到分段结束才知道：
+// 写头时先放一个定长的 comment 占位，结束时在文件里原地替换，超出部分截断
+const MP4_COMMENT_LENGTH: usize = 1024;
+// moov 在文件开头，只在这个范围里查找占位
+const MP4_HEADER_SEARCH: u64 = 1 << 20;
+
+pub fn mp4_placeholder() -> String {
+    format!("{:<1$}", "save_rtsp quality pending", MP4_COMMENT_LENGTH)
+}
+
+pub fn fill_mp4_comment(path: &Path, text: &str) -> io::Result<()> {
+    let placeholder = mp4_placeholder();
+    let mut head = Vec::new();
+    File::open(path)?
+        .take(MP4_HEADER_SEARCH)
+        .read_to_end(&mut head)?;
+    let position = match head
+        .windows(placeholder.len())
+        .position(|window| window == placeholder.as_bytes())
+    {
+        Some(position) => position,
+        // 封装器没有写出注释
+        None => return Ok(()),
+    };
+    let mut comment = text.as_bytes()[..text.len().min(MP4_COMMENT_LENGTH)].to_vec();
+    comment.resize(MP4_COMMENT_LENGTH, b' ');
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(position as u64))?;
+    file.write_all(&comment)
+}
+
+// Annex B 格式的 SEI NAL，其他编码返回 None
+pub fn sei(codec: Id, text: &str) -> Option<Vec<u8>> {
+    let header: &[u8] = match codec {
+        Id::H264 => &[0x06],
+        // 后缀 SEI（类型 40），放在最后一帧之后
+        Id::HEVC => &[40 << 1, 0x01],
+        _ => return None,
+    };
+    let mut payload = SEI_UUID.to_vec();
+    payload.extend_from_slice(text.as_bytes());
+    // payloadType 5：user data unregistered
+    let mut rbsp = vec![5u8];
+    let mut size = payload.len();
+    while size >= 255 {
+        rbsp.push(0xff);
+        size -= 255;
+    }
+    rbsp.push(size as u8);
+    rbsp.extend_from_slice(&payload);
+    rbsp.push(0x80);
+
+    let mut nal = vec![0, 0, 0, 1];
+    nal.extend_from_slice(header);
+    // 防竞争：连续两个 0 之后的字节不大于 3 时插入 0x03
+    let mut zeros = 0;
+    for byte in rbsp {
+        if zeros >= 2 && byte <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+        nal.push(byte);
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+    }
+    Some(nal)
+}
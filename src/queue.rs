@@ -0,0 +1,166 @@
+// 分段的写入队列：录像目录所在的磁盘（或 NAS 镜像）卡住时，读流不跟着卡住，
+// 待写的数据也不会无限占用内存。队列满时按策略丢弃最旧的数据，或者等待写入跟上
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::config::CameraConfig;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    DropOldest,
+    Block,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub packets: Option<usize>,
+    pub bytes: Option<usize>,
+    pub policy: Policy,
+}
+
+impl Limits {
+    // 没有设置上限时不使用队列，直接写入
+    pub fn of(camera: &CameraConfig) -> Option<Limits> {
+        (camera.write_queue_packets.is_some() || camera.write_queue_bytes.is_some()).then_some(
+            Limits {
+                packets: camera.write_queue_packets,
+                bytes: camera.write_queue_bytes,
+                policy: if camera.write_queue_block {
+                    Policy::Block
+                } else {
+                    Policy::DropOldest
+                },
+            },
+        )
+    }
+
+    fn exceeded(&self, packets: usize, bytes: usize) -> bool {
+        self.packets.is_some_and(|limit| packets > limit)
+            || self.bytes.is_some_and(|limit| bytes > limit)
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    chunks: VecDeque<Vec<u8>>,
+    bytes: usize,
+    dropped: u64,
+    // 写入线程遇到的错误，下一次写入时返回
+    error: Option<io::Error>,
+    closed: bool,
+}
+
+struct Shared {
+    pending: Mutex<Pending>,
+    changed: Condvar,
+}
+
+pub struct QueuedFile {
+    shared: Arc<Shared>,
+    limits: Limits,
+    writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl QueuedFile {
+    pub fn new(mut file: File, limits: Limits) -> QueuedFile {
+        let shared = Arc::new(Shared {
+            pending: Mutex::new(Pending::default()),
+            changed: Condvar::new(),
+        });
+        let writer = {
+            let shared = shared.clone();
+            thread::spawn(move || loop {
+                let chunk = {
+                    let mut pending = shared.pending.lock().unwrap();
+                    loop {
+                        if let Some(chunk) = pending.chunks.pop_front() {
+                            pending.bytes -= chunk.len();
+                            shared.changed.notify_all();
+                            break chunk;
+                        }
+                        if pending.closed {
+                            return file.flush();
+                        }
+                        pending = shared.changed.wait(pending).unwrap();
+                    }
+                };
+                if let Err(e) = file.write_all(&chunk) {
+                    let mut pending = shared.pending.lock().unwrap();
+                    pending.error = Some(io::Error::new(e.kind(), e.to_string()));
+                    shared.changed.notify_all();
+                    return Err(e);
+                }
+            })
+        };
+        QueuedFile {
+            shared,
+            limits,
+            writer: Some(writer),
+        }
+    }
+
+    // 放入队列，返回因队列已满丢弃的块数；写入线程出错后返回该错误
+    pub fn write(&mut self, data: &[u8]) -> io::Result<u64> {
+        let mut pending = self.shared.pending.lock().unwrap();
+        if let Some(e) = &pending.error {
+            return Err(io::Error::new(e.kind(), e.to_string()));
+        }
+        let mut dropped = 0;
+        match self.limits.policy {
+            Policy::DropOldest => {
+                while !pending.chunks.is_empty()
+                    && self
+                        .limits
+                        .exceeded(pending.chunks.len() + 1, pending.bytes + data.len())
+                {
+                    if let Some(chunk) = pending.chunks.pop_front() {
+                        pending.bytes -= chunk.len();
+                        dropped += 1;
+                    }
+                }
+                pending.dropped += dropped;
+            }
+            Policy::Block => {
+                while !pending.chunks.is_empty()
+                    && pending.error.is_none()
+                    && self
+                        .limits
+                        .exceeded(pending.chunks.len() + 1, pending.bytes + data.len())
+                {
+                    pending = self.shared.changed.wait(pending).unwrap();
+                }
+            }
+        }
+        pending.chunks.push_back(data.to_vec());
+        pending.bytes += data.len();
+        self.shared.changed.notify_all();
+        Ok(dropped)
+    }
+
+    // 等队列写完，返回一共丢弃的块数
+    pub fn finish(&mut self) -> io::Result<u64> {
+        {
+            let mut pending = self.shared.pending.lock().unwrap();
+            pending.closed = true;
+            self.shared.changed.notify_all();
+        }
+        if let Some(writer) = self.writer.take() {
+            writer
+                .join()
+                .map_err(|_| io::Error::other("Writer thread panicked"))??;
+        }
+        Ok(self.shared.pending.lock().unwrap().dropped)
+    }
+}
+
+impl Drop for QueuedFile {
+    // 分段被放弃（写入失败、切换到备用目录）时也让写入线程退出
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.finish();
+        }
+    }
+}
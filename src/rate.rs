@@ -0,0 +1,77 @@
+// 码率/帧率看门狗：摄像头没断线却降到 1 fps 或码率骤降（编码器异常、带宽被挤占）时，
+// 录像看起来一切正常。按 rate_window_seconds 统计收到的视频，低于 min_bitrate_kbps
+// 或 min_fps 时告警，rate_action=reconnect 时还会断开重连
+use std::time::Instant;
+
+use crate::alert;
+use crate::config::CameraConfig;
+
+pub struct RateWatchdog {
+    window_start: Instant,
+    bytes: u64,
+    frames: u64,
+    // 已告警且尚未恢复，跨重连保留，重连后仍然偏低时不重复告警
+    low: bool,
+}
+
+impl RateWatchdog {
+    // 未配置期望值时返回 None
+    pub fn new(camera: &CameraConfig) -> Option<RateWatchdog> {
+        (camera.min_bitrate_kbps.is_some() || camera.min_fps.is_some()).then(|| RateWatchdog {
+            window_start: Instant::now(),
+            bytes: 0,
+            frames: 0,
+            low: false,
+        })
+    }
+
+    // 每次连接成功后重新开始统计，探测和重连的时间不计入
+    pub fn restart(&mut self) {
+        self.window_start = Instant::now();
+        self.bytes = 0;
+        self.frames = 0;
+    }
+
+    pub fn record(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+        self.frames += 1;
+    }
+
+    // 统计窗口结束时检查一次，返回 Err 表示需要重连
+    pub fn check(&mut self, id: usize, camera: &CameraConfig) -> Result<(), String> {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < camera.rate_window {
+            return Ok(());
+        }
+        let kbps = self.bytes as f64 * 8.0 / elapsed.as_secs_f64() / 1000.0;
+        let fps = self.frames as f64 / elapsed.as_secs_f64();
+        self.restart();
+        let mut problems = Vec::new();
+        if let Some(min) = camera.min_bitrate_kbps.filter(|min| kbps < *min as f64) {
+            problems.push(format!("bitrate {:.0} kbps is below {} kbps", kbps, min));
+        }
+        if let Some(min) = camera.min_fps.filter(|min| fps < *min) {
+            problems.push(format!("frame rate {:.1} fps is below {} fps", fps, min));
+        }
+        if problems.is_empty() {
+            if self.low {
+                info!("[Stream {}] Back to {:.0} kbps, {:.1} fps", id, kbps, fps);
+                self.low = false;
+            }
+            return Ok(());
+        }
+        let message = format!(
+            "Over the last {} seconds {}",
+            elapsed.as_secs(),
+            problems.join(" and ")
+        );
+        if !self.low {
+            self.low = true;
+            alert::raise(camera, "degraded", &message);
+        }
+        if camera.rate_reconnect {
+            return Err(format!("{}, reconnecting", message));
+        }
+        Ok(())
+    }
+}
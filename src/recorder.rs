@@ -0,0 +1,1511 @@
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::codec::Parameters;
+
+use crate::alert;
+use crate::audio::{self, Aligner, AudioSource};
+use crate::avlog;
+use crate::backfill;
+use crate::clock::Jump;
+use crate::cluster;
+use crate::config::{self, CameraConfig, Config};
+use crate::control::{StreamHandle, Throttle};
+use crate::coverage;
+use crate::encryption;
+use crate::export::Exporter;
+use crate::health::Health;
+use crate::hooks;
+use crate::http;
+use crate::input::{self, Watchdog};
+use crate::log;
+use crate::mqtt;
+use crate::mux::{Muxer, StreamInfo};
+use crate::ntp;
+use crate::onvif;
+use crate::otlp::OtlpPlugin;
+use crate::plugin::{self, StreamState};
+use crate::preflight;
+use crate::pressure;
+use crate::preview::Preview;
+use crate::rate::RateWatchdog;
+use crate::retention;
+use crate::script;
+use crate::segment::{self, Segment, SegmentClock, SegmentRing};
+use crate::sla;
+use crate::snapshot::Snapshotter;
+use crate::source::PacketSource;
+use crate::state;
+use crate::stats::StreamStats;
+use crate::storage;
+use crate::timestamps::Normalizer;
+use crate::trigger;
+use crate::upload;
+use crate::webhook;
+
+// 按配置录制所有摄像头，直到在标准输入收到 q；
+// 插件需要在调用前通过 plugin::register 注册
+pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    record(config, 0, false)
+}
+
+// 进程隔离模式的子进程：只录制分给它的摄像头，日志编号沿用主进程分配的 id；
+// 触发目录由主进程处理，标准输入关闭（主进程退出）时停止录制
+pub fn run_worker(mut config: Config, id: usize) -> Result<(), Box<dyn std::error::Error>> {
+    config.trigger_dir = None;
+    record(config, id, true)
+}
+
+fn record(config: Config, first_id: usize, worker: bool) -> Result<(), Box<dyn std::error::Error>> {
+    log::set_format(config.log_format);
+    for (index, camera) in config.cameras.iter().enumerate() {
+        log::name_stream(first_id + index, &camera.name);
+    }
+    ffmpeg::init()?;
+    avlog::capture();
+    hooks::set_concurrency(config.hook_concurrency);
+    if let Some(endpoint) = &config.otlp_endpoint {
+        plugin::register(OtlpPlugin::new(endpoint, &config.otlp_service_name)?);
+    }
+    if let Some(path) = &config.script {
+        script::init(path, config.cameras.clone())?;
+    }
+    // 创建一个原子布尔值来控制程序运行
+    let running = Arc::new(AtomicBool::new(true));
+
+    // 订阅事件总线的集成，在流开始之前订阅，不漏掉预检等第一批事件；
+    // 其他线程都退出后才停止，最后的分段结束等事件也能送达
+    let delivering = Arc::new(AtomicBool::new(true));
+    let mut bus_threads = Vec::new();
+    if let Some(url) = &config.webhook_url {
+        bus_threads.push(webhook::spawn(
+            url,
+            config.publish_events.clone(),
+            delivering.clone(),
+        )?);
+    }
+    if let Some(broker) = &config.mqtt_broker {
+        bus_threads.push(mqtt::spawn(
+            broker,
+            config.mqtt_topic.clone(),
+            config.publish_events.clone(),
+            delivering.clone(),
+        )?);
+    }
+
+    // 创建输出文件夹
+    for camera in &config.cameras {
+        fs::create_dir_all(&camera.output_dir)?;
+    }
+    // 进程隔离模式下由主进程统一检查
+    if let (Some(size_mb), false) = (config.preflight_mb, worker) {
+        preflight::run(&config.cameras, size_mb);
+    }
+
+    let streams: Vec<Arc<StreamHandle>> = config
+        .cameras
+        .iter()
+        .cloned()
+        .map(StreamHandle::restore)
+        .collect();
+
+    // 命令可以选中运行中通过 POST /streams 新增的摄像头
+    let registry = Arc::new(Mutex::new(streams.clone()));
+    let added: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::new(Mutex::new(Vec::new()));
+
+    // 插件在总线的插件线程上收到回调，摄像头配置从正在录制的流中找
+    if plugin::has_plugins() {
+        let registry = registry.clone();
+        bus_threads.push(plugin::spawn(
+            move |name| {
+                registry
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|handle| handle.camera.name == name)
+                    .map(|handle| handle.camera.clone())
+            },
+            delivering.clone(),
+        ));
+    }
+
+    // 按保留策略清理过期分段
+    let retention_thread = retention::spawn(registry.clone(), running.clone());
+
+    // 记录系统时钟与 NTP 的偏差
+    let ntp_thread = config
+        .ntp_server
+        .clone()
+        .map(|server| ntp::spawn(server, running.clone()));
+
+    // 通过触发文件接入外部报警
+    let trigger_thread = config.trigger_dir.clone().map(|dir| {
+        let activate: trigger::Activate = Box::new(|handle| {
+            handle.activate(Duration::from_secs(handle.camera.on_demand_seconds));
+        });
+        let notify: trigger::Notify = Box::new(|handle, source| {
+            let now = chrono::Local::now().naive_local();
+            trigger::record_event(&handle.camera, source, now)
+        });
+        trigger::spawn(dir, streams.clone(), activate, notify, running.clone())
+    });
+
+    // 订阅摄像头的 ONVIF 事件（移动侦测、遮挡、越线等）作为事件触发
+    let onvif_threads: Vec<JoinHandle<()>> = streams
+        .iter()
+        .filter_map(|handle| onvif::spawn(handle.clone(), running.clone()))
+        .collect();
+
+    // 轮换密钥后把旧分段改用当前密钥
+    let encryption_thread = config
+        .cameras
+        .iter()
+        .any(|c| c.encryption_keys.is_some() && c.encryption_reencrypt)
+        .then(|| encryption::spawn(config.cameras.clone(), running.clone()));
+
+    // 把完成的分段上传到远端，带持久化的重试队列
+    let upload_thread = upload::spawn(registry.clone(), running.clone());
+
+    // 定期检查录像磁盘的写延迟和 SMART 状态
+    let storage_thread = config.storage_check.map(|interval| {
+        storage::spawn(
+            config.cameras.clone(),
+            interval,
+            config.max_write_latency,
+            running.clone(),
+        )
+    });
+
+    // 每小时统计录像覆盖率
+    let coverage_thread = coverage::spawn(registry.clone(), running.clone());
+
+    // 运行中开始录制新增的摄像头（POST /streams、控制台的 add）
+    let start: Arc<dyn Fn(Vec<CameraConfig>) + Send + Sync> = {
+        let next_id = AtomicUsize::new(first_id + config.cameras.len());
+        let registry = registry.clone();
+        let added = added.clone();
+        let running = running.clone();
+        Arc::new(move |cameras| {
+            for camera in cameras {
+                let id = next_id.fetch_add(1, Ordering::SeqCst);
+                log::name_stream(id, &camera.name);
+                if let Err(e) = fs::create_dir_all(&camera.output_dir) {
+                    error!(
+                        "[Stream {}] Failed to create {}: {}",
+                        id,
+                        camera.output_dir.display(),
+                        e
+                    );
+                }
+                let handle = StreamHandle::restore(camera);
+                registry.lock().unwrap().push(handle.clone());
+                let running = running.clone();
+                added
+                    .lock()
+                    .unwrap()
+                    .push(thread::spawn(move || process_stream(id, handle, running)));
+            }
+        })
+    };
+
+    // 创建一个线程来监听用户输入：子进程读主进程转发的命令，
+    // 在终端中运行时是交互式控制台，否则（systemd 等）不读取标准输入
+    let input_thread = {
+        let registry = registry.clone();
+        let running = running.clone();
+        let console = Console {
+            config_path: config.path.clone().filter(|_| !worker),
+            start: start.clone(),
+        };
+        thread::spawn(move || {
+            if worker || io::stdin().is_terminal() {
+                listen_for_commands(&registry, &running, worker, &console)
+            } else {
+                info!("stdin is not a terminal, the command console is off");
+                while running.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        })
+    };
+
+    // 按需剪辑和新增摄像头的 HTTP 接口
+    let admin = match (&config.admin_token, &config.path) {
+        (Some(token), Some(path)) if !worker => Some(http::Admin {
+            token: token.clone(),
+            config_path: path.clone(),
+            start: Box::new(move |cameras| start(cameras)),
+        }),
+        _ => None,
+    };
+    let http_thread = match &config.http_listen {
+        Some(address) => Some(
+            http::spawn(
+                address.clone(),
+                config.cameras.clone(),
+                registry.clone(),
+                admin,
+                running.clone(),
+            )
+            .map_err(|e| format!("Failed to listen on {}: {}", address, e))?,
+        ),
+        None => None,
+    };
+
+    // 集群模式：与其他节点分摊摄像头，必须在录制线程启动前完成
+    let cluster_thread = match &config.cluster_dir {
+        Some(dir) => {
+            let node = config
+                .node_name
+                .clone()
+                .unwrap_or_else(cluster::default_node_name);
+            Some(
+                cluster::spawn(dir.clone(), node, streams.clone(), running.clone())
+                    .map_err(|e| format!("Failed to join cluster in {}: {}", dir.display(), e))?,
+            )
+        }
+        None => None,
+    };
+
+    // 磁盘或 CPU 紧张时按优先级降级、暂停摄像头
+    let pressure_thread = (config.min_free_mb.is_some() || config.max_load.is_some()).then(|| {
+        let limits = pressure::Limits {
+            min_free_mb: config.min_free_mb,
+            max_load: config.max_load,
+        };
+        pressure::spawn(limits, registry.clone(), running.clone())
+    });
+
+    // 为每个摄像头创建一个线程
+    let handles: Vec<_> = streams
+        .into_iter()
+        .enumerate()
+        .map(|(index, handle)| {
+            let running = running.clone();
+            thread::spawn(move || process_stream(first_id + index, handle, running))
+        })
+        .collect();
+
+    // 等待输入线程完成（即用户按下'q'）
+    input_thread.join().unwrap();
+
+    info!("Stopping all streams...");
+
+    // 等待所有工作线程完成
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    retention_thread.join().unwrap();
+    coverage_thread.join().unwrap();
+    if let Some(encryption_thread) = encryption_thread {
+        encryption_thread.join().unwrap();
+    }
+    upload_thread.join().unwrap();
+    if let Some(storage_thread) = storage_thread {
+        storage_thread.join().unwrap();
+    }
+    if let Some(ntp_thread) = ntp_thread {
+        ntp_thread.join().unwrap();
+    }
+    if let Some(trigger_thread) = trigger_thread {
+        trigger_thread.join().unwrap();
+    }
+    for onvif_thread in onvif_threads {
+        onvif_thread.join().unwrap();
+    }
+    if let Some(pressure_thread) = pressure_thread {
+        pressure_thread.join().unwrap();
+    }
+    if let Some(cluster_thread) = cluster_thread {
+        cluster_thread.join().unwrap();
+    }
+    if let Some(http_thread) = http_thread {
+        http_thread.join().unwrap();
+    }
+    // HTTP 线程已退出，不会再新增
+    for handle in added.lock().unwrap().drain(..) {
+        handle.join().unwrap();
+    }
+
+    info!("All streams stopped. Program exiting.");
+    delivering.store(false, Ordering::SeqCst);
+    for bus_thread in bus_threads {
+        bus_thread.join().unwrap();
+    }
+
+    Ok(())
+}
+
+// 控制台的 add 命令所需：按配置文件检查新增的一行，再交给 start 开始录制
+struct Console {
+    config_path: Option<PathBuf>,
+    start: Arc<dyn Fn(Vec<CameraConfig>) + Send + Sync>,
+}
+
+// 监听用户输入：q 退出，pause/resume <摄像头名|@标签> 暂停或恢复录制，
+// status 列出各路流的状态，stop/rotate <摄像头> 停止录制或立即切分，activate <摄像头> [秒数] 触发按需摄像头，
+// add <url> [key=value ...] 本次运行中新增摄像头（不写入配置文件）
+fn listen_for_commands(
+    registry: &Mutex<Vec<Arc<StreamHandle>>>,
+    running: &AtomicBool,
+    stop_on_eof: bool,
+    console: &Console,
+) {
+    let mut input = String::new();
+    loop {
+        input.clear();
+        match io::stdin().read_line(&mut input) {
+            Ok(0) if stop_on_eof => {
+                running.store(false, Ordering::SeqCst);
+                break;
+            }
+            Ok(_) => {}
+            Err(_) => continue,
+        }
+        let words: Vec<&str> = input.split_whitespace().collect();
+        let streams = registry.lock().unwrap().clone();
+        let streams = streams.as_slice();
+        match words.as_slice() {
+            ["q"] => {
+                running.store(false, Ordering::SeqCst);
+                break;
+            }
+            ["pause", selector] => set_paused(streams, selector, true),
+            ["resume", selector] => set_paused(streams, selector, false),
+            ["enable", selector] => enable(streams, selector),
+            ["status"] => print_status(streams),
+            ["stop", selector] => stop(streams, selector),
+            ["rotate", selector] => rotate(streams, selector),
+            ["activate", selector] => activate(streams, selector, None),
+            ["activate", selector, seconds] => match seconds.parse() {
+                Ok(seconds) => activate(streams, selector, Some(Duration::from_secs(seconds))),
+                Err(_) => error!("Invalid number of seconds: {}", seconds),
+            },
+            ["add", ..] => add(streams, console, input.trim()["add".len()..].trim()),
+            // 进程隔离模式下主进程转发的触发事件
+            ["event", name, source @ ..] if !source.is_empty() => {
+                forward_event(streams, name, &source.join(" "))
+            }
+            ["help"] => println!(
+                "Commands: status, pause/resume/enable/stop/rotate <camera|@tag|%tenant>, activate <camera|@tag|%tenant> [seconds], add <url> [key=value ...], q"
+            ),
+            [] => {}
+            _ => error!("Unknown command: {}", input.trim()),
+        }
+    }
+}
+
+fn print_status(streams: &[Arc<StreamHandle>]) {
+    for handle in streams {
+        let state = if handle.is_stopped() {
+            "stopped"
+        } else if handle.is_disabled() {
+            "disabled"
+        } else if !handle.is_demanded() {
+            "on demand"
+        } else if handle.is_paused() {
+            "paused"
+        } else if handle.is_connected() {
+            "recording"
+        } else {
+            "connecting"
+        };
+        let sequence = handle
+            .saved_state()
+            .map_or("-".to_string(), |state| state.sequence.to_string());
+        println!(
+            "{}: {}, {} segment(s), {}",
+            handle.camera.name,
+            state,
+            sequence,
+            handle.source().unwrap_or_else(|| handle.camera.url.clone())
+        );
+    }
+}
+
+// 按需摄像头立即开始录制 length（默认 on_demand_seconds）
+fn activate(streams: &[Arc<StreamHandle>], selector: &str, length: Option<Duration>) {
+    let mut matched = 0;
+    for handle in streams
+        .iter()
+        .filter(|h| h.camera.on_demand && h.camera.matches(selector))
+    {
+        handle.activate(length.unwrap_or(Duration::from_secs(handle.camera.on_demand_seconds)));
+        matched += 1;
+    }
+    if matched == 0 {
+        error!("No on-demand camera matches '{}'", selector);
+    } else {
+        info!("Activated {} camera(s) matching '{}'", matched, selector);
+    }
+}
+
+// 主进程监视触发目录，事件在录制这个摄像头的子进程里生效：主码流记入索引并通知订阅者，
+// 子码流只需要跟着切换事件分段
+fn forward_event(streams: &[Arc<StreamHandle>], name: &str, source: &str) {
+    let now = chrono::Local::now().naive_local();
+    for handle in streams
+        .iter()
+        .filter(|h| h.camera.name == name || h.camera.substream_of.as_deref() == Some(name))
+    {
+        let camera = &handle.camera;
+        if camera.substream_of.is_some() {
+            segment::note_event(camera, now, camera.event_seconds);
+        } else if let Err(e) = trigger::record_event(camera, source, now) {
+            error!("[Trigger] Failed to record event on {}: {}", camera.name, e);
+        }
+    }
+}
+
+// 本次运行中不再录制，重启后照常录制
+fn stop(streams: &[Arc<StreamHandle>], selector: &str) {
+    let mut matched = 0;
+    for handle in streams.iter().filter(|h| h.camera.matches(selector)) {
+        if !handle.stop() {
+            matched += 1;
+        }
+    }
+    if matched == 0 {
+        error!("No running camera matches '{}'", selector);
+    } else {
+        info!("Stopped {} camera(s) matching '{}'", matched, selector);
+    }
+}
+
+fn rotate(streams: &[Arc<StreamHandle>], selector: &str) {
+    let mut matched = 0;
+    for handle in streams.iter().filter(|h| h.camera.matches(selector)) {
+        handle.request_rotate();
+        matched += 1;
+    }
+    if matched == 0 {
+        error!("No camera matches '{}'", selector);
+    } else {
+        info!(
+            "Starting a new segment for {} camera(s) matching '{}' at the next keyframe",
+            matched, selector
+        );
+    }
+}
+
+fn add(streams: &[Arc<StreamHandle>], console: &Console, line: &str) {
+    let path = match &console.config_path {
+        Some(path) => path,
+        None => {
+            error!("add needs a config file");
+            return;
+        }
+    };
+    let cameras = match config::check_camera(path, line) {
+        Ok(cameras) => cameras,
+        Err(e) => {
+            error!("Cannot add camera: {}", e);
+            return;
+        }
+    };
+    let names: Vec<String> = cameras.iter().map(|c| c.name.clone()).collect();
+    if cameras.is_empty()
+        || cameras
+            .iter()
+            .any(|c| streams.iter().any(|h| h.camera.name == c.name))
+    {
+        error!(
+            "Cannot add camera: no new camera in '{}', it may already be configured or recording",
+            line
+        );
+        return;
+    }
+    (console.start)(cameras);
+    info!("Added {}", names.join(", "));
+}
+
+fn set_paused(streams: &[Arc<StreamHandle>], selector: &str, paused: bool) {
+    let mut matched = 0;
+    for handle in streams.iter().filter(|h| h.camera.matches(selector)) {
+        handle.set_paused(paused);
+        matched += 1;
+    }
+    if matched == 0 {
+        error!("No camera matches '{}'", selector);
+    } else {
+        let action = if paused { "Paused" } else { "Resumed" };
+        info!("{} {} camera(s) matching '{}'", action, matched, selector);
+    }
+}
+
+// 重新启用自动停用的摄像头
+fn enable(streams: &[Arc<StreamHandle>], selector: &str) {
+    let mut matched = 0;
+    let mut enabled = 0;
+    for handle in streams.iter().filter(|h| h.camera.matches(selector)) {
+        matched += 1;
+        if handle.set_disabled(false) {
+            enabled += 1;
+        }
+    }
+    if matched == 0 {
+        error!("No camera matches '{}'", selector);
+    } else {
+        info!("Enabled {} camera(s) matching '{}'", enabled, selector);
+    }
+}
+
+// 一路流跨重连保留的状态
+struct StreamContext {
+    ring: SegmentRing,
+    // 连接期间由录制线程持有并在包循环里写统计，断线期间由定时线程写，不漏掉断线时段
+    stats: Arc<Mutex<StreamStats>>,
+    // socket 跨重连保留，消费者不必跟着重连
+    exporter: Option<Exporter>,
+    preview: Option<Preview>,
+    snapshots: Option<Snapshotter>,
+    // 上次成功连接时的流参数，重连时跳过探测以缩短断档
+    parameters: Option<(usize, Vec<Parameters>)>,
+    // 外接音频跨视频重连保留；两路以 origin 为共同的时间零点
+    audio: Option<AudioSource>,
+    origin: Instant,
+    parked: Option<Parked>,
+    rate: Option<RateWatchdog>,
+}
+
+// 断线时暂不关闭的分段，宽限期内以相同的编码和轨道重连成功时继续写入
+struct Parked {
+    segment: Segment,
+    clock: SegmentClock,
+    codec: ffmpeg::codec::Id,
+    tracks: Option<usize>,
+    since: Instant,
+}
+
+fn process_stream(id: usize, handle: Arc<StreamHandle>, running: Arc<AtomicBool>) {
+    avlog::attach(id);
+    let camera = &handle.camera;
+    let exporter = camera
+        .export_socket
+        .as_ref()
+        .map(|path| PathBuf::from(path.to_string_lossy().replace("{camera}", &camera.name)))
+        .and_then(|path| match Exporter::bind(&path) {
+            Ok(exporter) => Some(exporter),
+            Err(e) => {
+                error!("[Stream {}] Failed to bind {}: {}", id, path.display(), e);
+                None
+            }
+        });
+    if let Some(saved) = handle.saved_state() {
+        state::recover(id, camera, &saved);
+        sla::resume(camera);
+        if saved.paused {
+            info!("[Stream {}] Still paused since the last run", id);
+        }
+        if saved.disabled {
+            info!(
+                "[Stream {}] Still disabled since the last run, `enable {}` to retry",
+                id, camera.name
+            );
+        }
+    }
+    let origin = Instant::now();
+    let stats = Arc::new(Mutex::new(StreamStats::new(camera)));
+    let stats_thread = {
+        let stats = stats.clone();
+        let handle = handle.clone();
+        let running = running.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::SeqCst) && !handle.is_stopped() {
+                thread::sleep(Duration::from_secs(1));
+                if let Ok(mut stats) = stats.try_lock() {
+                    stats.maybe_write(id, &handle.camera);
+                }
+            }
+        })
+    };
+    let mut context = StreamContext {
+        ring: SegmentRing::new(id, camera),
+        stats,
+        exporter,
+        preview: Preview::new(camera),
+        snapshots: Snapshotter::spawn(id, camera),
+        parameters: None,
+        audio: audio::spawn(id, handle.clone(), origin, running.clone()),
+        origin,
+        parked: None,
+        rate: RateWatchdog::new(camera),
+    };
+    reconnect_loop(id, &handle, &running, |reconnect| {
+        if reconnect {
+            context.stats.lock().unwrap().record_reconnect();
+        }
+        stream_to_file(id, &handle, &mut context, running.clone())
+    });
+    if let Some(mut parked) = context.parked.take() {
+        close_segment(id, camera, &mut parked.segment);
+    }
+    if let Some(audio) = context.audio {
+        audio.join();
+    }
+    stats_thread.join().unwrap();
+}
+
+// 反复连接并处理一路流，断开后等待 5 秒重连，直到停止；暂停期间不连接。
+// connect 的参数表示是否为断线后的重连
+fn reconnect_loop<F: FnMut(bool) -> Result<(), String>>(
+    id: usize,
+    handle: &StreamHandle,
+    running: &AtomicBool,
+    mut connect: F,
+) {
+    let camera = &handle.camera;
+    let url = &camera.url;
+    info!("[Stream {}] Starting: {}", id, url);
+    let mut health = handle
+        .saved_state()
+        .map_or_else(Health::default, |state| Health::restore(&state));
+    let mut first_attempt = true;
+    while running.load(Ordering::SeqCst) && !handle.is_stopped() {
+        if handle.is_paused() {
+            if handle.keeps_state() {
+                sla::record(
+                    camera,
+                    if handle.is_disabled() {
+                        "disabled"
+                    } else {
+                        "paused"
+                    },
+                );
+            }
+            thread::sleep(Duration::from_secs(1));
+            continue;
+        }
+        let reconnect = !first_attempt;
+        first_attempt = false;
+        match connect(reconnect) {
+            Ok(_) => info!("[Stream {}] Ended for {}", id, url),
+            Err(e) => {
+                error!("[Stream {}] Error processing {}: {:?}", id, url, e);
+                report_state(handle, StreamState::Down(&e));
+            }
+        }
+        let was_up = handle.is_connected();
+        handle.set_connected(false);
+        if handle.is_stopped() {
+            break;
+        } else if handle.is_paused() {
+            report_state(handle, StreamState::Paused);
+            first_attempt = true;
+        } else if running.load(Ordering::SeqCst) {
+            // 流正常结束时插件不收到 down，但等待重连的时间仍算断线
+            if handle.keeps_state() {
+                sla::record(camera, "down");
+            }
+            health.disconnected(camera, was_up);
+            handle.update_state(|state| health.store(state));
+            if let Some(limit) = camera.disable_after {
+                if health.down_for().is_some_and(|down| down >= limit) {
+                    health = Health::default();
+                    handle.update_state(|state| health.store(state));
+                    handle.set_disabled(true);
+                    alert::raise(
+                        camera,
+                        "disabled",
+                        &format!(
+                            "Cannot connect for {} hours; stopped retrying until `enable {}`",
+                            limit.as_secs() / 3600,
+                            camera.name
+                        ),
+                    );
+                    report_state(handle, StreamState::Disabled);
+                    first_attempt = true;
+                    continue;
+                }
+            }
+            info!("[Stream {}] Retrying {} in 5 seconds...", id, url);
+            thread::sleep(Duration::from_secs(5)); // 等待5秒后重试
+        }
+    }
+    if handle.keeps_state() {
+        sla::record(camera, "stopped");
+    }
+    info!("[Stream {}] Stopped: {}", id, url);
+}
+
+// 通知插件和事件总线，并记入可用率（只记保存状态的流）
+fn report_state(handle: &StreamHandle, state: StreamState) {
+    if handle.keeps_state() {
+        sla::record(&handle.camera, state.name());
+    }
+    plugin::stream_state(&handle.camera, state);
+}
+
+// 管道模式：把一路流封装成 MPEG-TS 写到标准输出，如 `save_rtsp pipe rtsp://... | ffplay -`；
+// 断线重连与录制相同，日志改写到 stderr。读取方退出后结束
+pub fn pipe(camera: CameraConfig) -> Result<(), Box<dyn std::error::Error>> {
+    log::use_stderr();
+    log::name_stream(0, &camera.name);
+    ffmpeg::init()?;
+    avlog::capture();
+    avlog::attach(0);
+    let running = Arc::new(AtomicBool::new(true));
+    let handle = StreamHandle::new(camera);
+    {
+        let streams = Mutex::new(vec![handle.clone()]);
+        let running = running.clone();
+        let console = Console {
+            config_path: None,
+            start: Arc::new(|_| {}),
+        };
+        if io::stdin().is_terminal() {
+            thread::spawn(move || listen_for_commands(&streams, &running, false, &console));
+        }
+    }
+    reconnect_loop(0, &handle, &running, |_| pipe_stream(0, &handle, &running));
+    Ok(())
+}
+
+fn pipe_stream(
+    id: usize,
+    handle: &Arc<StreamHandle>,
+    running: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let watchdog = Watchdog::new(handle.camera.read_timeout);
+    let options = input::Options {
+        probesize: handle.camera.probesize,
+        analyzeduration: handle.camera.analyzeduration,
+        avoptions: &handle.camera.avoptions,
+        ..Default::default()
+    };
+    let mut source = input::open(
+        &handle.camera.url,
+        &options,
+        &watchdog,
+        stop_condition(handle, running),
+    )?;
+    let video_stream_index = source.best(ffmpeg::media::Type::Video)?;
+    let input = source
+        .context
+        .stream(video_stream_index)
+        .map(|stream| StreamInfo::of(&stream))
+        .ok_or_else(|| "No video stream found".to_string())?;
+    let mut muxer =
+        Muxer::open(Path::new("pipe:1"), Some("mpegts"), &[input], &[], &[]).map_err(|e| {
+            running.store(false, Ordering::SeqCst);
+            format!("Failed to open stdout: {}", e)
+        })?;
+    handle.set_connected(true);
+    plugin::stream_state(&handle.camera, StreamState::Up);
+
+    let mut normalizer = Normalizer::new(handle.camera.timestamp_policy);
+    for result in source.context.packets() {
+        if !running.load(Ordering::SeqCst) || handle.is_paused() {
+            break;
+        }
+        let (stream, mut packet) = match result {
+            Ok(item) => item,
+            Err(_) if watchdog.expired() => return Err(watchdog.timeout_error()),
+            Err(_) => continue,
+        };
+        watchdog.feed();
+        if stream.index() != video_stream_index || !normalizer.normalize(id, &mut packet) {
+            continue;
+        }
+        if let Err(e) = muxer.write(0, packet) {
+            // 读取方已退出，不再重连
+            running.store(false, Ordering::SeqCst);
+            return Err(format!("Failed to write to stdout: {}", e));
+        }
+    }
+    muxer.finish().map_err(|e| e.to_string())
+}
+
+fn stream_to_file(
+    id: usize,
+    handle: &Arc<StreamHandle>,
+    context: &mut StreamContext,
+    running: Arc<AtomicBool>,
+) -> Result<(), String> {
+    let camera = &handle.camera;
+    let StreamContext {
+        ring,
+        stats,
+        exporter,
+        preview,
+        snapshots,
+        parameters,
+        audio,
+        origin,
+        parked,
+        rate,
+    } = context;
+    // 宽限期内没能重连，关闭暂留的分段
+    if parked.as_ref().is_some_and(|p| {
+        camera
+            .continue_grace
+            .is_none_or(|grace| p.since.elapsed() > grace)
+    }) {
+        if let Some(mut expired) = parked.take() {
+            close_segment(id, camera, &mut expired.segment);
+        }
+    }
+    let watchdog = Watchdog::new(camera.read_timeout);
+    // 缓存只在读到数据后才放回，参数失效导致连接失败时下次会重新探测
+    let cached = parameters.take().filter(|_| camera.reuse_stream_info);
+    // 依次尝试主地址和备用地址，每次重连都从主地址开始
+    let mut opened = None;
+    let mut errors = Vec::new();
+    for (index, url) in camera.urls().enumerate() {
+        let options = input::Options {
+            // 缓存的参数只对同一个地址有效
+            cached: cached
+                .as_ref()
+                .filter(|(cached_index, _)| *cached_index == index)
+                .map(|(_, cached)| cached.as_slice()),
+            probesize: camera.probesize,
+            analyzeduration: camera.analyzeduration,
+            avoptions: &camera.avoptions,
+            ..Default::default()
+        };
+        match input::open(url, &options, &watchdog, stop_condition(handle, &running)) {
+            Ok(source) => {
+                opened = Some((index, url, source));
+                break;
+            }
+            Err(e) if camera.failover_urls.is_empty() => return Err(e),
+            Err(e) => {
+                error!("[Stream {}] Failed to open {}: {}", id, url, e);
+                errors.push(e);
+                if !running.load(Ordering::SeqCst) || handle.is_paused() {
+                    break;
+                }
+            }
+        }
+    }
+    let (url_index, url, mut source) = opened.ok_or_else(|| errors.join("; "))?;
+    if url_index > 0 {
+        info!("[Stream {}] Connected to failover URL {}", id, url);
+    }
+    let switched = handle.source().is_some_and(|previous| previous != url);
+    handle.set_source(url);
+    if source.reused {
+        info!("[Stream {}] Reused cached stream parameters", id);
+    }
+    for key in &source.ignored {
+        error!(
+            "[Stream {}] Option opt.{} was not used by the input",
+            id, key
+        );
+    }
+    let fresh = source.parameters();
+    let video_stream_index = source.best(ffmpeg::media::Type::Video)?;
+    if let (Some(snapshots), Some(stream)) = (
+        snapshots.as_mut(),
+        source.context.stream(video_stream_index),
+    ) {
+        snapshots.connected(camera, StreamInfo::of(&stream));
+    }
+
+    // 有外接音频时把分段封装为 MP4，视频时间戳与音频对齐到同一时间轴
+    let (video, mut aligner) = match (audio.as_ref(), source.context.stream(video_stream_index)) {
+        (Some(audio), Some(stream)) => {
+            audio.discard_pending();
+            let video = StreamInfo {
+                parameters: stream.parameters(),
+                time_base: audio::TIME_BASE,
+            };
+            (Some(video), Some(Aligner::new(*origin, stream.time_base())))
+        }
+        _ => (None, None),
+    };
+    // 音频在分段中途才连上时，从下一个分段开始包含音轨
+    let tracks = || {
+        video.clone().map(|video| {
+            std::iter::once(video)
+                .chain(audio.as_ref().and_then(AudioSource::info))
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let (codec, time_base) = source
+        .context
+        .stream(video_stream_index)
+        .map_or((ffmpeg::codec::Id::None, audio::TIME_BASE), |stream| {
+            (stream.parameters().id(), stream.time_base())
+        });
+    let track_count = tracks().map(|tracks| tracks.len());
+    let mut stats = stats.lock().unwrap();
+    let resumed = match parked.take() {
+        Some(p) if p.codec == codec && p.tracks == track_count => Some(p),
+        Some(mut p) => {
+            close_segment(id, camera, &mut p.segment);
+            None
+        }
+        None => None,
+    };
+    let (mut segment, mut clock) = match resumed {
+        Some(Parked {
+            mut segment,
+            mut clock,
+            ..
+        }) => {
+            info!("[Stream {}] Continuing {}", id, segment.path.display());
+            clock.resume(time_base);
+            segment.set_time_base(time_base);
+            segment.record_reconnect();
+            // 改用了另一个地址时在元数据里追加一行，分段中途换了来源也能看出来
+            if switched {
+                write_source(id, &segment, url);
+            }
+            (segment, clock)
+        }
+        None => {
+            let mut segment = open_segment(
+                id,
+                handle,
+                ring,
+                (codec, time_base),
+                false,
+                tracks().as_deref(),
+            )?;
+            // 之前连上过，这个分段从重连开始
+            if stats.last_packet.is_some() {
+                segment.record_reconnect();
+            }
+            (segment, SegmentClock::new(time_base))
+        }
+    };
+
+    // 断线足够久且摄像头支持回放时，在后台补录缺失的时间段
+    if let Some(last_packet) = stats.last_packet {
+        let gap = (chrono::Local::now().naive_local() - last_packet)
+            .to_std()
+            .unwrap_or_default();
+        if camera.replay_url.is_some() && gap >= camera.backfill_min {
+            backfill::spawn(
+                id,
+                camera.clone(),
+                last_packet,
+                segment.started,
+                running.clone(),
+            );
+        }
+    }
+
+    handle.set_connected(true);
+    report_state(handle, StreamState::Up);
+    if let Some(rate) = rate.as_mut() {
+        rate.restart();
+    }
+
+    let mut result = Ok(());
+    // 自适应分段时当前是否在事件期间，只在关键帧上更新，事件边界的分段以关键帧开头
+    let mut in_event = false;
+    // 按下 q 或暂停，而不是断线
+    let mut stopped = false;
+    let mut normalizer = Normalizer::new(camera.timestamp_policy);
+    for item in source.context.packets() {
+        if !running.load(Ordering::SeqCst) {
+            info!("[Stream {}] Stopping gracefully...", id);
+            stopped = true;
+            break;
+        }
+        if handle.is_paused() {
+            stopped = true;
+            break;
+        }
+        stats.maybe_write(id, camera);
+
+        let (stream, mut packet) = match item {
+            Ok(item) => item,
+            Err(_) if watchdog.expired() => {
+                result = Err(watchdog.timeout_error());
+                break;
+            }
+            Err(_) => {
+                stats.record_drop();
+                segment.record_drop();
+                continue;
+            }
+        };
+        watchdog.feed();
+        if parameters.is_none() {
+            *parameters = Some((url_index, fresh.clone()));
+        }
+
+        if let Some(audio) = audio.as_ref() {
+            for audio_packet in audio.receiver.try_iter() {
+                if let Err(e) = segment.write_packet(camera, 1, &audio_packet) {
+                    error!("[Stream {}] Failed to write audio packet: {}", id, e);
+                }
+            }
+        }
+
+        if stream.index() == video_stream_index {
+            // 封装器拒绝倒退的 dts，在交给各个输出之前整理
+            if !normalizer.normalize(id, &mut packet) {
+                stats.record_drop();
+                segment.record_drop();
+                continue;
+            }
+            if let (Some(exporter), Some(data)) = (exporter.as_mut(), packet.data()) {
+                exporter.send(id, packet.is_key(), packet.pts(), data);
+            }
+            if let Some(preview) = preview.as_mut() {
+                if let Err(e) = preview.write(camera, &packet) {
+                    error!("[Stream {}] Failed to write preview: {}", id, e);
+                }
+            }
+            if let Some(snapshots) = snapshots.as_mut() {
+                snapshots.offer(camera, &packet);
+            }
+            // 统计摄像头发来的码率，不受下面只保留关键帧的影响
+            if let Some(rate) = rate.as_mut() {
+                rate.record(packet.size());
+                if let Err(e) = rate.check(id, camera) {
+                    result = Err(e);
+                    break;
+                }
+            }
+            // 资源紧张时只保留关键帧
+            if handle.throttle() == Throttle::KeyframesOnly && !packet.is_key() {
+                continue;
+            }
+            // 在达到分段时长的那个包之前切分，分段恰好包含目标时长的画面
+            let ts = packet.dts().or(packet.pts());
+            let elapsed = clock.advance(ts);
+            let mut cut = should_cut(camera, elapsed, packet.is_key(), &mut in_event);
+            // 系统时钟跳变（校时、休眠恢复）后在关键帧上切分，不让一个分段跨过跳变
+            cut |= packet.is_key() && segment.clock_jump(camera).is_some();
+            // 控制台的 rotate 命令
+            cut |= packet.is_key() && handle.take_rotate();
+            if cut {
+                let path = segment.path.clone();
+                finish_segment(id, camera, &mut segment);
+                ring.retimed(&path, &segment);
+                segment::update_latest(id, camera, &segment.path);
+                segment = open_segment(
+                    id,
+                    handle,
+                    ring,
+                    (codec, time_base),
+                    segment.fallback,
+                    tracks().as_deref(),
+                )?;
+                clock.restart(ts);
+            }
+            if let Some(aligner) = aligner.as_mut() {
+                aligner.align(&mut packet);
+            }
+            if let Some(data) = packet.data() {
+                stats.record_packet(data.len());
+                if let Err(e) = segment.write_packet(camera, 0, &packet) {
+                    if segment.fallback || camera.fallback_dir.is_none() {
+                        return Err(format!("Failed to write packet data: {}", e));
+                    }
+                    // 主目录写入失败（磁盘被拔出、挂载丢失等），立即改写到备用目录
+                    alert::raise(
+                        camera,
+                        "storage",
+                        &format!(
+                            "Failed to write {}: {}; switching to fallback directory",
+                            segment.path.display(),
+                            e
+                        ),
+                    );
+                    finish_segment(id, camera, &mut segment);
+                    segment = Segment::create_fallback(camera, tracks().as_deref())
+                        .map_err(|e| format!("Failed to create fallback file: {}", e))?;
+                    segment.codec = Some(codec);
+                    segment.set_time_base(time_base);
+                    ring.push(id, camera, &segment);
+                    segment
+                        .write_packet(camera, 0, &packet)
+                        .map_err(|e| format!("Failed to write packet data: {}", e))?;
+                }
+            }
+        }
+    }
+
+    if let (false, Some(grace)) = (stopped, camera.continue_grace) {
+        info!(
+            "[Stream {}] Keeping {} open for {} seconds",
+            id,
+            segment.path.display(),
+            grace.as_secs()
+        );
+        *parked = Some(Parked {
+            segment,
+            clock,
+            codec,
+            tracks: track_count,
+            since: Instant::now(),
+        });
+    } else {
+        close_segment(id, camera, &mut segment);
+        info!("[Stream {}] Finished writing to file", id);
+    }
+
+    result
+}
+
+// 是否在这个视频包之前切分：达到分段时长，或者事件开始、结束（只在关键帧上判断）
+fn should_cut(camera: &CameraConfig, elapsed: Duration, key: bool, in_event: &mut bool) -> bool {
+    let mut cut = elapsed >= segment::target_length(camera, *in_event);
+    if key && camera.event_segment.is_some() {
+        let active = segment::in_event(camera);
+        if active != *in_event {
+            *in_event = active;
+            cut |= !elapsed.is_zero();
+        }
+    }
+    cut
+}
+
+// 模拟录制得到的一个分段
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedSegment {
+    pub packets: usize,
+    pub keyframes: usize,
+    pub starts_with_keyframe: bool,
+    // 按时间戳计的媒体时长
+    pub duration: Duration,
+    // 断线后继续写入的次数（continue_grace）
+    pub reconnects: usize,
+}
+
+// 用与录制相同的切分规则处理一个包来源，不写文件，返回各分段的情况；
+// 断线时按 continue_grace 决定继续原分段还是新建分段（模拟中重连总在宽限期内）
+pub fn simulate(camera: &CameraConfig, source: &mut dyn PacketSource) -> Vec<SimulatedSegment> {
+    let rational = |(num, den): (i32, i32)| ffmpeg::Rational::new(num, den);
+    let mut clock = SegmentClock::new(rational(source.time_base()));
+    let mut segments = Vec::new();
+    let mut current: Option<SimulatedSegment> = None;
+    let mut in_event = false;
+    loop {
+        let packet = match source.next_packet() {
+            Ok(Some(packet)) => packet,
+            Ok(None) => break,
+            Err(_) => {
+                match (&mut current, camera.continue_grace) {
+                    (Some(segment), Some(_)) => {
+                        segment.reconnects += 1;
+                        clock.resume(rational(source.time_base()));
+                    }
+                    _ => {
+                        segments.extend(current.take());
+                        clock = SegmentClock::new(rational(source.time_base()));
+                    }
+                }
+                continue;
+            }
+        };
+        let ts = packet.dts.or(packet.pts);
+        let elapsed = clock.advance(ts);
+        if current.is_some() && should_cut(camera, elapsed, packet.key, &mut in_event) {
+            segments.extend(current.take());
+            clock.restart(ts);
+        }
+        let segment = current.get_or_insert_with(|| SimulatedSegment {
+            starts_with_keyframe: packet.key,
+            ..Default::default()
+        });
+        segment.packets += 1;
+        segment.keyframes += packet.key as usize;
+        segment.duration = clock.elapsed();
+    }
+    segments.extend(current);
+    segments
+}
+
+// 在分段的 .meta 里记下录制所用的地址
+fn write_source(id: usize, segment: &Segment, url: &str) {
+    if let Err(e) = segment::write_metadata(&segment.path, &[("source_url", url.to_string())]) {
+        error!(
+            "[Stream {}] Failed to write metadata of {}: {}",
+            id,
+            segment.path.display(),
+            e
+        );
+    }
+}
+
+// 确保所有数据都写入磁盘
+fn close_segment(id: usize, camera: &CameraConfig, segment: &mut Segment) {
+    finish_segment(id, camera, segment);
+    segment::update_latest(id, camera, &segment.path);
+}
+
+// 按下 q 或暂停时打断阻塞的读取
+fn stop_condition(
+    handle: &Arc<StreamHandle>,
+    running: &Arc<AtomicBool>,
+) -> impl Fn() -> bool + Send + 'static {
+    let handle = handle.clone();
+    let running = running.clone();
+    move || !running.load(Ordering::SeqCst) || handle.is_paused()
+}
+
+// 分段期间系统时钟跳变过时先改正：一步校时按新的时钟改名，休眠则结束于休眠开始时
+fn finish_segment(id: usize, camera: &CameraConfig, segment: &mut Segment) {
+    let mut ended = chrono::Local::now().naive_local();
+    match segment.clock_jump(camera) {
+        Some(Jump::Step(delta)) => {
+            info!(
+                "[Stream {}] System clock jumped by {} seconds, retiming {}",
+                id,
+                delta.num_seconds(),
+                segment.path.display()
+            );
+            if let Err(e) = segment.retime(camera, delta) {
+                error!(
+                    "[Stream {}] Failed to retime {}: {}",
+                    id,
+                    segment.path.display(),
+                    e
+                );
+            }
+        }
+        Some(Jump::Suspend(slept)) => {
+            info!(
+                "[Stream {}] System was suspended for {} seconds, ending {} before that",
+                id,
+                slept.as_secs(),
+                segment.path.display()
+            );
+            ended -= chrono::Duration::from_std(slept).unwrap_or_default();
+        }
+        None => {}
+    }
+    // 即便如此也不写出结束早于开始的分段
+    if let Err(e) = segment.finish(camera, ended.max(segment.started)) {
+        error!(
+            "[Stream {}] Failed to finish {}: {}",
+            id,
+            segment.path.display(),
+            e
+        );
+    }
+}
+
+// 创建新分段并加入环形缓冲；主目录不可写时切换到备用目录，
+// 每次切分都会先尝试主目录，恢复后自动切回
+fn open_segment(
+    id: usize,
+    handle: &StreamHandle,
+    ring: &mut SegmentRing,
+    (codec, time_base): (ffmpeg::codec::Id, ffmpeg::Rational),
+    was_fallback: bool,
+    tracks: Option<&[StreamInfo]>,
+) -> Result<Segment, String> {
+    let camera = &handle.camera;
+    let mut segment = match Segment::create(camera, tracks) {
+        Ok(segment) => {
+            if was_fallback {
+                info!(
+                    "[Stream {}] Output directory {} is writable again",
+                    id,
+                    camera.output_dir.display()
+                );
+            }
+            segment
+        }
+        Err(e) if camera.fallback_dir.is_some() => {
+            if !was_fallback {
+                alert::raise(
+                    camera,
+                    "storage",
+                    &format!(
+                        "Cannot create segment in {}: {}; switching to fallback directory",
+                        camera.output_dir.display(),
+                        e
+                    ),
+                );
+            }
+            Segment::create_fallback(camera, tracks)
+                .map_err(|e| format!("Failed to create fallback file: {}", e))?
+        }
+        Err(e) => return Err(format!("Failed to create output file: {}", e)),
+    };
+    segment.codec = Some(codec);
+    segment.set_time_base(time_base);
+    ring.push(id, camera, &segment);
+    if let Some(url) = handle.source() {
+        write_source(id, &segment, &url);
+    }
+    // 分段序号跨重启递增，记在分段的 .meta 里
+    let mut sequence = None;
+    handle.update_state(|state| {
+        state.sequence += 1;
+        state.last_segment = Some((segment.path.clone(), segment.started));
+        sequence = Some(state.sequence);
+    });
+    if let Some(sequence) = sequence {
+        if let Err(e) =
+            segment::write_metadata(&segment.path, &[("sequence", sequence.to_string())])
+        {
+            error!(
+                "[Stream {}] Failed to write metadata of {}: {}",
+                id,
+                segment.path.display(),
+                e
+            );
+        }
+    }
+    Ok(segment)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::source::{PacketSource, SourcePacket, Synthetic};
+
+    // 按脚本给出的包，时间基为毫秒；None 表示断线
+    struct Script(VecDeque<Option<SourcePacket>>);
+
+    impl Script {
+        fn new() -> Script {
+            Script(VecDeque::new())
+        }
+
+        fn frame(mut self, ms: i64, key: bool) -> Script {
+            self.0.push_back(Some(SourcePacket {
+                key,
+                pts: Some(ms),
+                dts: Some(ms),
+                data: vec![0, 0, 0, 1, if key { 0x65 } else { 0x41 }],
+            }));
+            self
+        }
+
+        fn disconnect(mut self) -> Script {
+            self.0.push_back(None);
+            self
+        }
+    }
+
+    impl PacketSource for Script {
+        fn time_base(&self) -> (i32, i32) {
+            (1, 1000)
+        }
+
+        fn next_packet(&mut self) -> Result<Option<SourcePacket>, String> {
+            match self.0.pop_front() {
+                Some(Some(packet)) => Ok(Some(packet)),
+                Some(None) => Err("Scripted disconnect".to_string()),
+                None => Ok(None),
+            }
+        }
+    }
+
+    fn camera(quiet: Duration) -> CameraConfig {
+        CameraConfig {
+            quiet_segment: Some(quiet),
+            ..Default::default()
+        }
+    }
+
+    fn packets(segments: &[SimulatedSegment]) -> Vec<usize> {
+        segments.iter().map(|segment| segment.packets).collect()
+    }
+
+    #[test]
+    fn cuts_at_segment_length_on_keyframe() {
+        // 25 帧每秒、每 2 秒一个关键帧，10 秒的分段恰好从关键帧开始
+        let mut source = Synthetic::new(25, 50).frames(1000);
+        let segments = simulate(&camera(Duration::from_secs(10)), &mut source);
+        assert_eq!(packets(&segments), [250; 4]);
+        for segment in &segments {
+            assert!(segment.starts_with_keyframe);
+            assert_eq!(segment.keyframes, 5);
+            assert_eq!(segment.duration, Duration::from_millis(9960));
+        }
+    }
+
+    #[test]
+    fn cuts_before_the_packet_reaching_the_length() {
+        // 每秒一帧、每 5 帧一个关键帧，到 10 秒的那个包开始新分段
+        let mut script = Script::new();
+        for second in 0..25 {
+            script = script.frame(second * 1000, second % 5 == 0);
+        }
+        let segments = simulate(&camera(Duration::from_secs(10)), &mut script);
+        assert_eq!(packets(&segments), [10, 10, 5]);
+        assert_eq!(
+            segments.iter().map(|s| s.keyframes).collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+        assert!(segments.iter().all(|s| s.starts_with_keyframe));
+        assert_eq!(segments[0].duration, Duration::from_secs(9));
+    }
+
+    #[test]
+    fn short_gap_counts_towards_the_segment() {
+        let mut source = Synthetic::new(25, 50)
+            .frames(125)
+            .gap(Duration::from_secs(5))
+            .frames(125);
+        let segments = simulate(&camera(Duration::from_secs(10)), &mut source);
+        assert_eq!(packets(&segments), [125, 125]);
+        assert_eq!(segments[0].duration, Duration::from_millis(4960));
+    }
+
+    #[test]
+    fn timestamp_jump_is_not_counted() {
+        // 超过 10 秒的跳变视为摄像头重启，不计入时长
+        let mut source = Synthetic::new(25, 50)
+            .frames(125)
+            .gap(Duration::from_secs(30))
+            .frames(125);
+        let segments = simulate(&camera(Duration::from_secs(10)), &mut source);
+        assert_eq!(packets(&segments), [250]);
+        assert_eq!(segments[0].duration, Duration::from_millis(9920));
+    }
+
+    #[test]
+    fn disconnect_starts_a_new_segment_without_grace() {
+        let mut source = Synthetic::new(25, 50).frames(100).disconnect().frames(100);
+        let segments = simulate(&camera(Duration::from_secs(300)), &mut source);
+        assert_eq!(packets(&segments), [100, 100]);
+        assert!(segments.iter().all(|s| s.starts_with_keyframe));
+        assert!(segments.iter().all(|s| s.reconnects == 0));
+    }
+
+    #[test]
+    fn reconnect_within_grace_continues_the_segment() {
+        let camera = CameraConfig {
+            continue_grace: Some(Duration::from_secs(30)),
+            ..camera(Duration::from_secs(300))
+        };
+        let mut source = Synthetic::new(25, 50).frames(100).disconnect().frames(100);
+        let segments = simulate(&camera, &mut source);
+        assert_eq!(packets(&segments), [200]);
+        assert_eq!(segments[0].reconnects, 1);
+        // 新连接的时间戳从零开始，时长接着之前的累计
+        assert_eq!(segments[0].duration, Duration::from_millis(7920));
+    }
+
+    #[test]
+    fn reports_pre_roll_before_the_first_keyframe() {
+        // 从 GOP 中间接入：第一个关键帧之前的包也写进分段，分段标记为不以关键帧开头
+        let mut script = Script::new()
+            .frame(0, false)
+            .frame(40, false)
+            .frame(80, true)
+            .frame(120, false)
+            .disconnect()
+            .frame(0, false)
+            .frame(40, true);
+        let segments = simulate(&camera(Duration::from_secs(300)), &mut script);
+        assert_eq!(packets(&segments), [4, 2]);
+        assert!(segments.iter().all(|s| !s.starts_with_keyframe));
+        assert!(segments.iter().all(|s| s.keyframes == 1));
+        assert_eq!(segments[0].duration, Duration::from_millis(120));
+    }
+}
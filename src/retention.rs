@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::config::CameraConfig;
+use crate::control::StreamHandle;
+use crate::index::{self, Record};
+use crate::preview;
+use crate::segment;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(600);
+
+// registry 包括运行中新增的摄像头，每次清理时重新读取
+pub fn spawn(
+    registry: Arc<Mutex<Vec<Arc<StreamHandle>>>>,
+    running: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_sweep: Option<Instant> = None;
+        while running.load(Ordering::SeqCst) {
+            if last_sweep.is_none_or(|t| t.elapsed() >= SWEEP_INTERVAL) {
+                let streams = registry.lock().unwrap().clone();
+                for handle in &streams {
+                    sweep(&handle.camera);
+                    preview::sweep(&handle.camera);
+                }
+                last_sweep = Some(Instant::now());
+            }
+            // 小步睡眠，按下 q 后能及时退出
+            thread::sleep(Duration::from_secs(1));
+        }
+    })
+}
+
+fn sweep(camera: &CameraConfig) {
+    // 顺便从索引中去掉已被删除（环形缓冲、清理或人工删除）的分段；
+    // 子码流的行在主码流的索引里，由主码流整理（子进程模式下两者在不同进程）
+    if camera.substream_of.is_none() {
+        let compacted = index::retain(camera, |record| match record {
+            Record::Segment { path, .. } | Record::Encrypted { path, .. } => path.exists(),
+            _ => true,
+        });
+        if let Err(e) = compacted {
+            error!(
+                "[Retention] Failed to compact index of {}: {}",
+                camera.name, e
+            );
+        }
+    }
+
+    let days = match camera.retention_days {
+        Some(days) => days,
+        None => return,
+    };
+    let segments = match segment::list_segments(camera) {
+        Ok(segments) => segments,
+        Err(e) => {
+            error!(
+                "[Retention] Failed to list segments of {}: {}",
+                camera.name, e
+            );
+            return;
+        }
+    };
+    let holds = match index::holds(camera) {
+        Ok(holds) => holds,
+        Err(e) => {
+            // 读不到保全记录时宁可这次不清理
+            error!("[Retention] Failed to read holds of {}: {}", camera.name, e);
+            return;
+        }
+    };
+    let cutoff = chrono::Local::now().naive_local() - chrono::Duration::days(days as i64);
+    // 分段的结束时间取下一个分段的开始时间，最新的分段永远不删
+    for pair in segments.windows(2) {
+        let (path, started) = &pair[0];
+        let (_, next_started) = &pair[1];
+        if *next_started >= cutoff {
+            break;
+        }
+        if index::is_held(&holds, *started, *next_started) {
+            continue;
+        }
+        match segment::remove_segment(path) {
+            Ok(_) => info!("[Retention] Removed expired segment {}", path.display()),
+            Err(e) => error!("[Retention] Failed to remove {}: {}", path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+// Rockchip 板子（RK3566/RK3588 等）上的 MPP 硬件解码和 RGA 缩放，
+// 依赖 ffmpeg-rockchip 提供的 *_rkmpp 解码器和 scale_rkrga 滤镜
+use std::path::Path;
+
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::codec::context::Context;
+use ffmpeg::{ffi, filter, frame};
+
+use crate::mux::StreamInfo;
+
+// 运行时检测：同一个程序也会部署到没有 MPP 的机器上
+pub fn mpp_available() -> bool {
+    Path::new("/dev/mpp_service").exists()
+}
+
+pub fn rga_available() -> bool {
+    Path::new("/dev/rga").exists()
+}
+
+pub fn open_decoder(info: &StreamInfo) -> Result<ffmpeg::decoder::Video, String> {
+    if !mpp_available() {
+        return Err("/dev/mpp_service not found".to_string());
+    }
+    let name = match info.parameters.id() {
+        ffmpeg::codec::Id::H264 => "h264_rkmpp",
+        ffmpeg::codec::Id::HEVC => "hevc_rkmpp",
+        id => return Err(format!("{:?} is not supported by MPP", id)),
+    };
+    let codec = ffmpeg::decoder::find_by_name(name)
+        .ok_or_else(|| format!("ffmpeg was built without {}", name))?;
+    Context::from_parameters(info.parameters.clone())
+        .and_then(|context| context.decoder().open_as(codec))
+        .and_then(|opened| opened.video())
+        .map_err(|e| e.to_string())
+}
+
+// 用 RGA 把 MPP 解码出的 DRM_PRIME 帧缩放为 NV12 并下载到内存，不经过 CPU 缩放
+pub fn scale(decoded: &frame::Video, width: u32, height: u32) -> Result<frame::Video, String> {
+    let graph_error = |e: ffmpeg::Error| format!("Failed to scale with RGA: {}", e);
+    let mut graph = filter::Graph::new();
+    let args = format!(
+        "video_size={}x{}:pix_fmt=drm_prime:time_base=1/1",
+        decoded.width(),
+        decoded.height()
+    );
+    let buffer = filter::find("buffer").ok_or("No buffer filter")?;
+    let sink = filter::find("buffersink").ok_or("No buffersink filter")?;
+    graph.add(&buffer, "in", &args).map_err(graph_error)?;
+    graph.add(&sink, "out", "").map_err(graph_error)?;
+    // 硬件帧需要把帧池交给 buffer 滤镜
+    unsafe {
+        let mut source = graph.get("in").ok_or("No buffer source")?;
+        let parameters = ffi::av_buffersrc_parameters_alloc();
+        if parameters.is_null() {
+            return Err("Failed to allocate buffer parameters".to_string());
+        }
+        (*parameters).hw_frames_ctx = (*decoded.as_ptr()).hw_frames_ctx;
+        let code = ffi::av_buffersrc_parameters_set(source.as_mut_ptr(), parameters);
+        ffi::av_free(parameters as *mut _);
+        if code < 0 {
+            return Err(graph_error(ffmpeg::Error::from(code)));
+        }
+    }
+    graph
+        .output("in", 0)
+        .and_then(|parser| parser.input("out", 0))
+        .and_then(|parser| {
+            parser.parse(&format!(
+                "scale_rkrga=w={}:h={}:format=nv12,hwdownload,format=nv12",
+                width, height
+            ))
+        })
+        .and_then(|_| graph.validate())
+        .map_err(graph_error)?;
+    let mut scaled = frame::Video::empty();
+    graph
+        .get("in")
+        .ok_or("No buffer source")?
+        .source()
+        .add(decoded)
+        .map_err(graph_error)?;
+    graph
+        .get("out")
+        .ok_or("No buffer sink")?
+        .sink()
+        .frame(&mut scaled)
+        .map_err(graph_error)?;
+    Ok(scaled)
+}
@@ -0,0 +1,156 @@
+// Lua 脚本钩子（需要 lua feature）：脚本中定义的全局函数会在对应事件发生时被调用
+//   on_segment_closed(path, camera, start, end) 可返回一个表，写入分段的 .meta
+//   on_stream_state(camera, state, detail)      state 为 "up"、"down" 或 "paused"
+//   on_event(camera, source)                    触发文件等产生的事件
+//   on_event_clip(camera, source, path)         事件片段生成后
+// 脚本可以调用 recorder.log(message) 和 recorder.event(camera, source[, seconds])
+use std::path::Path;
+
+use crate::config::CameraConfig;
+#[cfg(feature = "lua")]
+use crate::plugin::{self, RecorderPlugin, SegmentInfo, StreamState};
+
+#[cfg(feature = "lua")]
+mod lua {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Mutex, OnceLock};
+
+    use mlua::{FromLuaMulti, Function, IntoLuaMulti, Lua, Table};
+
+    use crate::clip;
+    use crate::config::CameraConfig;
+    use crate::index::{self, Record};
+    use crate::segment;
+
+    static STATE: OnceLock<Mutex<Lua>> = OnceLock::new();
+
+    pub fn init(path: &Path, cameras: Vec<CameraConfig>) -> Result<(), String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let lua = Lua::new();
+        register_api(&lua, cameras).map_err(|e| e.to_string())?;
+        lua.load(&source)
+            .set_name(path.display().to_string())
+            .exec()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        STATE
+            .set(Mutex::new(lua))
+            .map_err(|_| "Script already loaded".to_string())
+    }
+
+    fn register_api(lua: &Lua, cameras: Vec<CameraConfig>) -> mlua::Result<()> {
+        let api = lua.create_table()?;
+        api.set(
+            "log",
+            lua.create_function(|_, message: String| {
+                info!("[Script] {}", message);
+                Ok(())
+            })?,
+        )?;
+        api.set(
+            "event",
+            lua.create_function(
+                move |_, (name, source, seconds): (String, String, Option<u64>)| {
+                    let camera = cameras.iter().find(|c| c.name == name).ok_or_else(|| {
+                        mlua::Error::runtime(format!("No camera named '{}'", name))
+                    })?;
+                    let time = chrono::Local::now().naive_local();
+                    let seconds = seconds.unwrap_or(camera.event_seconds);
+                    let source = format!("script:{}", source);
+                    clip::schedule(camera, &source, time, seconds);
+                    segment::note_event(camera, time, seconds);
+                    let record = Record::Event {
+                        time,
+                        seconds,
+                        source,
+                    };
+                    index::append(camera, &record).map_err(mlua::Error::external)
+                },
+            )?,
+        )?;
+        lua.globals().set("recorder", api)
+    }
+
+    // 调用脚本中的全局函数，未定义时返回 None
+    pub fn call<R: FromLuaMulti>(name: &str, args: impl IntoLuaMulti) -> Option<R> {
+        let lua = STATE.get()?.lock().unwrap();
+        let function: Option<Function> = match lua.globals().get(name) {
+            Ok(function) => function,
+            Err(e) => {
+                error!("[Script] {} is not a function: {}", name, e);
+                return None;
+            }
+        };
+        match function?.call::<R>(args) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                error!("[Script] {} failed: {}", name, e);
+                None
+            }
+        }
+    }
+
+    pub fn table_entries(table: Table) -> Vec<(String, String)> {
+        table
+            .pairs::<String, String>()
+            .filter_map(|pair| pair.ok())
+            .collect()
+    }
+}
+
+#[cfg(feature = "lua")]
+pub fn init(path: &Path, cameras: Vec<CameraConfig>) -> Result<(), String> {
+    lua::init(path, cameras)?;
+    plugin::register(LuaPlugin);
+    Ok(())
+}
+
+#[cfg(not(feature = "lua"))]
+pub fn init(_path: &Path, _cameras: Vec<CameraConfig>) -> Result<(), String> {
+    Err("script requires building with --features lua".to_string())
+}
+
+// 脚本作为一个普通插件接入
+#[cfg(feature = "lua")]
+struct LuaPlugin;
+
+#[cfg(feature = "lua")]
+impl RecorderPlugin for LuaPlugin {
+    fn segment_metadata(&self, segment: &SegmentInfo) -> Vec<(String, String)> {
+        let end = segment.end.unwrap_or(segment.start);
+        let args = (
+            segment.path.display().to_string(),
+            segment.camera.name.clone(),
+            segment.start.format("%Y-%m-%d %H:%M:%S").to_string(),
+            end.format("%Y-%m-%d %H:%M:%S").to_string(),
+        );
+        lua::call::<Option<mlua::Table>>("on_segment_closed", args)
+            .flatten()
+            .map(lua::table_entries)
+            .unwrap_or_default()
+    }
+
+    fn on_stream_state(&self, camera: &CameraConfig, state: &StreamState) {
+        let detail = match state {
+            StreamState::Up => camera.url.clone(),
+            StreamState::Down(error) => error.to_string(),
+            StreamState::Paused | StreamState::Disabled => String::new(),
+        };
+        let args = (camera.name.clone(), state.name().to_string(), detail);
+        lua::call::<()>("on_stream_state", args);
+    }
+
+    fn on_event(&self, camera: &CameraConfig, source: &str) {
+        lua::call::<()>("on_event", (camera.name.clone(), source.to_string()));
+    }
+
+    fn on_event_clip(&self, camera: &CameraConfig, source: &str, clip: &Path) {
+        let args = (
+            camera.name.clone(),
+            source.to_string(),
+            clip.display().to_string(),
+        );
+        lua::call::<()>("on_event_clip", args);
+    }
+}
@@ -0,0 +1,967 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use ffmpeg_the_third::codec::Id;
+use ffmpeg_the_third::{Packet, Rational};
+
+use crate::alert;
+use crate::bus::{self, Event};
+use crate::clock::{Anchor, Jump};
+use crate::config::CameraConfig;
+use crate::encryption;
+use crate::hooks;
+use crate::index::{self, Record};
+use crate::mux::{Muxer, StreamInfo};
+use crate::ntp;
+use crate::plugin::{self, SegmentInfo};
+use crate::quality::{self, Quality};
+use crate::queue::{Limits, QueuedFile};
+use crate::upload;
+
+// 5分钟切分一次
+pub const SEGMENT_DURATION: Duration = Duration::from_secs(300);
+
+// 自适应分段：正在发生事件的摄像头（主码流名）及事件结束时间
+static EVENTS: Mutex<Vec<(String, NaiveDateTime)>> = Mutex::new(Vec::new());
+
+// 没有事件时的分段长度
+pub fn quiet_length(camera: &CameraConfig) -> Duration {
+    camera.quiet_segment.unwrap_or(SEGMENT_DURATION)
+}
+
+// 当前应使用的分段长度
+pub fn target_length(camera: &CameraConfig, in_event: bool) -> Duration {
+    match camera.event_segment {
+        Some(length) if in_event => length,
+        _ => quiet_length(camera),
+    }
+}
+
+// 记录事件时调用；未配置 event_segment_seconds 时不影响分段
+pub fn note_event(camera: &CameraConfig, time: NaiveDateTime, seconds: u64) {
+    if camera.event_segment.is_none() {
+        return;
+    }
+    // 与 in_event 一样按主码流名记录，子码流的子进程也能收到主码流的事件
+    let name = camera.substream_of.as_ref().unwrap_or(&camera.name);
+    let end = time + chrono::Duration::seconds(seconds as i64);
+    let mut events = EVENTS.lock().unwrap();
+    match events.iter_mut().find(|(n, _)| *n == *name) {
+        Some((_, until)) => *until = (*until).max(end),
+        None => events.push((name.clone(), end)),
+    }
+}
+
+pub fn in_event(camera: &CameraConfig) -> bool {
+    let name = camera.substream_of.as_ref().unwrap_or(&camera.name);
+    let now = chrono::Local::now().naive_local();
+    EVENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(n, until)| n == name && *until > now)
+}
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+// 同名文件已存在时最多尝试的序号
+const MAX_ATTEMPTS: u32 = 100;
+
+pub struct Segment {
+    // 主副本的路径；主目录不可用时为镜像副本的路径
+    pub path: PathBuf,
+    pub started: NaiveDateTime,
+    // 同时写入的各个副本，某个副本写入失败后单独放弃
+    outputs: Vec<Output>,
+    // 关键帧索引 <分段>.keyframes
+    keyframes: Option<BufWriter<File>>,
+    // 封装模式（带外接音频）下的时间轴，裸流模式为 None
+    timeline: Option<Timeline>,
+    // 按视频时间戳计算关键帧距分段开始的媒体时长，设置时间基之前为 None
+    media: Option<SegmentClock>,
+    pub bytes: u64,
+    // 是否写在备用目录中
+    pub fallback: bool,
+    quality: Quality,
+    // 视频编码，裸流写入质量统计时需要
+    pub codec: Option<Id>,
+    // 检测系统时钟跳变的基准
+    anchor: Anchor,
+    // 写入队列已满时丢弃的包数
+    dropped: u64,
+}
+
+struct Output {
+    path: PathBuf,
+    sink: Sink,
+}
+
+enum Sink {
+    // 原样写入视频包数据
+    Raw(File),
+    // 同上，经有上限的队列由单独的线程写入，磁盘卡住时不阻塞读流
+    Queued(QueuedFile),
+    // 音视频封装为分片 MP4，写到一半断电也能播放
+    Muxed(Muxer),
+}
+
+impl Output {
+    fn create(
+        path: PathBuf,
+        tracks: Option<&[StreamInfo]>,
+        queue: Option<Limits>,
+    ) -> io::Result<Output> {
+        // 每次切分都确保目录存在，清理程序或运维删掉空目录后也能继续写
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let sink = match tracks {
+            Some(tracks) => Sink::Muxed({
+                // 先占住文件名，muxer 随后打开这个空文件
+                claim(&path)?;
+                Muxer::open(
+                    &path,
+                    Some("mp4"),
+                    tracks,
+                    &[("movflags", "frag_keyframe+empty_moov")],
+                    &[("comment", &quality::mp4_placeholder())],
+                )
+                .map_err(io::Error::other)?
+            }),
+            None => match queue {
+                Some(limits) => Sink::Queued(QueuedFile::new(claim(&path)?, limits)),
+                None => Sink::Raw(claim(&path)?),
+            },
+        };
+        Ok(Output { path, sink })
+    }
+
+    // 返回队列已满时丢弃的包数
+    fn write(&mut self, track: usize, packet: &Packet, data: &[u8]) -> io::Result<u64> {
+        match &mut self.sink {
+            Sink::Raw(file) => file.write_all(data).map(|_| 0),
+            Sink::Queued(queue) => queue.write(data),
+            Sink::Muxed(muxer) => muxer
+                .write(track, packet.clone())
+                .map(|_| 0)
+                .map_err(io::Error::other),
+        }
+    }
+
+    // 写完后把质量统计写进文件
+    fn finish(&mut self, quality: &str, codec: Option<Id>) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Raw(file) => {
+                if let Some(sei) = codec.and_then(|codec| quality::sei(codec, quality)) {
+                    file.write_all(&sei)?;
+                }
+                file.flush()
+            }
+            Sink::Queued(queue) => {
+                if let Some(sei) = codec.and_then(|codec| quality::sei(codec, quality)) {
+                    queue.write(&sei)?;
+                }
+                queue.finish().map(|_| ())
+            }
+            Sink::Muxed(muxer) => {
+                muxer.finish().map_err(io::Error::other)?;
+                quality::fill_mp4_comment(&self.path, quality)
+            }
+        }
+    }
+}
+
+// 封装模式下各轨的时间戳以分段的第一个视频包为零点，并保证每轨 dts 递增
+struct Timeline {
+    base: Option<i64>,
+    last_dts: Vec<Option<i64>>,
+}
+
+impl Timeline {
+    // 返回 None 表示丢弃：早于分段开始的音频包，或分段创建时还没有的音轨
+    fn rebase(&mut self, track: usize, packet: &Packet) -> Option<Packet> {
+        if track >= self.last_dts.len() {
+            return None;
+        }
+        let ts = packet.dts().or(packet.pts())?;
+        if self.base.is_none() && track != 0 {
+            return None;
+        }
+        let base = *self.base.get_or_insert(ts);
+        let mut dts = ts - base;
+        if dts < 0 {
+            return None;
+        }
+        if let Some(last) = self.last_dts[track] {
+            dts = dts.max(last + 1);
+        }
+        self.last_dts[track] = Some(dts);
+        let mut packet = packet.clone();
+        packet.set_dts(Some(dts));
+        packet.set_pts(Some(packet.pts().map_or(dts, |pts| (pts - base).max(dts))));
+        Some(packet)
+    }
+}
+
+// 按视频包的时间戳计算分段已包含的媒体时长，摄像头突发或卡顿时分段仍是整 5 分钟，
+// 长期运行也不会漂移。时间戳回退或跳变（摄像头重启）时累计已有时长后重新计起，
+// 没有时间戳的流退回墙上时钟
+pub struct SegmentClock {
+    time_base: Rational,
+    start: Option<i64>,
+    last: Option<i64>,
+    // 时间戳不连续之前已累计的时长
+    carried: Duration,
+    wall: Instant,
+}
+
+impl SegmentClock {
+    // 超过该值的前进视为时间戳跳变
+    const MAX_STEP: Duration = Duration::from_secs(10);
+
+    pub fn new(time_base: Rational) -> SegmentClock {
+        SegmentClock {
+            time_base,
+            start: None,
+            last: None,
+            carried: Duration::ZERO,
+            wall: Instant::now(),
+        }
+    }
+
+    // 喂入一个视频包的时间戳，返回本分段到该包为止的媒体时长
+    pub fn advance(&mut self, ts: Option<i64>) -> Duration {
+        let ts = match ts {
+            Some(ts) => ts,
+            None if self.start.is_none() => return self.carried + self.wall.elapsed(),
+            None => return self.elapsed(),
+        };
+        let start = *self.start.get_or_insert(ts);
+        if let Some(last) = self.last {
+            if ts < last || self.duration(ts - last) > Self::MAX_STEP {
+                self.carried += self.duration(last - start);
+                self.start = Some(ts);
+            }
+        }
+        self.last = Some(ts);
+        self.elapsed()
+    }
+
+    // 开始新分段，ts 为新分段第一个包的时间戳
+    pub fn restart(&mut self, ts: Option<i64>) {
+        *self = SegmentClock {
+            start: ts,
+            last: ts,
+            ..SegmentClock::new(self.time_base)
+        };
+    }
+
+    // 断线重连后继续同一分段：新连接的时间戳与之前无关，保留已有时长重新计起
+    pub fn resume(&mut self, time_base: Rational) {
+        *self = SegmentClock {
+            carried: self.elapsed(),
+            ..SegmentClock::new(time_base)
+        };
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        match (self.start, self.last) {
+            (Some(start), Some(last)) => self.carried + self.duration(last - start),
+            _ => self.carried,
+        }
+    }
+
+    fn duration(&self, ticks: i64) -> Duration {
+        let (num, den) = (self.time_base.numerator(), self.time_base.denominator());
+        if ticks <= 0 || num <= 0 || den <= 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((ticks as i128 * num as i128 * 1_000_000_000 / den as i128) as u64)
+    }
+}
+
+impl Segment {
+    // tracks 为 None 时原样写入视频包；否则按给出的各轨（第 0 轨为视频）封装
+    pub fn create(camera: &CameraConfig, tracks: Option<&[StreamInfo]>) -> io::Result<Segment> {
+        Segment::create_in(
+            camera,
+            &camera.output_dir,
+            chrono::Local::now().naive_local(),
+            tracks,
+        )
+    }
+
+    // 以指定的开始时间命名，用于补录历史录像
+    pub fn create_at(camera: &CameraConfig, started: NaiveDateTime) -> io::Result<Segment> {
+        Segment::create_in(camera, &camera.output_dir, started, None)
+    }
+
+    pub fn create_fallback(
+        camera: &CameraConfig,
+        tracks: Option<&[StreamInfo]>,
+    ) -> io::Result<Segment> {
+        let dir = camera
+            .fallback_dir
+            .as_ref()
+            .ok_or_else(|| io::Error::other("No fallback_dir configured"))?;
+        let mut segment =
+            Segment::create_in(camera, dir, chrono::Local::now().naive_local(), tracks)?;
+        segment.fallback = true;
+        Ok(segment)
+    }
+
+    fn create_in(
+        camera: &CameraConfig,
+        dir: &Path,
+        started: NaiveDateTime,
+        tracks: Option<&[StreamInfo]>,
+    ) -> io::Result<Segment> {
+        let queue = Limits::of(camera);
+        // 同名文件已存在（重启后同一秒内再次创建、另一个实例在写同一目录）时加上序号，
+        // 不覆盖已有的录像
+        let mut attempt = 1;
+        let (relative, primary) = loop {
+            let relative = relative_path(camera, started, attempt);
+            match Output::create(dir.join(&relative), tracks, queue) {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < MAX_ATTEMPTS => {
+                    attempt += 1
+                }
+                result => break (relative, result),
+            }
+        };
+        if attempt > 1 {
+            error!(
+                "{} already exists, is another recorder writing to {}? Using {}",
+                relative_path(camera, started, 1).display(),
+                dir.display(),
+                relative.display()
+            );
+        }
+        // 镜像目录（如 NAS）与主目录互不影响，任一个可用就不会丢录像
+        let mirror = camera
+            .mirror_dir
+            .as_ref()
+            .map(|mirror| Output::create(mirror.join(&relative), tracks, queue));
+        let outputs = match (primary, mirror) {
+            (Ok(primary), None) => vec![primary],
+            (Ok(primary), Some(Ok(mirror))) => vec![primary, mirror],
+            (Ok(primary), Some(Err(e))) => {
+                alert::raise(
+                    camera,
+                    "mirror",
+                    &format!("Cannot create mirror copy of {}: {}", relative.display(), e),
+                );
+                vec![primary]
+            }
+            (Err(e), Some(Ok(mirror))) => {
+                alert::raise(
+                    camera,
+                    "storage",
+                    &format!(
+                        "Cannot create segment in {}: {}; recording to mirror only",
+                        dir.display(),
+                        e
+                    ),
+                );
+                vec![mirror]
+            }
+            (Err(e), _) => return Err(e),
+        };
+        let path = outputs[0].path.clone();
+        if let Some(sample) = ntp::latest() {
+            write_metadata(
+                &path,
+                &[
+                    ("camera", camera.name.clone()),
+                    ("started", started.format("%Y-%m-%d %H:%M:%S").to_string()),
+                    ("ntp_server", sample.server),
+                    ("ntp_offset_ms", format!("{:.1}", sample.offset_ms)),
+                    (
+                        "ntp_measured",
+                        sample.measured.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    ),
+                ],
+            )?;
+        }
+        let keyframes = if camera.keyframe_index {
+            Some(BufWriter::new(File::create(keyframes_path(&path))?))
+        } else {
+            None
+        };
+        let segment = Segment {
+            path,
+            started,
+            outputs,
+            keyframes,
+            timeline: tracks.map(|tracks| Timeline {
+                base: None,
+                last_dts: vec![None; tracks.len()],
+            }),
+            media: None,
+            bytes: 0,
+            fallback: false,
+            quality: Quality::new(),
+            codec: None,
+            anchor: Anchor::now(),
+            dropped: 0,
+        };
+        bus::publish(Event::SegmentStarted {
+            camera: camera.name.clone(),
+            path: segment.path.clone(),
+            start: segment.started,
+        });
+        Ok(segment)
+    }
+
+    // 写入第 track 轨的一个包，视频关键帧同时登记到关键帧索引
+    pub fn write_packet(
+        &mut self,
+        camera: &CameraConfig,
+        track: usize,
+        packet: &Packet,
+    ) -> io::Result<()> {
+        let data = match packet.data() {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+        let offset = self.bytes;
+        match self.timeline.as_mut() {
+            Some(timeline) => match timeline.rebase(track, packet) {
+                Some(rebased) => self.write(camera, track, &rebased, data)?,
+                None => return Ok(()),
+            },
+            // 裸流只能容纳视频
+            None if track != 0 => return Ok(()),
+            None => self.write(camera, track, packet, data)?,
+        }
+        if track != 0 {
+            return Ok(());
+        }
+        self.quality.record_write();
+        let media = self
+            .media
+            .as_mut()
+            .map(|clock| clock.advance(packet.dts().or(packet.pts())));
+        if packet.is_key() {
+            if let Some(keyframes) = &mut self.keyframes {
+                // 字节偏移（只有裸流的包原样落在文件里，封装模式留空）、距分段第一个包的
+                // 媒体毫秒数、原始 pts
+                writeln!(
+                    keyframes,
+                    "{}\t{}\t{}",
+                    if self.timeline.is_none() {
+                        offset.to_string()
+                    } else {
+                        String::new()
+                    },
+                    media.map_or(String::new(), |media| media.as_millis().to_string()),
+                    packet.pts().map_or(String::new(), |pts| pts.to_string())
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // 只有所有副本都写入失败时才返回错误
+    fn write(
+        &mut self,
+        camera: &CameraConfig,
+        track: usize,
+        packet: &Packet,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let mut failed = None;
+        let mut dropped = 0;
+        self.outputs
+            .retain_mut(|output| match output.write(track, packet, data) {
+                Ok(count) => {
+                    dropped = dropped.max(count);
+                    true
+                }
+                Err(e) => {
+                    failed = Some((output.path.clone(), e));
+                    false
+                }
+            });
+        if let Some((path, e)) = failed {
+            if self.outputs.is_empty() {
+                return Err(e);
+            }
+            self.dropped_output(camera, &path, &e, "write");
+        }
+        if dropped > 0 {
+            if self.dropped == 0 {
+                error!(
+                    "[Stream {}] Write queue of {} is full, dropping the oldest packets",
+                    camera.name,
+                    self.path.display()
+                );
+            }
+            self.dropped += dropped;
+            for _ in 0..dropped {
+                self.quality.record_drop();
+            }
+        }
+        self.bytes += data.len() as u64;
+        Ok(())
+    }
+
+    // 一个副本失败后告警；失败的是主副本时改以剩下的副本为准，
+    // 之后的元数据、索引、钩子和上传都指向它，主副本旁的关键帧索引随之作废
+    fn dropped_output(&mut self, camera: &CameraConfig, path: &Path, e: &io::Error, action: &str) {
+        alert::raise(
+            camera,
+            "storage",
+            &format!(
+                "Failed to {} {}: {}; continuing on {}",
+                action,
+                path.display(),
+                e,
+                self.outputs[0].path.display()
+            ),
+        );
+        if *path == self.path {
+            self.path = self.outputs[0].path.clone();
+            self.keyframes = None;
+        }
+    }
+
+    fn info<'a>(&'a self, camera: &'a CameraConfig, end: Option<NaiveDateTime>) -> SegmentInfo<'a> {
+        SegmentInfo {
+            camera,
+            path: &self.path,
+            start: self.started,
+            end,
+            bytes: self.bytes,
+        }
+    }
+
+    // 分段开始以来系统时钟是否跳变过，camera.clock_jump 为 None 时不检测
+    pub fn clock_jump(&self, camera: &CameraConfig) -> Option<Jump> {
+        self.anchor.check(camera.clock_jump?)
+    }
+
+    // 系统时钟一步跳变后按新的时钟改正开始时间：各副本连同 .meta、.keyframes 改名，
+    // 文件名与索引中的时间一致，元数据里记下改正的秒数
+    pub fn retime(&mut self, camera: &CameraConfig, delta: chrono::Duration) -> io::Result<()> {
+        let started = self.started + delta;
+        let depth = relative_path(camera, self.started, 1).components().count();
+        let mut roots = Vec::new();
+        for output in &self.outputs {
+            let root = output
+                .path
+                .ancestors()
+                .nth(depth)
+                .ok_or_else(|| io::Error::other("Unexpected segment path"))?;
+            roots.push(root.to_path_buf());
+        }
+        // 与创建时一样，改正后的名字已被占用时加上序号。先用硬链接占住新名字：名字已存在时
+        // 失败，不会像 rename 那样覆盖另一个实例刚创建的文件；全部占住后再删掉旧名字
+        let mut claimed = None;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let relative = relative_path(camera, started, attempt);
+            let targets: Vec<PathBuf> = roots.iter().map(|root| root.join(&relative)).collect();
+            match link_all(&self.outputs, &targets) {
+                Ok(_) => {
+                    claimed = Some(targets);
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let targets = claimed
+            .ok_or_else(|| io::Error::new(io::ErrorKind::AlreadyExists, "No free file name"))?;
+        for (output, target) in self.outputs.iter_mut().zip(targets) {
+            fs::remove_file(&output.path)?;
+            for (from, to) in [
+                (metadata_path(&output.path), metadata_path(&target)),
+                (keyframes_path(&output.path), keyframes_path(&target)),
+            ] {
+                if from.exists() {
+                    fs::rename(from, to)?;
+                }
+            }
+            output.path = target;
+        }
+        self.path = self.outputs[0].path.clone();
+        self.started = started;
+        self.anchor = Anchor::now();
+        write_metadata(
+            &self.path,
+            &[("clock_corrected_seconds", delta.num_seconds().to_string())],
+        )
+    }
+
+    // 读取失败、丢弃了一个包
+    pub fn record_drop(&mut self) {
+        self.quality.record_drop();
+    }
+
+    // 视频流的时间基，用于关键帧索引里的媒体时间；重连后继续同一分段时再次设置，
+    // 新连接的时间戳从头计起
+    pub fn set_time_base(&mut self, time_base: Rational) {
+        match self.media.as_mut() {
+            Some(clock) => clock.resume(time_base),
+            None => self.media = Some(SegmentClock::new(time_base)),
+        }
+    }
+
+    // 断线重连后继续写入
+    pub fn record_reconnect(&mut self) {
+        self.quality.record_reconnect();
+    }
+
+    // 分段结束：确保数据写入磁盘，执行钩子并登记到索引和上传队列
+    pub fn finish(&mut self, camera: &CameraConfig, ended: NaiveDateTime) -> io::Result<()> {
+        let quality = self.quality.describe();
+        let codec = self.codec;
+        let mut failed = Vec::new();
+        self.outputs
+            .retain_mut(|output| match output.finish(&quality, codec) {
+                Ok(_) => true,
+                Err(e) => {
+                    failed.push((output.path.clone(), e));
+                    false
+                }
+            });
+        // 只有所有副本都收尾失败时才放弃登记
+        if self.outputs.is_empty() {
+            if let Some((_, e)) = failed.pop() {
+                return Err(e);
+            }
+        }
+        for (path, e) in failed {
+            self.dropped_output(camera, &path, &e, "finish");
+        }
+        if let Some(keyframes) = &mut self.keyframes {
+            keyframes.flush()?;
+        }
+        // 丢过包后关键帧索引里的字节偏移不再准确
+        if self.dropped > 0 {
+            self.keyframes = None;
+            remove_if_exists(&keyframes_path(&self.path))?;
+            write_metadata(
+                &self.path,
+                &[("write_queue_dropped", self.dropped.to_string())],
+            )?;
+        }
+        let entries = plugin::segment_metadata(&self.info(camera, Some(ended)));
+        if !entries.is_empty() {
+            write_metadata(&self.path, &entries)?;
+        }
+        bus::publish(Event::SegmentFinished {
+            camera: camera.name.clone(),
+            path: self.path.clone(),
+            start: self.started,
+            end: ended,
+            bytes: self.bytes,
+        });
+        // 加密失败时分段保持明文，之后的重新加密会再试
+        if let Err(e) = encryption::seal(camera, &self.path) {
+            error!("[Stream {}] {}", camera.name, e);
+        }
+        hooks::segment_finished(camera, &self.path, self.started, ended);
+        index::append(
+            camera,
+            &Record::Segment {
+                start: self.started,
+                end: ended,
+                bytes: self.bytes,
+                path: self.path.clone(),
+                substream: camera.substream_of.is_some(),
+            },
+        )?;
+        upload::enqueue(camera, &self.path)
+    }
+}
+
+// 给每个副本加上对应的新名字；任何一个失败时撤销本次已加的链接
+fn link_all(outputs: &[Output], targets: &[PathBuf]) -> io::Result<()> {
+    for (i, (output, target)) in outputs.iter().zip(targets).enumerate() {
+        let result = match target.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|_| fs::hard_link(&output.path, target));
+        if let Err(e) = result {
+            for target in &targets[..i] {
+                let _ = fs::remove_file(target);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+// 分段旁的元数据文件 <分段>.meta，每行 key=value
+pub fn metadata_path(segment: &Path) -> PathBuf {
+    let mut name = segment.as_os_str().to_owned();
+    name.push(".meta");
+    PathBuf::from(name)
+}
+
+// 关键帧索引 <分段>.keyframes，每行：字节偏移（仅裸流）、距分段第一个包的媒体毫秒数、pts，以 tab 分隔
+pub fn keyframes_path(segment: &Path) -> PathBuf {
+    let mut name = segment.as_os_str().to_owned();
+    name.push(".keyframes");
+    PathBuf::from(name)
+}
+
+pub fn write_metadata<K: AsRef<str>>(segment: &Path, entries: &[(K, String)]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(metadata_path(segment))?;
+    for (key, value) in entries {
+        writeln!(file, "{}={}", key.as_ref(), value)?;
+    }
+    Ok(())
+}
+
+// 分段相对于录像目录的路径；attempt 大于 1 时文件名带序号，如 gate_20240501_080000_2.mp4
+fn relative_path(camera: &CameraConfig, started: NaiveDateTime, attempt: u32) -> PathBuf {
+    let suffix = if attempt > 1 {
+        format!("_{}", attempt)
+    } else {
+        String::new()
+    };
+    Path::new(&render_subdir(camera, started)).join(format!(
+        "{}_{}{}.mp4",
+        camera.name,
+        started.format(TIMESTAMP_FORMAT),
+        suffix
+    ))
+}
+
+// 只创建新文件，已存在时返回 AlreadyExists
+fn claim(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+fn render_subdir(camera: &CameraConfig, started: NaiveDateTime) -> String {
+    camera
+        .subdir
+        .replace("{camera}", &camera.name)
+        .replace(
+            "{tag}",
+            camera.tags.first().map_or("untagged", String::as_str),
+        )
+        .replace("{date}", &started.format("%Y-%m-%d").to_string())
+        .replace("{year}", &started.format("%Y").to_string())
+        .replace("{month}", &started.format("%m").to_string())
+        .replace("{day}", &started.format("%d").to_string())
+        .replace("{hour}", &started.format("%H").to_string())
+}
+
+// 让外部工具总能拿到最新完成的分段：unix 上维护 <name>_latest.mp4 软链接，
+// 其他平台写 <name>_latest.json
+pub fn update_latest(id: usize, camera: &CameraConfig, finished: &Path) {
+    if !camera.latest_link {
+        return;
+    }
+    if let Err(e) = write_latest(camera, finished) {
+        error!("[Stream {}] Failed to update latest pointer: {}", id, e);
+    }
+}
+
+#[cfg(unix)]
+fn write_latest(camera: &CameraConfig, finished: &Path) -> io::Result<()> {
+    let dir = &camera.output_dir;
+    let link = dir.join(format!("{}_latest.mp4", camera.name));
+    let tmp = dir.join(format!(".{}_latest.mp4.tmp", camera.name));
+    // 分段在 output_dir 内时使用相对路径，整个目录搬走后链接依然有效；
+    // 分段在备用目录时使用绝对路径
+    let target = match finished.strip_prefix(dir) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => fs::canonicalize(finished)?,
+    };
+    let _ = fs::remove_file(&tmp);
+    std::os::unix::fs::symlink(target, &tmp)?;
+    // rename 覆盖旧链接是原子的，读取方不会看到链接缺失
+    fs::rename(&tmp, &link)
+}
+
+#[cfg(not(unix))]
+fn write_latest(camera: &CameraConfig, finished: &Path) -> io::Result<()> {
+    let pointer = camera
+        .output_dir
+        .join(format!("{}_latest.json", camera.name));
+    let tmp = camera
+        .output_dir
+        .join(format!(".{}_latest.json.tmp", camera.name));
+    let escaped = finished
+        .display()
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    fs::write(&tmp, format!("{{\"path\": \"{}\"}}\n", escaped))?;
+    fs::rename(&tmp, &pointer)
+}
+
+// 列出某个摄像头已有的分段（包括备用目录中的，按开始时间排序）
+pub fn list_segments(camera: &CameraConfig) -> io::Result<Vec<(PathBuf, NaiveDateTime)>> {
+    let mut segments = scan_dir(camera, &camera.output_dir)?;
+    if let Some(fallback) = &camera.fallback_dir {
+        match scan_dir(camera, fallback) {
+            Ok(found) => segments.extend(found),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    segments.sort_by_key(|(_, started)| *started);
+    Ok(segments)
+}
+
+fn scan_dir(camera: &CameraConfig, dir: &Path) -> io::Result<Vec<(PathBuf, NaiveDateTime)>> {
+    let prefix = format!("{}_", camera.name);
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // 按子目录模板存放的分段分布在各级子目录中
+        if entry.file_type()?.is_dir() {
+            segments.extend(scan_dir(camera, &path)?);
+            continue;
+        }
+        let started = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|n| n.strip_suffix(".mp4"))
+            .and_then(parse_timestamp);
+        if let Some(started) = started {
+            segments.push((path, started));
+        }
+    }
+    Ok(segments)
+}
+
+// 文件名中的开始时间，可能带有去重的序号
+fn parse_timestamp(text: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(text, TIMESTAMP_FORMAT)
+        .ok()
+        .or_else(|| {
+            let (timestamp, attempt) = text.rsplit_once('_')?;
+            attempt.parse::<u32>().ok()?;
+            NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()
+        })
+}
+
+// 删除分段及其元数据和关键帧索引，文件已不存在时视为成功
+pub fn remove_segment(path: &Path) -> io::Result<()> {
+    remove_if_exists(&metadata_path(path))?;
+    remove_if_exists(&keyframes_path(path))?;
+    remove_if_exists(path)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+// 行车记录仪模式：只保留最近 N 个分段或最近 M 分钟
+pub struct SegmentRing {
+    keep_segments: Option<usize>,
+    keep_minutes: Option<u64>,
+    segments: VecDeque<(PathBuf, NaiveDateTime)>,
+}
+
+impl SegmentRing {
+    pub fn new(id: usize, camera: &CameraConfig) -> SegmentRing {
+        let mut segments = VecDeque::new();
+        if camera.keep_segments.is_some() || camera.keep_minutes.is_some() {
+            // 把上次运行留下的分段也算进来
+            match list_segments(camera) {
+                Ok(existing) => segments.extend(existing),
+                Err(e) => error!("[Stream {}] Failed to scan existing segments: {}", id, e),
+            }
+        }
+        SegmentRing {
+            keep_segments: camera.keep_segments,
+            keep_minutes: camera.keep_minutes,
+            segments,
+        }
+    }
+
+    // 记录新创建的分段并删除超出限制的旧分段；保全的分段既不删除也不计入限制
+    pub fn push(&mut self, id: usize, camera: &CameraConfig, segment: &Segment) {
+        if self.keep_segments.is_none() && self.keep_minutes.is_none() {
+            return;
+        }
+        self.segments
+            .push_back((segment.path.clone(), segment.started));
+
+        let holds = match index::holds(camera) {
+            Ok(holds) => holds,
+            Err(e) => {
+                error!("[Stream {}] Failed to read holds: {}", id, e);
+                return;
+            }
+        };
+        // 最新的分段正在写入，永远不删
+        let mut i = 0;
+        while i + 1 < self.segments.len() {
+            if self.is_held(&holds, i) {
+                i += 1;
+                continue;
+            }
+            let free = (0..self.segments.len())
+                .filter(|j| !self.is_held(&holds, *j))
+                .count();
+            if !self.is_expired(self.segments[i + 1].1, free) {
+                break;
+            }
+            if let Some((path, _)) = self.segments.remove(i) {
+                match remove_segment(&path) {
+                    Ok(_) => info!("[Stream {}] Removed old segment {}", id, path.display()),
+                    Err(e) => error!(
+                        "[Stream {}] Failed to remove old segment {}: {}",
+                        id,
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+    }
+
+    // 分段因时钟改正改了名
+    pub fn retimed(&mut self, old: &Path, segment: &Segment) {
+        if let Some(entry) = self.segments.iter_mut().find(|(path, _)| path == old) {
+            *entry = (segment.path.clone(), segment.started);
+        }
+    }
+
+    // 分段的结束时间即下一个分段的开始时间，最新的分段到现在为止
+    fn is_held(&self, holds: &[(NaiveDateTime, NaiveDateTime)], i: usize) -> bool {
+        let end = self
+            .segments
+            .get(i + 1)
+            .map_or_else(|| chrono::Local::now().naive_local(), |(_, next)| *next);
+        index::is_held(holds, self.segments[i].1, end)
+    }
+
+    // 结束于 end 的最旧未保全分段是否超出限制，free 为未保全的分段数
+    fn is_expired(&self, end: NaiveDateTime, free: usize) -> bool {
+        if let Some(keep) = self.keep_segments {
+            if free > keep {
+                return true;
+            }
+        }
+        if let Some(minutes) = self.keep_minutes {
+            let cutoff =
+                chrono::Local::now().naive_local() - chrono::Duration::minutes(minutes as i64);
+            if end < cutoff {
+                return true;
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,270 @@
+// 可用率（SLA）：每次连接状态变化时在 <output_dir>/<name>.uptime 追加一行 "时间\t状态"，
+// 按月统计摄像头在应当在线的时间里实际连上的比例，用来向业主证明摄像头的在线情况。
+// 连上（up）计为在线；断线（down）和自动停用（disabled）计为应在线但不在线；
+// 手动暂停、待机和录制程序没有运行（stopped）的时间不计入
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+
+use crate::config::CameraConfig;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// 每个摄像头最后记下的状态，重复的状态（如每次重试都失败）不再写
+static LAST: Mutex<Vec<(String, &'static str)>> = Mutex::new(Vec::new());
+
+pub struct Availability {
+    pub expected: Duration,
+    pub connected: Duration,
+    pub outages: usize,
+    pub longest_outage: Duration,
+    pub total_outage: Duration,
+}
+
+impl Availability {
+    // 没有应在线的时间时为 None
+    pub fn percent(&self) -> Option<f64> {
+        (!self.expected.is_zero())
+            .then(|| self.connected.as_secs_f64() * 100.0 / self.expected.as_secs_f64())
+    }
+
+    // 平均恢复时间
+    pub fn mean_outage(&self) -> Duration {
+        self.total_outage
+            .checked_div(self.outages as u32)
+            .unwrap_or_default()
+    }
+}
+
+fn uptime_path(camera: &CameraConfig) -> PathBuf {
+    camera.output_dir.join(format!("{}.uptime", camera.name))
+}
+
+// 记下一次状态变化，state 为 up、down、paused、disabled 或 stopped
+pub fn record(camera: &CameraConfig, state: &'static str) {
+    {
+        let mut last = LAST.lock().unwrap();
+        match last.iter_mut().find(|(name, _)| *name == camera.name) {
+            Some((_, previous)) if *previous == state => return,
+            Some((_, previous)) => *previous = state,
+            None => last.push((camera.name.clone(), state)),
+        }
+    }
+    let now = chrono::Local::now().naive_local();
+    if let Err(e) = append(camera, now, state) {
+        error!(
+            "[SLA] Failed to write {}: {}",
+            uptime_path(camera).display(),
+            e
+        );
+    }
+}
+
+fn append(camera: &CameraConfig, time: NaiveDateTime, state: &str) -> io::Result<()> {
+    fs::create_dir_all(&camera.output_dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(uptime_path(camera))?;
+    writeln!(file, "{}\t{}", time.format(TIME_FORMAT), state)
+}
+
+// 启动时调用：上次运行没有正常结束（最后一行不是 stopped）时，按状态文件最后写入的时间
+// 补一行 stopped，程序没在运行的时间不算作摄像头在线或断线
+pub fn resume(camera: &CameraConfig) {
+    let transitions = match load(camera) {
+        Ok(transitions) => transitions,
+        Err(e) => {
+            error!(
+                "[SLA] Failed to read {}: {}",
+                uptime_path(camera).display(),
+                e
+            );
+            return;
+        }
+    };
+    let (last_time, last_state) = match transitions.last() {
+        Some((time, state)) if state != "stopped" => (*time, state.clone()),
+        _ => return,
+    };
+    let state_file = camera.output_dir.join(format!("{}.state", camera.name));
+    let stopped = fs::metadata(state_file)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| chrono::DateTime::<chrono::Local>::from(modified).naive_local())
+        .unwrap_or(last_time)
+        .max(last_time);
+    info!(
+        "[SLA] {} was {} when the last run ended unexpectedly around {}",
+        camera.name,
+        last_state,
+        stopped.format(TIME_FORMAT)
+    );
+    if let Err(e) = append(camera, stopped, "stopped") {
+        error!(
+            "[SLA] Failed to write {}: {}",
+            uptime_path(camera).display(),
+            e
+        );
+    }
+}
+
+// 状态变化，按时间排序；看不懂的行忽略
+fn load(camera: &CameraConfig) -> io::Result<Vec<(NaiveDateTime, String)>> {
+    let text = match fs::read_to_string(uptime_path(camera)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut transitions: Vec<(NaiveDateTime, String)> = text
+        .lines()
+        .filter_map(|line| {
+            let (time, state) = line.split_once('\t')?;
+            let time = NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok()?;
+            Some((time, state.trim().to_string()))
+        })
+        .collect();
+    transitions.sort_by_key(|(time, _)| *time);
+    Ok(transitions)
+}
+
+// [from, to) 内的可用率；最后一个状态持续到现在
+pub fn availability(
+    camera: &CameraConfig,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> io::Result<Availability> {
+    let transitions = load(camera)?;
+    let now = chrono::Local::now().naive_local();
+    let mut availability = Availability {
+        expected: Duration::ZERO,
+        connected: Duration::ZERO,
+        outages: 0,
+        longest_outage: Duration::ZERO,
+        total_outage: Duration::ZERO,
+    };
+    // 当前这次断线在范围内的时长，跨越几行（down 之后 disabled）也算一次
+    let mut outage: Option<Duration> = None;
+    for (index, (start, state)) in transitions.iter().enumerate() {
+        let end = transitions
+            .get(index + 1)
+            .map_or(now, |(next, _)| *next)
+            .min(to);
+        let start = (*start).max(from);
+        let length = (end - start).to_std().unwrap_or_default();
+        let down = matches!(state.as_str(), "down" | "disabled");
+        if state == "up" {
+            availability.expected += length;
+            availability.connected += length;
+        } else if down {
+            availability.expected += length;
+        }
+        if down {
+            *outage.get_or_insert(Duration::ZERO) += length;
+        } else if let Some(length) = outage.take() {
+            // 暂停或程序停止也结束一次断线
+            end_outage(&mut availability, length);
+        }
+    }
+    if let Some(length) = outage {
+        end_outage(&mut availability, length);
+    }
+    Ok(availability)
+}
+
+fn end_outage(availability: &mut Availability, length: Duration) {
+    if length.is_zero() {
+        return;
+    }
+    availability.outages += 1;
+    availability.total_outage += length;
+    availability.longest_outage = availability.longest_outage.max(length);
+}
+
+// YYYY-MM 的起止时间
+pub fn month(text: &str) -> Result<(NaiveDateTime, NaiveDateTime), String> {
+    let start = NaiveDate::parse_from_str(&format!("{}-01", text), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid month '{}', expected YYYY-MM", text))?;
+    let end = if start.month() == 12 {
+        NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(start.year(), start.month() + 1, 1)
+    }
+    .ok_or_else(|| format!("Invalid month '{}'", text))?;
+    Ok((
+        start.and_hms_opt(0, 0, 0).unwrap(),
+        end.and_hms_opt(0, 0, 0).unwrap(),
+    ))
+}
+
+pub fn current_month() -> String {
+    chrono::Local::now().format("%Y-%m").to_string()
+}
+
+// 一个摄像头一个月的报告，每行 key=value，HTTP 接口和命令行共用
+pub fn report(camera: &CameraConfig, month: &str) -> Result<String, String> {
+    let (from, to) = self::month(month)?;
+    let availability = availability(camera, from, to)
+        .map_err(|e| format!("Failed to read {}: {}", uptime_path(camera).display(), e))?;
+    let mut text = format!(
+        "camera={}\nmonth={}\nexpected_hours={:.2}\nconnected_hours={:.2}\n",
+        camera.name,
+        month,
+        availability.expected.as_secs_f64() / 3600.0,
+        availability.connected.as_secs_f64() / 3600.0
+    );
+    if let Some(percent) = availability.percent() {
+        text.push_str(&format!("availability_percent={:.3}\n", percent));
+    }
+    text.push_str(&format!(
+        "outages={}\nlongest_outage_minutes={:.1}\nmean_time_to_restore_minutes={:.1}\n",
+        availability.outages,
+        availability.longest_outage.as_secs_f64() / 60.0,
+        availability.mean_outage().as_secs_f64() / 60.0
+    ));
+    if let Some(target) = camera.sla_target {
+        let met = availability
+            .percent()
+            .is_none_or(|percent| percent >= target);
+        text.push_str(&format!("target_percent={}\ntarget_met={}\n", target, met));
+    }
+    Ok(text)
+}
+
+// 命令行的月度汇总，每个摄像头一行
+pub fn print_summary(cameras: &[&CameraConfig], month: &str) -> Result<(), String> {
+    let (from, to) = self::month(month)?;
+    println!("Availability in {}:", month);
+    let width = cameras.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for camera in cameras {
+        let availability = availability(camera, from, to)
+            .map_err(|e| format!("Failed to read {}: {}", uptime_path(camera).display(), e))?;
+        let percent = match availability.percent() {
+            Some(percent) => format!("{:.3}%", percent),
+            None => "-".to_string(),
+        };
+        let target = match camera.sla_target {
+            Some(target) if availability.percent().is_some_and(|p| p < target) => {
+                format!("  target {}% MISSED", target)
+            }
+            Some(target) => format!("  target {}% met", target),
+            None => String::new(),
+        };
+        println!(
+            "  {:width$}  {:>8}  {:.1} of {:.1} h  {} outage(s), longest {:.1} min, mean {:.1} min{}",
+            camera.name,
+            percent,
+            availability.connected.as_secs_f64() / 3600.0,
+            availability.expected.as_secs_f64() / 3600.0,
+            availability.outages,
+            availability.longest_outage.as_secs_f64() / 60.0,
+            availability.mean_outage().as_secs_f64() / 60.0,
+            target,
+            width = width
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,221 @@
+// 定期截图：每隔 snapshot_interval 取一个关键帧解码、编码为 JPEG，原子地替换
+// <snapshot_dir>/<name>.jpg，供监控墙、网页等直接引用。解码在单独的线程中进行，不拖慢录制。
+// 开启 connect_snapshot 时每次连上摄像头后的第一个关键帧另存为
+// <name>_connect_<时间>.jpg 并登记到索引，运维能马上看出摄像头恢复后是否还对着原来的画面
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use chrono::NaiveDateTime;
+
+use ffmpeg_the_third as ffmpeg;
+
+use ffmpeg::codec::context::Context;
+use ffmpeg::format::Pixel;
+use ffmpeg::{frame, Packet};
+
+use crate::avlog;
+use crate::config::CameraConfig;
+use crate::decode::Decoder;
+use crate::index::{self, Record};
+use crate::mux::StreamInfo;
+
+struct Job {
+    // 每次重新连接加一，解码器据此重建
+    connection: u64,
+    info: StreamInfo,
+    packet: Packet,
+    // 连上后的第一张：连上的时间，是否为断线后的重连
+    connect: Option<(NaiveDateTime, bool)>,
+}
+
+// 每个摄像头保留的连接截图数
+const KEEP_CONNECT_SNAPSHOTS: usize = 100;
+
+pub struct Snapshotter {
+    // 线程忙时丢弃关键帧，等下一个
+    sender: Option<SyncSender<Job>>,
+    thread: Option<JoinHandle<()>>,
+    connection: u64,
+    info: Option<StreamInfo>,
+    last: Option<Instant>,
+    // 还没有截到的连接截图
+    pending: Option<(NaiveDateTime, bool)>,
+}
+
+impl Snapshotter {
+    pub fn spawn(id: usize, camera: &CameraConfig) -> Option<Snapshotter> {
+        let dir = camera.snapshot_dir.clone()?;
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let camera = camera.clone();
+        let thread = thread::spawn(move || {
+            avlog::attach(id);
+            run(id, &camera, dir, receiver)
+        });
+        Some(Snapshotter {
+            sender: Some(sender),
+            thread: Some(thread),
+            connection: 0,
+            info: None,
+            last: None,
+            pending: None,
+        })
+    }
+
+    // 每次连上摄像头后调用，视频参数可能已经变化
+    pub fn connected(&mut self, camera: &CameraConfig, info: StreamInfo) {
+        self.connection += 1;
+        self.info = Some(info);
+        self.last = None;
+        if camera.connect_snapshot {
+            self.pending = Some((chrono::Local::now().naive_local(), self.connection > 1));
+        }
+    }
+
+    pub fn offer(&mut self, camera: &CameraConfig, packet: &Packet) {
+        if !packet.is_key()
+            || (self.pending.is_none()
+                && self
+                    .last
+                    .is_some_and(|t| t.elapsed() < camera.snapshot_interval))
+        {
+            return;
+        }
+        let (Some(sender), Some(info)) = (&self.sender, &self.info) else {
+            return;
+        };
+        let job = Job {
+            connection: self.connection,
+            info: info.clone(),
+            packet: packet.clone(),
+            connect: self.pending,
+        };
+        // 线程忙时连接截图留到下一个关键帧
+        if sender.try_send(job).is_ok() {
+            self.last = Some(Instant::now());
+            self.pending = None;
+        }
+    }
+}
+
+impl Drop for Snapshotter {
+    fn drop(&mut self) {
+        // 关闭通道，等截图线程写完手上的一张
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+fn run(id: usize, camera: &CameraConfig, dir: PathBuf, receiver: Receiver<Job>) {
+    let mut decoder: Option<(u64, Decoder)> = None;
+    for job in receiver {
+        if decoder.as_ref().is_none_or(|(c, _)| *c != job.connection) {
+            decoder = match Decoder::new(id, camera, &job.info) {
+                Ok(created) => {
+                    info!("[Stream {}] Snapshots use {} decoding", id, created.backend);
+                    Some((job.connection, created))
+                }
+                Err(e) => {
+                    error!("[Stream {}] {}", id, e);
+                    continue;
+                }
+            };
+        }
+        let Some((_, decoder)) = decoder.as_mut() else {
+            continue;
+        };
+        let result = decoder
+            .picture(&job.packet, camera.snapshot_width, Pixel::YUVJ420P)
+            .and_then(|picture| encode_jpeg(&picture))
+            .and_then(|jpeg| {
+                write(camera, &dir, &jpeg)?;
+                match job.connect {
+                    Some((time, reconnect)) => save_connect(camera, &dir, &jpeg, time, reconnect),
+                    None => Ok(()),
+                }
+            });
+        if let Err(e) = result {
+            error!("[Stream {}] Failed to take snapshot: {}", id, e);
+        }
+    }
+}
+
+pub fn encode_jpeg(picture: &frame::Video) -> Result<Vec<u8>, String> {
+    let codec =
+        ffmpeg::encoder::find(ffmpeg::codec::Id::MJPEG).ok_or("No JPEG encoder available")?;
+    let mut encoder = Context::new_with_codec(codec)
+        .encoder()
+        .video()
+        .map_err(|e| e.to_string())?;
+    encoder.set_width(picture.width());
+    encoder.set_height(picture.height());
+    encoder.set_format(Pixel::YUVJ420P);
+    encoder.set_time_base((1, 1));
+    let mut encoder = encoder
+        .open_as(codec)
+        .map_err(|e| format!("Failed to open JPEG encoder: {}", e))?;
+    let mut packet = Packet::empty();
+    encoder
+        .send_frame(picture)
+        .and_then(|_| encoder.send_eof())
+        .and_then(|_| encoder.receive_packet(&mut packet))
+        .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+    Ok(packet.data().unwrap_or_default().to_vec())
+}
+
+// 先写临时文件再改名，读取方不会读到写了一半的图片
+fn write(camera: &CameraConfig, dir: &Path, jpeg: &[u8]) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("{}.jpg", camera.name));
+    let tmp = dir.join(format!(".{}.jpg.tmp", camera.name));
+    fs::write(&tmp, jpeg)
+        .and_then(|_| fs::rename(&tmp, &path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// 连接截图另存一份，作为 connect/reconnect 事件登记到索引，旧的只保留最近的若干张
+fn save_connect(
+    camera: &CameraConfig,
+    dir: &Path,
+    jpeg: &[u8],
+    time: NaiveDateTime,
+    reconnect: bool,
+) -> Result<(), String> {
+    let prefix = format!("{}_connect_", camera.name);
+    let path = dir.join(format!("{}{}.jpg", prefix, time.format("%Y%m%d_%H%M%S")));
+    fs::write(&path, jpeg).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    let record = Record::Event {
+        time,
+        seconds: 0,
+        source: format!(
+            "{}:{}",
+            if reconnect { "reconnect" } else { "connect" },
+            path.display()
+        ),
+    };
+    index::append(camera, &record)
+        .map_err(|e| format!("Failed to record {} in the index: {}", path.display(), e))?;
+
+    // 文件名里的时间按字典序即时间顺序
+    let mut saved: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(".jpg"))
+        })
+        .collect();
+    saved.sort();
+    let excess = saved.len().saturating_sub(KEEP_CONNECT_SNAPSHOTS);
+    for old in &saved[..excess] {
+        if let Err(e) = fs::remove_file(old) {
+            error!("[Snapshot] Failed to remove {}: {}", old.display(), e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+// 包来源的抽象：分段逻辑只关心视频包的时间戳、是否关键帧和数据。
+// FfmpegSource 包装真实的输入；Synthetic 按给定的帧率、关键帧间隔、断档和断线生成假包，
+// 下游的单元测试不需要网络和 ffmpeg 也能用 recorder::simulate 检查分段结果
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use ffmpeg_the_third as ffmpeg;
+
+use crate::input::{self, Source, Watchdog};
+
+// 合成包的时间基，与 RTSP 视频相同
+const SYNTHETIC_TIME_BASE: (i32, i32) = (1, 90_000);
+
+pub struct SourcePacket {
+    pub key: bool,
+    pub pts: Option<i64>,
+    pub dts: Option<i64>,
+    pub data: Vec<u8>,
+}
+
+pub trait PacketSource {
+    // 视频流的时间基（分子, 分母）
+    fn time_base(&self) -> (i32, i32);
+    // 下一个视频包；Ok(None) 表示流正常结束，Err 表示断线，之后再读是重连后的新连接
+    fn next_packet(&mut self) -> Result<Option<SourcePacket>, String>;
+}
+
+// 真实的输入，只读出最佳视频流的包
+pub struct FfmpegSource {
+    source: Source,
+    stream_index: usize,
+    time_base: (i32, i32),
+}
+
+impl FfmpegSource {
+    pub fn open(url: &str) -> Result<FfmpegSource, String> {
+        let source = input::open(url, &Default::default(), &Watchdog::new(None), || false)?;
+        let stream_index = source.best(ffmpeg::media::Type::Video)?;
+        let time_base = source
+            .context
+            .stream(stream_index)
+            .map(|stream| stream.time_base())
+            .ok_or("No video stream found")?;
+        Ok(FfmpegSource {
+            source,
+            stream_index,
+            time_base: (time_base.numerator(), time_base.denominator()),
+        })
+    }
+}
+
+impl PacketSource for FfmpegSource {
+    fn time_base(&self) -> (i32, i32) {
+        self.time_base
+    }
+
+    fn next_packet(&mut self) -> Result<Option<SourcePacket>, String> {
+        loop {
+            let item = self
+                .source
+                .context
+                .packets()
+                .next()
+                .map(|result| result.map(|(stream, packet)| (stream.index(), packet)));
+            match item {
+                Some(Ok((index, packet))) if index == self.stream_index => {
+                    return Ok(Some(SourcePacket {
+                        key: packet.is_key(),
+                        pts: packet.pts(),
+                        dts: packet.dts(),
+                        data: packet.data().map(<[u8]>::to_vec).unwrap_or_default(),
+                    }))
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.to_string()),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+enum Step {
+    Frames(u32),
+    Gap(Duration),
+    Disconnect,
+}
+
+// 合成的包序列，如 Synthetic::new(25, 50).frames(500).gap(30s).frames(100).disconnect().frames(100)
+pub struct Synthetic {
+    fps: u32,
+    gop: u32,
+    steps: VecDeque<Step>,
+    // 下一帧的时间戳和在 GOP 中的位置
+    pts: i64,
+    position: u32,
+}
+
+impl Synthetic {
+    // 每秒 fps 帧，每 gop 帧一个关键帧
+    pub fn new(fps: u32, gop: u32) -> Synthetic {
+        Synthetic {
+            fps: fps.max(1),
+            gop: gop.max(1),
+            steps: VecDeque::new(),
+            pts: 0,
+            position: 0,
+        }
+    }
+
+    pub fn frames(mut self, count: u32) -> Synthetic {
+        self.steps.push_back(Step::Frames(count));
+        self
+    }
+
+    // 摄像头这么久没有画面，之后的时间戳向后跳，GOP 照常继续
+    pub fn gap(mut self, length: Duration) -> Synthetic {
+        self.steps.push_back(Step::Gap(length));
+        self
+    }
+
+    // 断线；重连后时间戳从零开始，第一帧是关键帧
+    pub fn disconnect(mut self) -> Synthetic {
+        self.steps.push_back(Step::Disconnect);
+        self
+    }
+
+    fn ticks(&self, length: Duration) -> i64 {
+        let (num, den) = SYNTHETIC_TIME_BASE;
+        (length.as_secs_f64() * den as f64 / num as f64) as i64
+    }
+}
+
+impl PacketSource for Synthetic {
+    fn time_base(&self) -> (i32, i32) {
+        SYNTHETIC_TIME_BASE
+    }
+
+    fn next_packet(&mut self) -> Result<Option<SourcePacket>, String> {
+        loop {
+            match self.steps.front_mut() {
+                Some(Step::Frames(0)) => {
+                    self.steps.pop_front();
+                }
+                Some(Step::Frames(count)) => {
+                    *count -= 1;
+                    let key = self.position == 0;
+                    let pts = self.pts;
+                    self.position = (self.position + 1) % self.gop;
+                    self.pts += self.ticks(Duration::from_secs(1)) / self.fps as i64;
+                    // H.264 的 IDR 和普通片的 NAL 头
+                    let nal = if key { 0x65 } else { 0x41 };
+                    return Ok(Some(SourcePacket {
+                        key,
+                        pts: Some(pts),
+                        dts: Some(pts),
+                        data: vec![0, 0, 0, 1, nal],
+                    }));
+                }
+                Some(Step::Gap(length)) => {
+                    let length = *length;
+                    self.steps.pop_front();
+                    self.pts += self.ticks(length);
+                }
+                Some(Step::Disconnect) => {
+                    self.steps.pop_front();
+                    self.pts = 0;
+                    self.position = 0;
+                    return Err("Synthetic disconnect".to_string());
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+// 每路流的运行时状态 <output_dir>/<name>.state，每行 key=value，重启后恢复：
+// 手动暂停、自动停用、连续连不上的起始时间（停用倒计时和 down 告警不因重启清零）、
+// 分段序号和最后一个分段。进程被强制结束时最后一个分段没能登记，启动时补上
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+
+use crate::config::CameraConfig;
+use crate::index::{self, Record};
+use crate::upload;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Clone, Default)]
+pub struct State {
+    pub paused: bool,
+    pub disabled: bool,
+    pub down_since: Option<NaiveDateTime>,
+    pub down_alerted: bool,
+    // 已创建的分段数，写入分段的 .meta
+    pub sequence: u64,
+    pub last_segment: Option<(PathBuf, NaiveDateTime)>,
+}
+
+fn state_path(camera: &CameraConfig) -> PathBuf {
+    camera.output_dir.join(format!("{}.state", camera.name))
+}
+
+// 没有状态文件时为初始状态；看不懂的行忽略
+pub fn load(camera: &CameraConfig) -> io::Result<State> {
+    let text = match fs::read_to_string(state_path(camera)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+        Err(e) => return Err(e),
+    };
+    let mut state = State::default();
+    let mut last_path = None;
+    let mut last_started = None;
+    for line in text.lines() {
+        let (key, value) = match line.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        match key {
+            "paused" => state.paused = value == "true",
+            "disabled" => state.disabled = value == "true",
+            "down_since" => state.down_since = parse_time(value),
+            "down_alerted" => state.down_alerted = value == "true",
+            "sequence" => state.sequence = value.parse().unwrap_or(0),
+            "last_segment" => last_path = Some(PathBuf::from(value)),
+            "last_started" => last_started = parse_time(value),
+            _ => {}
+        }
+    }
+    state.last_segment = last_path.zip(last_started);
+    Ok(state)
+}
+
+// 先写临时文件再改名，断电时不会留下写了一半的状态
+pub fn save(camera: &CameraConfig, state: &State) -> io::Result<()> {
+    let mut text = format!(
+        "paused={}\ndisabled={}\ndown_alerted={}\nsequence={}\n",
+        state.paused, state.disabled, state.down_alerted, state.sequence
+    );
+    if let Some(since) = state.down_since {
+        text.push_str(&format!("down_since={}\n", since.format(TIME_FORMAT)));
+    }
+    if let Some((path, started)) = &state.last_segment {
+        text.push_str(&format!(
+            "last_segment={}\nlast_started={}\n",
+            path.display(),
+            started.format(TIME_FORMAT)
+        ));
+    }
+    let path = state_path(camera);
+    let tmp = path.with_extension("state.tmp");
+    fs::write(&tmp, text)?;
+    fs::rename(&tmp, &path)
+}
+
+fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, TIME_FORMAT).ok()
+}
+
+// 上次运行的最后一个分段不在索引中时（进程被强制结束），按文件的修改时间补登记并加入上传队列，
+// 列表、导出和清理都能看到它
+pub fn recover(id: usize, camera: &CameraConfig, state: &State) {
+    let (path, started) = match &state.last_segment {
+        Some(last) => last,
+        None => return,
+    };
+    match recover_segment(camera, path, *started) {
+        Ok(true) => info!(
+            "[Stream {}] Recovered unfinished segment {}",
+            id,
+            path.display()
+        ),
+        Ok(false) => {}
+        Err(e) => error!(
+            "[Stream {}] Failed to recover {}: {}",
+            id,
+            path.display(),
+            e
+        ),
+    }
+}
+
+// 返回是否补登记了
+fn recover_segment(camera: &CameraConfig, path: &Path, started: NaiveDateTime) -> io::Result<bool> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let recorded = index::load(camera)?
+        .iter()
+        .any(|record| matches!(record, Record::Segment { path: p, .. } if p == path));
+    if recorded {
+        return Ok(false);
+    }
+    let modified: chrono::DateTime<chrono::Local> = metadata.modified()?.into();
+    let end = modified.naive_local().max(started);
+    index::append(
+        camera,
+        &Record::Segment {
+            start: started,
+            end,
+            bytes: metadata.len(),
+            path: path.to_path_buf(),
+            substream: camera.substream_of.is_some(),
+        },
+    )?;
+    upload::enqueue(camera, path)?;
+    Ok(true)
+}
@@ -0,0 +1,114 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+
+use crate::bus::{self, Event};
+use crate::config::CameraConfig;
+use crate::plugin::{self, PacketStats};
+
+// 未配置 stats_seconds 但注册了插件时，按该周期向插件报告
+const PLUGIN_INTERVAL: Duration = Duration::from_secs(60);
+
+// 周期性地把每路流的统计追加到 <output_dir>/<name>_stats.csv，并报告给插件
+pub struct StreamStats {
+    interval: Option<Duration>,
+    // None 表示不写 CSV
+    path: Option<PathBuf>,
+    last_row: Instant,
+    bytes: u64,
+    packets: u64,
+    drops: u64,
+    reconnects: u64,
+    // 最近一次写入数据包的时间，不随统计周期清零
+    pub last_packet: Option<NaiveDateTime>,
+}
+
+impl StreamStats {
+    pub fn new(camera: &CameraConfig) -> StreamStats {
+        StreamStats {
+            interval: camera.stats_interval.or_else(|| {
+                (plugin::has_plugins() || bus::has_subscribers()).then_some(PLUGIN_INTERVAL)
+            }),
+            path: camera
+                .stats_interval
+                .map(|_| camera.output_dir.join(format!("{}_stats.csv", camera.name))),
+            last_row: Instant::now(),
+            bytes: 0,
+            packets: 0,
+            drops: 0,
+            reconnects: 0,
+            last_packet: None,
+        }
+    }
+
+    pub fn record_packet(&mut self, bytes: usize) {
+        self.packets += 1;
+        self.bytes += bytes as u64;
+        self.last_packet = Some(chrono::Local::now().naive_local());
+    }
+
+    pub fn record_drop(&mut self) {
+        self.drops += 1;
+    }
+
+    pub fn record_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+
+    // 到达统计周期时写入一行并清零计数
+    pub fn maybe_write(&mut self, id: usize, camera: &CameraConfig) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let elapsed = self.last_row.elapsed();
+        if elapsed < interval {
+            return;
+        }
+        let stats = PacketStats {
+            interval: elapsed,
+            bitrate_kbps: self.bytes as f64 * 8.0 / elapsed.as_secs_f64() / 1000.0,
+            packets: self.packets,
+            drops: self.drops,
+            reconnects: self.reconnects,
+        };
+        if let Some(path) = &self.path {
+            if let Err(e) = write_row(path, &stats) {
+                error!("[Stream {}] Failed to write stats: {}", id, e);
+            }
+        }
+        bus::publish(Event::Stats {
+            camera: camera.name.clone(),
+            interval: stats.interval,
+            bitrate_kbps: stats.bitrate_kbps,
+            packets: stats.packets,
+            drops: stats.drops,
+            reconnects: stats.reconnects,
+        });
+        self.last_row = Instant::now();
+        self.bytes = 0;
+        self.packets = 0;
+        self.drops = 0;
+        self.reconnects = 0;
+    }
+}
+
+fn write_row(path: &Path, stats: &PacketStats) -> io::Result<()> {
+    let new_file = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if new_file {
+        writeln!(file, "timestamp,bitrate_kbps,packets,drops,reconnects")?;
+    }
+    writeln!(
+        file,
+        "{},{:.1},{},{},{}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        stats.bitrate_kbps,
+        stats.packets,
+        stats.drops,
+        stats.reconnects
+    )
+}
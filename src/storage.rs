@@ -0,0 +1,220 @@
+// 存储健康检查：SD 卡、eMMC 和老化的硬盘坏掉之前往往先变慢或报错，等到写不进去时
+// 已经丢了录像。定期在每个录像目录写入并同步一小段数据测量写延迟，并在能拿到时
+// 读取所在磁盘的 SMART 状态（smartctl）或 eMMC 的寿命估计，出现征兆时告警
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::alert;
+use crate::config::CameraConfig;
+
+const PROBE_SIZE: usize = 1 << 20;
+// 连续这么多次写延迟超标才告警，偶尔一次慢写不算
+const SLOW_CHECKS: u32 = 3;
+// 增长即告警的 SMART 属性：重映射扇区、待映射扇区、无法校正的扇区
+const SMART_ATTRIBUTES: &[&str] = &[
+    "Reallocated_Sector_Ct",
+    "Current_Pending_Sector",
+    "Offline_Uncorrectable",
+];
+
+// 每个录像目录的检查状态
+#[derive(Default)]
+struct Disk {
+    slow: u32,
+    slow_alerted: bool,
+    smart_failed: bool,
+    // SMART 属性上次的原始值
+    attributes: HashMap<String, u64>,
+    worn: bool,
+}
+
+pub fn spawn(
+    cameras: Vec<CameraConfig>,
+    interval: Duration,
+    max_latency: Duration,
+    running: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        // 同一目录只检查一次，告警记在第一个使用它的摄像头上
+        let mut dirs: Vec<(PathBuf, &CameraConfig)> = Vec::new();
+        for camera in &cameras {
+            if !dirs.iter().any(|(dir, _)| *dir == camera.output_dir) {
+                dirs.push((camera.output_dir.clone(), camera));
+            }
+        }
+        let mut disks: HashMap<PathBuf, Disk> = HashMap::new();
+        let mut last_check: Option<Instant> = None;
+        while running.load(Ordering::SeqCst) {
+            if last_check.is_none_or(|t| t.elapsed() >= interval) {
+                for (dir, camera) in &dirs {
+                    let disk = disks.entry(dir.clone()).or_default();
+                    check(dir, camera, max_latency, disk);
+                }
+                last_check = Some(Instant::now());
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    })
+}
+
+fn check(dir: &Path, camera: &CameraConfig, max_latency: Duration, disk: &mut Disk) {
+    match write_latency(dir) {
+        Ok(latency) if latency > max_latency => {
+            disk.slow += 1;
+            if disk.slow >= SLOW_CHECKS && !disk.slow_alerted {
+                disk.slow_alerted = true;
+                alert::raise(
+                    camera,
+                    "storage_health",
+                    &format!(
+                        "Writing {} KB to {} took {} ms ({} checks in a row above {} ms); the disk may be failing",
+                        PROBE_SIZE / 1024,
+                        dir.display(),
+                        latency.as_millis(),
+                        disk.slow,
+                        max_latency.as_millis()
+                    ),
+                );
+            }
+        }
+        Ok(_) => {
+            if disk.slow_alerted {
+                info!("[Storage] Writes to {} are fast again", dir.display());
+            }
+            disk.slow = 0;
+            disk.slow_alerted = false;
+        }
+        Err(e) => error!(
+            "[Storage] Failed to test writing to {}: {}",
+            dir.display(),
+            e
+        ),
+    }
+
+    let device = match block_device(dir) {
+        Some(device) => device,
+        None => return,
+    };
+    if let Some(report) = smart(&device) {
+        check_smart(dir, camera, &device, &report, disk);
+    }
+    if let Some(wear) = emmc_wear(&device) {
+        if !disk.worn {
+            disk.worn = true;
+            alert::raise(
+                camera,
+                "storage_health",
+                &format!("{} ({}) is wearing out: {}", device, dir.display(), wear),
+            );
+        }
+    }
+}
+
+// 写入并同步一小段数据所用的时间
+fn write_latency(dir: &Path) -> io::Result<Duration> {
+    let path = dir.join(".storage_probe");
+    let started = Instant::now();
+    let result = (|| {
+        let mut file = File::create(&path)?;
+        file.write_all(&vec![0u8; PROBE_SIZE])?;
+        file.sync_all()
+    })();
+    let latency = started.elapsed();
+    let _ = fs::remove_file(&path);
+    result.map(|_| latency)
+}
+
+// 目录所在的整块磁盘，如 /dev/sda1 → sda、/dev/mmcblk0p1 → mmcblk0；仅 Linux
+fn block_device(dir: &Path) -> Option<String> {
+    let output = Command::new("df").arg("-P").arg(dir).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let source = text.lines().nth(1)?.split_whitespace().next()?;
+    let name = fs::canonicalize(source)
+        .ok()?
+        .file_name()?
+        .to_str()?
+        .to_string();
+    // 分区在 sysfs 中位于所属磁盘的目录下
+    let sys = fs::canonicalize(Path::new("/sys/class/block").join(&name)).ok()?;
+    if sys.join("partition").exists() {
+        return Some(sys.parent()?.file_name()?.to_str()?.to_string());
+    }
+    Some(name)
+}
+
+// smartctl 的输出；没有安装、没有权限或设备不支持 SMART 时为 None
+fn smart(device: &str) -> Option<String> {
+    let output = Command::new("smartctl")
+        .args(["-H", "-A"])
+        .arg(format!("/dev/{}", device))
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.contains("SMART").then_some(text)
+}
+
+fn check_smart(dir: &Path, camera: &CameraConfig, device: &str, report: &str, disk: &mut Disk) {
+    let failed = report
+        .lines()
+        .any(|line| line.contains("overall-health") && !line.contains("PASSED"));
+    if failed && !disk.smart_failed {
+        alert::raise(
+            camera,
+            "storage_health",
+            &format!(
+                "SMART health check of {} ({}) failed",
+                device,
+                dir.display()
+            ),
+        );
+    }
+    disk.smart_failed = failed;
+    // 属性表每行：ID 名称 标志 当前值 最差值 阈值 类型 更新方式 失效时间 原始值
+    for line in report.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (name, raw) = match fields.as_slice() {
+            [_, name, .., raw] if SMART_ATTRIBUTES.contains(name) => match raw.parse::<u64>() {
+                Ok(raw) => (*name, raw),
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+        let previous = disk.attributes.insert(name.to_string(), raw);
+        // 第一次检查时已经不为零也告警一次
+        if raw > previous.unwrap_or(0) {
+            alert::raise(
+                camera,
+                "storage_health",
+                &format!("{} of {} ({}) rose to {}", name, device, dir.display(), raw),
+            );
+        }
+    }
+}
+
+// eMMC 的寿命估计：pre_eol_info 为 2（保留块用掉 80%）或 3（紧急），
+// 或 life_time 中任一类型的已用寿命达到 90% 时返回说明
+fn emmc_wear(device: &str) -> Option<String> {
+    let sys = Path::new("/sys/block").join(device).join("device");
+    let parse = |value: &str| u8::from_str_radix(value.trim_start_matches("0x"), 16).ok();
+    let pre_eol = fs::read_to_string(sys.join("pre_eol_info"))
+        .ok()
+        .and_then(|text| parse(text.trim()));
+    if let Some(level @ 2..) = pre_eol {
+        let state = if level == 2 { "warning" } else { "urgent" };
+        return Some(format!("pre-EOL state is {}", state));
+    }
+    let life = fs::read_to_string(sys.join("life_time")).ok()?;
+    // 每项 0x01 表示用了 0-10%，0x0A 表示 90-100%，0x0B 表示超出估计寿命
+    let used = life.split_whitespace().filter_map(parse).max()?;
+    (used >= 0x0A).then(|| format!("estimated life time used is {}0% or more", used - 1))
+}
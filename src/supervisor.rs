@@ -0,0 +1,345 @@
+// 进程隔离模式：每个摄像头由一个 `save_rtsp worker` 子进程录制，
+// 某路流让 ffmpeg 崩溃或内存损坏时不会拖垮其他摄像头
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use save_rtsp::alert;
+use save_rtsp::config::{CameraConfig, Config};
+use save_rtsp::control::StreamHandle;
+use save_rtsp::preflight;
+use save_rtsp::trigger;
+
+// 重启退避：从 1 秒开始翻倍，最长 60 秒；子进程稳定运行超过 60 秒后重新计算
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+// 一个子进程；stdin 用于转发 q、pause、resume、enable 命令和触发事件
+struct Worker {
+    id: usize,
+    camera: CameraConfig,
+    stdin: Mutex<Option<ChildStdin>>,
+    state: Mutex<WorkerState>,
+}
+
+#[derive(Default)]
+struct WorkerState {
+    pid: Option<u32>,
+    started: Option<Instant>,
+    restarts: u64,
+    rss_mb: Option<u64>,
+    last_exit: Option<String>,
+}
+
+impl Worker {
+    fn send(&self, line: &str) {
+        if let Some(stdin) = self.stdin.lock().unwrap().as_mut() {
+            if let Err(e) = writeln!(stdin, "{}", line) {
+                eprintln!("[Worker {}] Failed to send '{}': {}", self.id, line, e);
+            }
+        }
+    }
+}
+
+pub fn run(config: Config, config_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let exe = env::current_exe()?;
+    let running = Arc::new(AtomicBool::new(true));
+    if let Some(size_mb) = config.preflight_mb {
+        preflight::run(&config.cameras, size_mb);
+    }
+
+    let workers: Vec<Arc<Worker>> = config
+        .cameras
+        .iter()
+        .enumerate()
+        .map(|(id, camera)| {
+            Arc::new(Worker {
+                id,
+                camera: camera.clone(),
+                stdin: Mutex::new(None),
+                state: Mutex::new(WorkerState::default()),
+            })
+        })
+        .collect();
+
+    // 触发目录只在主进程中监视，避免多个子进程争抢同一个触发文件
+    let trigger_thread = config.trigger_dir.clone().map(|dir| {
+        let streams = config
+            .cameras
+            .iter()
+            .cloned()
+            .map(StreamHandle::new)
+            .collect();
+        let forwarded = workers.clone();
+        // 按需摄像头由录制它的子进程开始录制
+        let workers = workers.clone();
+        let activate: trigger::Activate = Box::new(move |handle| {
+            for worker in workers
+                .iter()
+                .filter(|w| w.camera.name == handle.camera.name)
+            {
+                worker.send(&format!("activate {}", worker.camera.name));
+            }
+        });
+        // 事件交给录制该摄像头及其子码流的子进程记录，主进程里没有订阅者和分段
+        let notify: trigger::Notify = Box::new(move |handle, source| {
+            let name = &handle.camera.name;
+            for worker in forwarded
+                .iter()
+                .filter(|w| w.camera.name == *name || w.camera.substream_of.as_ref() == Some(name))
+            {
+                worker.send(&format!("event {} {}", name, source));
+            }
+            Ok(())
+        });
+        trigger::spawn(dir, streams, activate, notify, running.clone())
+    });
+
+    let handles: Vec<_> = workers
+        .iter()
+        .cloned()
+        .map(|worker| {
+            let exe = exe.clone();
+            let config_path = config_path.to_string();
+            let running = running.clone();
+            let max_rss_mb = config.worker_max_rss_mb;
+            thread::spawn(move || supervise(&worker, &exe, &config_path, max_rss_mb, &running))
+        })
+        .collect();
+
+    if io::stdin().is_terminal() {
+        listen_for_commands(&workers);
+    } else {
+        // 不在终端中运行时不读取标准输入，由信号结束；子进程在管道关闭后自行退出
+        println!("stdin is not a terminal, the command console is off");
+        loop {
+            thread::park();
+        }
+    }
+    running.store(false, Ordering::SeqCst);
+    println!("Stopping all streams...");
+    for worker in &workers {
+        worker.send("q");
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    if let Some(trigger_thread) = trigger_thread {
+        trigger_thread.join().unwrap();
+    }
+
+    println!("All streams stopped. Program exiting.");
+    Ok(())
+}
+
+// 和线程模式相同的命令，pause/resume/enable/stop/rotate/activate 转发给匹配的子进程
+fn listen_for_commands(workers: &[Arc<Worker>]) {
+    let mut input = String::new();
+    loop {
+        input.clear();
+        if io::stdin().read_line(&mut input).is_err() {
+            continue;
+        }
+        let words: Vec<&str> = input.split_whitespace().collect();
+        match words.as_slice() {
+            ["q"] => break,
+            ["status"] => print_status(workers),
+            [command @ ("pause" | "resume" | "enable" | "stop" | "rotate" | "activate"), selector, rest @ ..]
+                if rest.is_empty() || (*command == "activate" && rest.len() == 1) =>
+            {
+                let matched: Vec<_> = workers
+                    .iter()
+                    .filter(|w| w.camera.matches(selector))
+                    .collect();
+                if matched.is_empty() {
+                    eprintln!("No camera matches '{}'", selector);
+                }
+                for worker in matched {
+                    let mut line = format!("{} {}", command, worker.camera.name);
+                    for word in rest {
+                        line.push(' ');
+                        line.push_str(word);
+                    }
+                    worker.send(&line);
+                }
+            }
+            [] => {}
+            _ => eprintln!("Unknown command: {}", input.trim()),
+        }
+    }
+}
+
+// 启动子进程并在其退出后按退避时间重新启动，直到按下 q；
+// 内存占用超过 max_rss_mb 的子进程视为泄漏，杀掉后重启
+fn supervise(
+    worker: &Worker,
+    exe: &Path,
+    config_path: &str,
+    max_rss_mb: Option<u64>,
+    running: &AtomicBool,
+) {
+    let mut backoff = MIN_BACKOFF;
+    while running.load(Ordering::SeqCst) {
+        let mut child = match spawn_worker(worker, exe, config_path) {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("[Worker {}] Failed to start: {}", worker.id, e);
+                sleep_while_running(running, backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        let started = Instant::now();
+        {
+            let mut state = worker.state.lock().unwrap();
+            state.pid = Some(child.id());
+            state.started = Some(started);
+        }
+        *worker.stdin.lock().unwrap() = child.stdin.take();
+        // 刚启动时按下 q 的情况
+        if !running.load(Ordering::SeqCst) {
+            worker.send("q");
+        }
+        let status = monitor(worker, &mut child, max_rss_mb);
+        worker.stdin.lock().unwrap().take();
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+
+        if started.elapsed() >= STABLE_RUN {
+            backoff = MIN_BACKOFF;
+        }
+        let exit = match &status {
+            Ok(status) => status.to_string(),
+            Err(e) => format!("wait failed: {}", e),
+        };
+        let restarts = {
+            let mut state = worker.state.lock().unwrap();
+            state.pid = None;
+            state.started = None;
+            state.restarts += 1;
+            state.last_exit = Some(exit.clone());
+            state.restarts
+        };
+        if let Err(e) = write_metrics(worker, &exit, started.elapsed()) {
+            eprintln!("[Worker {}] Failed to write metrics: {}", worker.id, e);
+        }
+        alert::raise(
+            &worker.camera,
+            "worker",
+            &format!(
+                "Worker exited ({}), restart #{} in {} seconds",
+                exit,
+                restarts,
+                backoff.as_secs()
+            ),
+        );
+        sleep_while_running(running, backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+// 每秒检查一次子进程是否退出及其内存占用
+fn monitor(worker: &Worker, child: &mut Child, max_rss_mb: Option<u64>) -> io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        let rss_mb = rss_mb(child.id());
+        worker.state.lock().unwrap().rss_mb = rss_mb;
+        if let (Some(rss), Some(limit)) = (rss_mb, max_rss_mb) {
+            if rss > limit {
+                eprintln!(
+                    "[Worker {}] Using {} MB, above worker_max_rss_mb={}, killing",
+                    worker.id, rss, limit
+                );
+                child.kill()?;
+                return child.wait();
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+// 常驻内存，仅 Linux 可用
+fn rss_mb(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024)
+}
+
+// 每次子进程退出时追加一行到 <output_dir>/<name>_worker.csv
+fn write_metrics(worker: &Worker, exit: &str, uptime: Duration) -> io::Result<()> {
+    let camera = &worker.camera;
+    let path = camera
+        .output_dir
+        .join(format!("{}_worker.csv", camera.name));
+    let new_file = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    if new_file {
+        writeln!(file, "timestamp,exit,uptime_seconds,rss_mb,restarts")?;
+    }
+    let state = worker.state.lock().unwrap();
+    writeln!(
+        file,
+        "{},\"{}\",{},{},{}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        exit,
+        uptime.as_secs(),
+        state.rss_mb.map_or(String::new(), |mb| mb.to_string()),
+        state.restarts
+    )
+}
+
+fn print_status(workers: &[Arc<Worker>]) {
+    for worker in workers {
+        let state = worker.state.lock().unwrap();
+        let running = match (state.pid, state.started) {
+            (Some(pid), Some(started)) => format!(
+                "pid {}, up {}s, {} MB",
+                pid,
+                started.elapsed().as_secs(),
+                state.rss_mb.map_or("?".to_string(), |mb| mb.to_string())
+            ),
+            _ => "not running".to_string(),
+        };
+        println!(
+            "[Worker {}] {}: {}, {} restart(s), last exit: {}",
+            worker.id,
+            worker.camera.name,
+            running,
+            state.restarts,
+            state.last_exit.as_deref().unwrap_or("-")
+        );
+    }
+}
+
+fn spawn_worker(worker: &Worker, exe: &Path, config_path: &str) -> io::Result<Child> {
+    Command::new(exe)
+        .arg("worker")
+        .arg("--camera")
+        .arg(&worker.camera.name)
+        .arg("--id")
+        .arg(worker.id.to_string())
+        .arg("--config")
+        .arg(config_path)
+        .stdin(Stdio::piped())
+        .spawn()
+}
+
+fn sleep_while_running(running: &AtomicBool, duration: Duration) {
+    let mut slept = Duration::ZERO;
+    while slept < duration && running.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_secs(1));
+        slept += Duration::from_secs(1);
+    }
+}
@@ -0,0 +1,92 @@
+// 视频包时间戳的整理：有的摄像头在时间戳回绕或重启编码器后送出倒退或重复的 dts，
+// 封装器会拒绝这样的包，整个分段随之失败。每次连接一个 Normalizer，按 timestamp_policy
+// 把后面的包整体平移接上（offset）、丢弃（drop）或只记日志原样放行（warn）
+use ffmpeg_the_third::Packet;
+
+use crate::config::TimestampPolicy;
+
+// 每个连接最多记这么多条日志，之后只计数
+const MAX_WARNINGS: u64 = 10;
+
+pub struct Normalizer {
+    policy: TimestampPolicy,
+    // 上一个包（已平移）的 dts 和与再上一个包的间隔
+    last: Option<i64>,
+    step: i64,
+    // 累计平移量
+    offset: i64,
+    corrected: u64,
+}
+
+impl Normalizer {
+    pub fn new(policy: TimestampPolicy) -> Normalizer {
+        Normalizer {
+            policy,
+            last: None,
+            step: 1,
+            offset: 0,
+            corrected: 0,
+        }
+    }
+
+    // 返回 false 表示丢弃这个包
+    pub fn normalize(&mut self, id: usize, packet: &mut Packet) -> bool {
+        // 只看 dts，有 B 帧时 pts 本来就不递增
+        let dts = match packet.dts() {
+            Some(dts) => dts + self.offset,
+            None => {
+                self.shift(packet);
+                return true;
+            }
+        };
+        let last = match self.last {
+            Some(last) if dts <= last => last,
+            Some(last) => {
+                self.step = dts - last;
+                self.last = Some(dts);
+                self.shift(packet);
+                return true;
+            }
+            None => {
+                self.last = Some(dts);
+                self.shift(packet);
+                return true;
+            }
+        };
+        self.corrected += 1;
+        if self.corrected <= MAX_WARNINGS {
+            error!(
+                "[Stream {}] Non-monotonic timestamp {} after {}, {}",
+                id,
+                dts - self.offset,
+                last - self.offset,
+                match self.policy {
+                    TimestampPolicy::Offset => "shifting the following packets",
+                    TimestampPolicy::Drop => "dropping the packet",
+                    TimestampPolicy::Warn => "passing it through",
+                }
+            );
+        }
+        match self.policy {
+            TimestampPolicy::Offset => {
+                // 按之前的帧间隔接在上一个包后面
+                self.offset += last + self.step.max(1) - dts;
+                self.last = Some(last + self.step.max(1));
+                self.shift(packet);
+                true
+            }
+            TimestampPolicy::Drop => false,
+            TimestampPolicy::Warn => {
+                self.shift(packet);
+                true
+            }
+        }
+    }
+
+    fn shift(&self, packet: &mut Packet) {
+        if self.offset != 0 {
+            packet.set_dts(packet.dts().map(|ts| ts + self.offset));
+            packet.set_pts(packet.pts().map(|ts| ts + self.offset));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use crate::bus::{self, Event};
+use crate::clip;
+use crate::config::CameraConfig;
+use crate::control::StreamHandle;
+use crate::index::{self, Record};
+use crate::segment;
+
+// 按需摄像头被触发时调用；进程隔离模式下由主进程转发给子进程
+pub type Activate = Box<dyn Fn(&StreamHandle) + Send>;
+
+// 在摄像头上记录触发事件（来源如 trigger:gate），通常就是 record_event；
+// 进程隔离模式下由主进程转发给录制它的子进程，事件分段和订阅者都在子进程里
+pub type Notify = Box<dyn Fn(&StreamHandle, &str) -> std::io::Result<()> + Send>;
+
+// 监视触发目录：放入 <摄像头名>.trigger（或 @<标签>.trigger）即在对应摄像头上记录一个事件，
+// 方便只能创建文件的老式报警主机接入；按需摄像头同时开始录制
+pub fn spawn(
+    dir: PathBuf,
+    streams: Vec<Arc<StreamHandle>>,
+    activate: Activate,
+    notify: Notify,
+    running: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            error!("[Trigger] Failed to create {}: {}", dir.display(), e);
+        }
+        while running.load(Ordering::SeqCst) {
+            if let Err(e) = scan(&dir, &streams, &activate, &notify) {
+                error!("[Trigger] Failed to scan {}: {}", dir.display(), e);
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    })
+}
+
+fn scan(
+    dir: &Path,
+    streams: &[Arc<StreamHandle>],
+    activate: &Activate,
+    notify: &Notify,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let selector = match path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".trigger"))
+        {
+            Some(selector) => selector.to_string(),
+            None => continue,
+        };
+        // 先删除触发文件，避免同一个触发被处理两次
+        fs::remove_file(&path)?;
+        fire(&selector, streams, activate, notify);
+    }
+    Ok(())
+}
+
+fn fire(selector: &str, streams: &[Arc<StreamHandle>], activate: &Activate, notify: &Notify) {
+    let mut matched = false;
+    // 按需摄像头（包括子码流）开始录制
+    for handle in streams
+        .iter()
+        .filter(|h| h.camera.on_demand && h.camera.matches(selector))
+    {
+        activate(handle);
+    }
+    // 子码流与主码流共用索引，事件只记一次
+    for handle in streams
+        .iter()
+        .filter(|h| h.camera.matches(selector) && h.camera.substream_of.is_none())
+    {
+        matched = true;
+        let camera = &handle.camera;
+        match notify(handle, &format!("trigger:{}", selector)) {
+            Ok(_) => info!(
+                "[Trigger] Event on {} from {}.trigger",
+                camera.name, selector
+            ),
+            Err(e) => error!("[Trigger] Failed to record event on {}: {}", camera.name, e),
+        }
+    }
+    if !matched {
+        error!("[Trigger] No camera matches '{}'", selector);
+    }
+}
+
+// 记录一个事件：登记到索引，通知插件，自适应分段和事件片段随之生效
+pub(crate) fn record_event(
+    camera: &CameraConfig,
+    source: &str,
+    time: NaiveDateTime,
+) -> std::io::Result<()> {
+    let result = index::append(
+        camera,
+        &Record::Event {
+            time,
+            seconds: camera.event_seconds,
+            source: source.to_string(),
+        },
+    );
+    bus::publish(Event::Triggered {
+        camera: camera.name.clone(),
+        source: source.to_string(),
+    });
+    segment::note_event(camera, time, camera.event_seconds);
+    clip::schedule(camera, source, time, camera.event_seconds);
+    result
+}
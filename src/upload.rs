@@ -0,0 +1,201 @@
+// 上传队列：upload_command（如 rclone、scp、aws s3 cp）把完成的分段传到远端。
+// 待上传的分段记在 <output_dir>/<name>.uploads 中，重启后继续；失败按指数退避重试，
+// 远端长时间不可用时告警，分段在上传前被删掉也会告警，不会悄悄丢失录像。
+// upload_window 限制开始上传的时段，upload_max_mbps 限制平均带宽，避免与实时视频抢上行
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveTime;
+
+use crate::alert;
+use crate::config::CameraConfig;
+use crate::control::StreamHandle;
+use crate::hooks;
+
+// 队列文件的追加和重写互斥
+static LOCK: Mutex<()> = Mutex::new(());
+
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+const MAX_RETRY: Duration = Duration::from_secs(3600);
+// 连续失败这么多次后告警
+const ALERT_AFTER: u32 = 5;
+
+fn queue_path(camera: &CameraConfig) -> PathBuf {
+    camera.output_dir.join(format!("{}.uploads", camera.name))
+}
+
+// 分段完成后登记，由上传线程处理
+pub fn enqueue(camera: &CameraConfig, path: &Path) -> io::Result<()> {
+    if camera.upload_command.is_none() {
+        return Ok(());
+    }
+    let _guard = LOCK.lock().unwrap();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(queue_path(camera))?;
+    writeln!(file, "{}", path.display())
+}
+
+fn load(camera: &CameraConfig) -> io::Result<Vec<PathBuf>> {
+    match fs::read_to_string(queue_path(camera)) {
+        Ok(text) => Ok(text
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// 从队列中去掉一项，用临时文件替换保证原子性
+fn remove(camera: &CameraConfig, done: &Path) -> io::Result<()> {
+    let _guard = LOCK.lock().unwrap();
+    let path = queue_path(camera);
+    let remaining: String = load(camera)?
+        .into_iter()
+        .filter(|p| p != done)
+        .map(|p| format!("{}\n", p.display()))
+        .collect();
+    let tmp = path.with_extension("uploads.tmp");
+    fs::write(&tmp, remaining)?;
+    fs::rename(&tmp, &path)
+}
+
+// 每个待上传分段的重试状态，只保存在内存中：重启后立即重试一次
+struct Retry {
+    failures: u32,
+    next: Instant,
+}
+
+// registry 包括运行中新增的摄像头，每一轮重新读取，启动时没有配置上传的也可能之后加入
+pub fn spawn(
+    registry: Arc<Mutex<Vec<Arc<StreamHandle>>>>,
+    running: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut retries: HashMap<PathBuf, Retry> = HashMap::new();
+        // 限速的上传一个接一个进行，按上一个的大小推迟下一个，平均带宽不超过上限
+        let mut paced_until = Instant::now();
+        while running.load(Ordering::SeqCst) {
+            let cameras: Vec<CameraConfig> = registry
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|h| h.camera.clone())
+                .filter(|c| c.upload_command.is_some())
+                .collect();
+            for camera in &cameras {
+                if !in_window(camera, chrono::Local::now().naive_local().time()) {
+                    continue;
+                }
+                let pending = match load(camera) {
+                    Ok(pending) => pending,
+                    Err(e) => {
+                        error!("[Upload] Failed to read queue of {}: {}", camera.name, e);
+                        continue;
+                    }
+                };
+                for path in pending {
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if retries.get(&path).is_some_and(|r| r.next > Instant::now()) {
+                        continue;
+                    }
+                    if camera.upload_max_kbps.is_some() && paced_until > Instant::now() {
+                        break;
+                    }
+                    let started = Instant::now();
+                    let size = fs::metadata(&path).map_or(0, |m| m.len());
+                    if upload(camera, &path, &mut retries) {
+                        if let Some(kbps) = camera.upload_max_kbps {
+                            paced_until = started
+                                + Duration::from_secs_f64(
+                                    size as f64 * 8.0 / (kbps as f64 * 1000.0),
+                                );
+                        }
+                        retries.remove(&path);
+                        if let Err(e) = remove(camera, &path) {
+                            error!("[Upload] Failed to update queue of {}: {}", camera.name, e);
+                        }
+                    }
+                }
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    })
+}
+
+// 是否在允许开始上传的时段内
+fn in_window(camera: &CameraConfig, now: NaiveTime) -> bool {
+    match camera.upload_window {
+        None => true,
+        Some((from, to)) if from < to => now >= from && now < to,
+        Some((from, to)) => now >= from || now < to,
+    }
+}
+
+// 返回 true 表示这一项已经处理完（上传成功或文件已不存在）
+fn upload(camera: &CameraConfig, path: &Path, retries: &mut HashMap<PathBuf, Retry>) -> bool {
+    if !path.exists() {
+        alert::raise(
+            camera,
+            "upload",
+            &format!("{} was deleted before it was uploaded", path.display()),
+        );
+        return true;
+    }
+    let command = camera.upload_command.as_deref().unwrap_or_default();
+    let mut cmd = hooks::shell(command);
+    cmd.arg(path)
+        .env("SEGMENT_PATH", path)
+        .env("SEGMENT_CAMERA", &camera.name);
+    // 给支持限速的工具用，如 scp -l $UPLOAD_LIMIT_KBPS
+    if let Some(kbps) = camera.upload_max_kbps {
+        cmd.env("UPLOAD_LIMIT_KBPS", kbps.to_string());
+    }
+    match hooks::run_with_timeout(cmd, camera.hook_timeout) {
+        Ok(_) => {
+            info!("[Upload] Uploaded {}", path.display());
+            true
+        }
+        Err(e) => {
+            let retry = retries.entry(path.to_path_buf()).or_insert(Retry {
+                failures: 0,
+                next: Instant::now(),
+            });
+            retry.failures += 1;
+            let delay = FIRST_RETRY
+                .saturating_mul(1 << (retry.failures - 1).min(10))
+                .min(MAX_RETRY);
+            retry.next = Instant::now() + delay;
+            error!(
+                "[Upload] Failed to upload {} ({}), retrying in {} seconds",
+                path.display(),
+                e,
+                delay.as_secs()
+            );
+            if retry.failures == ALERT_AFTER {
+                alert::raise(
+                    camera,
+                    "upload",
+                    &format!(
+                        "Upload of {} failed {} times: {}",
+                        path.display(),
+                        retry.failures,
+                        e
+                    ),
+                );
+            }
+            false
+        }
+    }
+}
@@ -0,0 +1,27 @@
+// webhook：把总线上的事件逐个 POST 到 webhook_url，正文为一行 JSON（见 bus::Event::to_json）
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::bus;
+use crate::post;
+
+pub fn spawn(
+    url: &str,
+    events: Vec<String>,
+    running: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, String> {
+    let address = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("webhook_url must start with http://: '{}'", url))?;
+    let (host, path) = match address.split_once('/') {
+        Some((host, path)) => (host.to_string(), format!("/{}", path)),
+        None => (address.to_string(), "/".to_string()),
+    };
+    Ok(bus::spawn("Webhook", running, move |event| {
+        if !events.iter().any(|name| name == event.name()) {
+            return Ok(());
+        }
+        post::json(&host, &path, &event.to_json())
+    }))
+}